@@ -0,0 +1,454 @@
+use crate::core::*;
+use crate::netlist::{Net, Netlist};
+use crate::basic_comp::*;
+use crate::arithmetic_comp::HalfAdder;
+use crate::flip_flop::select;
+
+// 繰り上がりの計算を速くした加算器, 入出力はNByteAdderと同じで a(8N), b(8N) -> a + b(8N)
+// どれも bitごとに半加算器で p = a ^ b, g = a & b を作り, 繰り上がり c を求めて, p ^ c を和にする
+// 繰り上がりの部分は (p0, g0, p1, g1, ...) -> (p0, c0, p1, c1, ...) で, c0は0
+
+// pとgから和を作る部分をつける, a(B), b(B) -> a + b(B)
+fn adder_from_carries<const B: usize>(
+    carries: Box<dyn Component<{2 * B}, {2 * B}>>,
+) -> MergeLayers<{2 * B}, {1 * B}, B> where
+    [(); 2 * B]: Sized,
+    [(); 1 * B]: Sized,
+{
+    // a0, b0, a1, b1, ...
+    let mut table = [0; 2 * B];
+    for (i, v) in table.iter_mut().enumerate() {
+        *v = i / 2 + if i % 2 == 0 { 0 } else { B };
+    }
+    let layer1 = Wiring::<{2 * B}, {2 * B}>::create(table);
+    let layer2 = ConcatBlocks::<2, 2, B>::create_from_fn(HalfAdder::new);
+    let layer3 = ConcatBlocks::<2, 1, B>::create_from_fn(XOR::<2>::new);
+    let out_wrapper = Wiring::<{1 * B}, B>::wrapper();
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(carries)
+        .connect_to(Box::new(layer3))
+        .connect_to(Box::new(out_wrapper))
+}
+
+// NByteAdderと同じ a(8N), b(8N) の入力につなぐ
+fn byte_adder<const N: usize>(
+    carries: Box<dyn Component<{2 * (8 * N)}, {2 * (8 * N)}>>,
+) -> MergeLayers<{16 * N}, {2 * (8 * N)}, {8 * N}> where
+    [(); 16 * N]: Sized,
+    [(); 2 * (8 * N)]: Sized,
+    [(); 1 * (8 * N)]: Sized,
+{
+    let in_wrapper = Wiring::<{16 * N}, {2 * (8 * N)}>::wrapper();
+    MergeLayers::create(Box::new(in_wrapper), Box::new(adder_from_carries::<{8 * N}>(carries)))
+}
+
+// 0, 1 を後ろにつける
+fn with_constants<const N: usize>() -> ConcatDifferentShapeBlocks<N, 0, N, 2> {
+    ConcatDifferentShapeBlocks::create(
+        Box::new(Wiring::<N, N>::buffer()),
+        Box::new(ConcatDifferentShapeBlocks::<0, 0, 1, 1>::create(
+            Box::new(Constant::<0, 1, false>::new()),
+            Box::new(Constant::<0, 1, true>::new()),
+        )),
+    )
+}
+
+// 4bitずつの組で繰り上がりを求め, 組の間は順に伝える
+// 組は c, (p, g) * 4 -> (p, c) * 4, 次の組への c
+fn group_carries<const N: usize, T: Component<9, 9> + 'static>(
+    group: fn() -> T,
+) -> MergeLayers<{2 * (8 * N)}, {8 * (2 * N) + 1}, {2 * (8 * N)}> where
+    [(); 2 * (8 * N)]: Sized,
+    [(); 0 + 2 * (8 * N)]: Sized,
+    [(); 1 + 2 * (8 * N)]: Sized,
+    [(); 1 + 8 * (2 * N)]: Sized,
+    [(); 8 * (2 * N) + 1]: Sized,
+{
+    let in_wrapper = Wiring::<{2 * (8 * N)}, {0 + 2 * (8 * N)}>::wrapper();
+    let carry_in = ConcatDifferentShapeBlocks::<0, {2 * (8 * N)}, 1, {2 * (8 * N)}>::create(
+        Box::new(Constant::<0, 1, false>::new()),
+        Box::new(Wiring::<{2 * (8 * N)}, {2 * (8 * N)}>::buffer()),
+    );
+    let groups_wrapper = Wiring::<{1 + 2 * (8 * N)}, {1 + 8 * (2 * N)}>::wrapper();
+    let groups = RecurrentBlock::<1, 8, 8, {2 * N}>::create_from_fn(group);
+    // 最後の繰り上がりは使わない
+    let cut = Wiring::<{8 * (2 * N) + 1}, {2 * (8 * N)}>::cut();
+    MergeLayers::create(Box::new(in_wrapper), Box::new(carry_in))
+        .connect_to(Box::new(groups_wrapper))
+        .connect_to(Box::new(groups))
+        .connect_to(Box::new(cut))
+}
+
+// 組の出力の並べ替え, c1, c2, c3, c4, c0, p0, p1, p2, p3 -> (p, c) * 4, c4
+fn group_output() -> Wiring<9, 9> {
+    Wiring::create([5, 4, 6, 0, 7, 1, 8, 2, 3])
+}
+
+// 5項までの積和, 使わないところは1や0につなぐ
+fn sum_of_products() -> MergeLayers<25, 5, 1> {
+    MergeLayers::create(
+        Box::new(ConcatBlocks::<5, 1, 5>::create_from_fn(And::<5>::new)),
+        Box::new(Or::<5>::new()),
+    )
+}
+
+// 4bitの先読み, c(k) = g(k-1) | p(k-1) & g(k-2) | ... | p(k-1) & ... & p0 & c0 を2段で求める
+fn lookahead_group() -> MergeLayers<9, 9, 9> {
+    let (carry_in, p, g, zero, one) = (0, |k| 1 + 2 * k, |k| 2 + 2 * k, 9, 10);
+    let mut table = [0; 4 * 25 + 5];
+    for k in 1..=4 {
+        for term in 0..5 {
+            let inputs = &mut table[((k - 1) * 25 + term * 5)..((k - 1) * 25 + term * 5 + 5)];
+            if term > k {
+                inputs.fill(zero);
+                continue;
+            }
+            // 0項目はc0から, j項目はg(j - 1)から, それより上のpをすべてかける
+            inputs.fill(one);
+            inputs[0] = if term == 0 { carry_in } else { g(term - 1) };
+            for (i, bit) in (term..k).enumerate() {
+                inputs[1 + i] = p(bit);
+            }
+        }
+    }
+    table[100..].copy_from_slice(&[carry_in, p(0), p(1), p(2), p(3)]);
+    let layer2 = Wiring::<11, 105>::create(table);
+    let layer3 = ConcatDifferentShapeBlocks::<100, 5, 4, 5>::create(
+        Box::new(ConcatBlocks::<25, 1, 4>::create_from_fn(sum_of_products)),
+        Box::new(Wiring::<5, 5>::buffer()),
+    );
+    MergeLayers::create(Box::new(with_constants::<9>()), Box::new(layer2))
+        .connect_to(Box::new(layer3))
+        .connect_to(Box::new(group_output()))
+}
+
+// c, p, g -> g | p & c を2本
+fn carry_cell() -> MergeLayers<3, 1, 2> {
+    let layer1 = Wiring::<3, 3>::create([2, 1, 0]);
+    let layer2 = ConcatDifferentShapeBlocks::<1, 2, 1, 1>::create(
+        Box::new(Buffer::new()),
+        Box::new(And::<2>::new()),
+    );
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(Or::<2>::new()))
+        .connect_to(Box::new(Branch::<2>::new()))
+}
+
+fn ripple_carry4() -> RecurrentBlock<1, 2, 1, 4> {
+    RecurrentBlock::create_from_fn(carry_cell)
+}
+
+// 4bitの組で, 前からの繰り上がりが0の場合と1の場合を両方求めておき, 届いた繰り上がりで選ぶ
+fn select_group() -> MergeLayers<9, 9, 9> {
+    let (carry_in, zero, one) = (0, 9, 10);
+    let mut table = [0; 23];
+    table[0] = zero;
+    table[9] = one;
+    for i in 0..8 {
+        table[1 + i] = 1 + i;
+        table[10 + i] = 1 + i;
+    }
+    table[18..].copy_from_slice(&[carry_in, 1, 3, 5, 7]);
+    let layer2 = Wiring::<11, 23>::create(table);
+    // 0のときのc1..c4, c4, 1のときのc1..c4, c4, c0, p0..p3
+    let layer3 = ConcatDifferentShapeBlocks::<18, 5, 10, 5>::create(
+        Box::new(ConcatBlocks::<9, 5, 2>::create_from_fn(ripple_carry4)),
+        Box::new(Wiring::<5, 5>::buffer()),
+    );
+    let mut table = [0; 17];
+    for k in 0..4 {
+        table[3 * k..3 * k + 3].copy_from_slice(&[10, 5 + k, k]);
+    }
+    table[12..].copy_from_slice(&[10, 11, 12, 13, 14]);
+    let layer4 = Wiring::<15, 17>::create(table);
+    let layer5 = ConcatDifferentShapeBlocks::<12, 5, 4, 5>::create(
+        Box::new(ConcatBlocks::<3, 1, 4>::create_from_fn(select)),
+        Box::new(Wiring::<5, 5>::buffer()),
+    );
+    MergeLayers::create(Box::new(with_constants::<9>()), Box::new(layer2))
+        .connect_to(Box::new(layer3))
+        .connect_to(Box::new(layer4))
+        .connect_to(Box::new(layer5))
+        .connect_to(Box::new(group_output()))
+}
+
+// p, G(i), P(i), G(j), P(j) -> p, G(i) | P(i) & G(j), P(i) & P(j)
+fn prefix_cell() -> MergeLayers<5, 4, 3> {
+    let layer1 = Wiring::<5, 6>::create([0, 1, 2, 3, 2, 4]);
+    let layer2 = ConcatDifferentShapeBlocks::<2, 4, 2, 2>::create(
+        Box::new(Wiring::<2, 2>::buffer()),
+        Box::new(ConcatBlocks::<2, 1, 2>::create_from_fn(And::<2>::new)),
+    );
+    let layer3 = ConcatDifferentShapeBlocks::<1, 3, 1, 2>::create(
+        Box::new(Buffer::new()),
+        Box::new(ConcatDifferentShapeBlocks::<2, 1, 1, 1>::create(
+            Box::new(Or::<2>::new()),
+            Box::new(Buffer::new()),
+        )),
+    );
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(layer3))
+}
+
+// bitごとの (p, G, P) で, distanceだけ下の (G, P) とまとめる, 下がないところは (0, 1) とまとめて変えない
+fn prefix_level<const B: usize>(distance: usize) -> MergeLayers<{3 * B}, {5 * B}, {3 * B}> where
+    [(); 3 * B]: Sized,
+    [(); 3 * B + 0]: Sized,
+    [(); 3 * B + 2]: Sized,
+    [(); 5 * B]: Sized,
+{
+    let (zero, one) = (3 * B, 3 * B + 1);
+    let mut table = [0; 5 * B];
+    for (bit, inputs) in table.chunks_mut(5).enumerate() {
+        let lower = match bit.checked_sub(distance) {
+            Some(j) => [3 * j + 1, 3 * j + 2],
+            None => [zero, one],
+        };
+        inputs.copy_from_slice(&[3 * bit, 3 * bit + 1, 3 * bit + 2, lower[0], lower[1]]);
+    }
+    let in_wrapper = Wiring::<{3 * B}, {3 * B + 0}>::wrapper();
+    let layer2 = Wiring::<{3 * B + 2}, {5 * B}>::create(table);
+    let layer3 = ConcatBlocks::<5, 3, {B}>::create_from_fn(prefix_cell);
+    MergeLayers::create(Box::new(in_wrapper), Box::new(with_constants::<{3 * B}>()))
+        .connect_to(Box::new(layer2))
+        .connect_to(Box::new(layer3))
+}
+
+// 距離を1, 2, 4, ... と倍にしながらまとめ, log2(B)段で全bitの繰り上がりを求める
+fn kogge_stone_carries<const B: usize>() -> MergeLayers<{2 * B}, {3 * B + 1}, {2 * B}> where
+    [(); 2 * B]: Sized,
+    [(); 3 * B]: Sized,
+    [(); 3 * B + 0]: Sized,
+    [(); 3 * B + 1]: Sized,
+    [(); 3 * B + 2]: Sized,
+    [(); 5 * B]: Sized,
+{
+    // p, G = g, P = p
+    let mut table = [0; 3 * B];
+    for (bit, v) in table.chunks_mut(3).enumerate() {
+        v.copy_from_slice(&[2 * bit, 2 * bit + 1, 2 * bit]);
+    }
+    let expand = Wiring::<{2 * B}, {3 * B}>::create(table);
+    let mut prefix: Box<dyn Component<{3 * B}, {3 * B}>> =
+        Box::new(Wiring::<{3 * B}, {3 * B}>::buffer());
+    let mut distance = 1;
+    while distance < B {
+        prefix = Box::new(MergeLayers::create(prefix, Box::new(prefix_level::<B>(distance))));
+        distance *= 2;
+    }
+    // c0は0, c(i)はi - 1までまとめたG
+    let in_wrapper = Wiring::<{3 * B}, {3 * B + 0}>::wrapper();
+    let carry_in = ConcatDifferentShapeBlocks::<{3 * B}, 0, {3 * B}, 1>::create(
+        Box::new(Wiring::<{3 * B}, {3 * B}>::buffer()),
+        Box::new(Constant::<0, 1, false>::new()),
+    );
+    let mut table = [0; 2 * B];
+    for (bit, v) in table.chunks_mut(2).enumerate() {
+        v[0] = 3 * bit;
+        v[1] = if bit == 0 { 3 * B } else { 3 * (bit - 1) + 1 };
+    }
+    let carries = Wiring::<{3 * B + 1}, {2 * B}>::create(table);
+    MergeLayers::create(Box::new(expand), prefix)
+        .connect_to(Box::new(in_wrapper))
+        .connect_to(Box::new(carry_in))
+        .connect_to(Box::new(carries))
+}
+
+// Bbitの並列プレフィックス加算器, a(B), b(B) -> a + b(B), 乗算器などの中で使う
+pub(crate) fn kogge_stone_adder<const B: usize>() -> MergeLayers<{2 * B}, {1 * B}, B> where
+    [(); 2 * B]: Sized,
+    [(); 1 * B]: Sized,
+    [(); 3 * B]: Sized,
+    [(); 3 * B + 0]: Sized,
+    [(); 3 * B + 1]: Sized,
+    [(); 3 * B + 2]: Sized,
+    [(); 5 * B]: Sized,
+{
+    adder_from_carries::<B>(Box::new(kogge_stone_carries::<B>()))
+}
+
+// 4bitごとに繰り上がりを2段の積和で先読みし, 組の間は順に伝える
+pub struct CarryLookaheadAdder<const N: usize> where
+    [(); 16 * N]: Sized,
+    [(); 2 * (8 * N)]: Sized,
+{
+    adder: MergeLayers<{16 * N}, {2 * (8 * N)}, {8 * N}>,
+}
+
+impl<const N: usize> Component<{16 * N}, {8 * N}> for CarryLookaheadAdder<N> where
+    [(); 16 * N]: Sized,
+    [(); 2 * (8 * N)]: Sized,
+    [(); 8 * N]: Sized,
+{
+    fn eval(&self, input: [bool; 16 * N]) -> [bool; 8 * N] {
+        self.adder.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.adder.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.adder.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.adder.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.adder.netlist(netlist, input)
+    }
+}
+
+impl<const N: usize> CarryLookaheadAdder<N> where
+    [(); 16 * N]: Sized,
+    [(); 1 * (8 * N)]: Sized,
+    [(); 2 * (8 * N)]: Sized,
+    [(); 0 + 2 * (8 * N)]: Sized,
+    [(); 1 + 2 * (8 * N)]: Sized,
+    [(); 1 + 8 * (2 * N)]: Sized,
+    [(); 8 * (2 * N) + 1]: Sized,
+{
+    pub fn new() -> Self {
+        Self { adder: byte_adder::<N>(Box::new(group_carries::<N, _>(lookahead_group))) }
+    }
+}
+
+// 並列プレフィックスで全bitの繰り上がりを同時に求める, 段数はlog2(8N)に比例する
+pub struct KoggeStoneAdder<const N: usize> where
+    [(); 16 * N]: Sized,
+    [(); 2 * (8 * N)]: Sized,
+{
+    adder: MergeLayers<{16 * N}, {2 * (8 * N)}, {8 * N}>,
+}
+
+impl<const N: usize> Component<{16 * N}, {8 * N}> for KoggeStoneAdder<N> where
+    [(); 16 * N]: Sized,
+    [(); 2 * (8 * N)]: Sized,
+    [(); 8 * N]: Sized,
+{
+    fn eval(&self, input: [bool; 16 * N]) -> [bool; 8 * N] {
+        self.adder.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.adder.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.adder.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.adder.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.adder.netlist(netlist, input)
+    }
+}
+
+impl<const N: usize> KoggeStoneAdder<N> where
+    [(); 16 * N]: Sized,
+    [(); 1 * (8 * N)]: Sized,
+    [(); 2 * (8 * N)]: Sized,
+    [(); 3 * (8 * N)]: Sized,
+    [(); 3 * (8 * N) + 0]: Sized,
+    [(); 3 * (8 * N) + 1]: Sized,
+    [(); 3 * (8 * N) + 2]: Sized,
+    [(); 5 * (8 * N)]: Sized,
+{
+    pub fn new() -> Self {
+        Self { adder: byte_adder::<N>(Box::new(kogge_stone_carries::<{8 * N}>())) }
+    }
+}
+
+// 4bitごとに繰り上がりが0と1の両方の場合を先に計算しておき, 届いた繰り上がりで選ぶ
+pub struct CarrySelectAdder<const N: usize> where
+    [(); 16 * N]: Sized,
+    [(); 2 * (8 * N)]: Sized,
+{
+    adder: MergeLayers<{16 * N}, {2 * (8 * N)}, {8 * N}>,
+}
+
+impl<const N: usize> Component<{16 * N}, {8 * N}> for CarrySelectAdder<N> where
+    [(); 16 * N]: Sized,
+    [(); 2 * (8 * N)]: Sized,
+    [(); 8 * N]: Sized,
+{
+    fn eval(&self, input: [bool; 16 * N]) -> [bool; 8 * N] {
+        self.adder.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.adder.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.adder.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.adder.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.adder.netlist(netlist, input)
+    }
+}
+
+impl<const N: usize> CarrySelectAdder<N> where
+    [(); 16 * N]: Sized,
+    [(); 1 * (8 * N)]: Sized,
+    [(); 2 * (8 * N)]: Sized,
+    [(); 0 + 2 * (8 * N)]: Sized,
+    [(); 1 + 2 * (8 * N)]: Sized,
+    [(); 1 + 8 * (2 * N)]: Sized,
+    [(); 8 * (2 * N) + 1]: Sized,
+{
+    pub fn new() -> Self {
+        Self { adder: byte_adder::<N>(Box::new(group_carries::<N, _>(select_group))) }
+    }
+}
+
+#[test]
+fn fast_adder_test() {
+    use crate::arithmetic_comp::NByteAdder;
+    use crate::num_bit_converter::*;
+
+    // 8bitはすべての組み合わせ, 16bitは飛び飛びに順番どおりの加算器と比べる
+    let ripple = NByteAdder::<1>::new();
+    let lookahead = CarryLookaheadAdder::<1>::new();
+    let kogge_stone = KoggeStoneAdder::<1>::new();
+    let carry_select = CarrySelectAdder::<1>::new();
+    for i in 0..(1 << 16) {
+        let input = num_to_bit::<16>(i);
+        let expected = ripple.eval(input);
+        assert_eq!(bit_to_num(expected), ((i & 0xff) + (i >> 8)) & 0xff);
+        assert_eq!(lookahead.eval(input), expected, "{:04x}", i);
+        assert_eq!(kogge_stone.eval(input), expected, "{:04x}", i);
+        assert_eq!(carry_select.eval(input), expected, "{:04x}", i);
+    }
+
+    let ripple = NByteAdder::<2>::new();
+    let lookahead = CarryLookaheadAdder::<2>::new();
+    let kogge_stone = KoggeStoneAdder::<2>::new();
+    let carry_select = CarrySelectAdder::<2>::new();
+    for i in 0..5000_usize {
+        let (a, b) = ((i * 40503) & 0xffff, (i * 7919 + 0xff00) & 0xffff);
+        let input = num_to_bit::<32>(a + (b << 16));
+        let expected = ripple.eval(input);
+        assert_eq!(lookahead.eval(input), expected);
+        assert_eq!(kogge_stone.eval(input), expected);
+        assert_eq!(carry_select.eval(input), expected);
+    }
+    // 繰り上がりが全部の桁を伝わる
+    let input = num_to_bit::<32>(0xffff + (1 << 16));
+    assert_eq!(kogge_stone.eval(input), [false; 16]);
+}
+
+#[test]
+fn fast_adder_metrics_test() {
+    use crate::arithmetic_comp::NByteAdder;
+    use crate::optimize::simplify;
+
+    // 定数につないだところを畳んでから比べる
+    let ripple = simplify(&NByteAdder::<4>::new()).unwrap().0.netlist().metrics();
+    let lookahead = simplify(&CarryLookaheadAdder::<4>::new()).unwrap().0.netlist().metrics();
+    let kogge_stone = simplify(&KoggeStoneAdder::<4>::new()).unwrap().0.netlist().metrics();
+    let carry_select = simplify(&CarrySelectAdder::<4>::new()).unwrap().0.netlist().metrics();
+    assert!(kogge_stone.depth < lookahead.depth, "{:?} {:?}", kogge_stone, lookahead);
+    assert!(lookahead.depth < ripple.depth, "{:?} {:?}", lookahead, ripple);
+    assert!(carry_select.depth < ripple.depth, "{:?} {:?}", carry_select, ripple);
+    // 速いものはゲートが多い
+    assert!(kogge_stone.gates.total() > ripple.gates.total(), "{:?} {:?}", kogge_stone, ripple);
+}
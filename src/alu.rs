@@ -0,0 +1,451 @@
+use crate::core::*;
+use crate::netlist::{Net, Netlist};
+use crate::basic_comp::*;
+use crate::arithmetic_comp::{pass_not, FullAdder};
+use crate::decoder::BitDecoder;
+use crate::divider::is_zero;
+use crate::multiplier::with_zero;
+use crate::mux::and_or;
+use crate::num_bit_converter::num_to_bit;
+
+// Aluの命令, 値がそのままopcodeになる, 15は何もせず結果は0
+// キャリーは足し算では繰り上がり, 引き算では借り, シフトでは押し出されたbit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AluOp {
+    Add,
+    // キャリーも足す
+    Adc,
+    Sub,
+    // キャリーを借りとして引く
+    Sbc,
+    And,
+    Or,
+    Xor,
+    // aだけ反転する
+    Not,
+    // 空いたところには0を入れる
+    Shl,
+    Shr,
+    Rol,
+    Ror,
+    Inc,
+    Dec,
+    // a - bのフラグを出し, 結果はaのまま
+    Cmp,
+}
+
+impl AluOp {
+    pub const ALL: [AluOp; 15] = [
+        AluOp::Add, AluOp::Adc, AluOp::Sub, AluOp::Sbc, AluOp::And, AluOp::Or, AluOp::Xor, AluOp::Not,
+        AluOp::Shl, AluOp::Shr, AluOp::Rol, AluOp::Ror, AluOp::Inc, AluOp::Dec, AluOp::Cmp,
+    ];
+    pub fn bits(self) -> [bool; 4] {
+        num_to_bit(self as usize)
+    }
+}
+
+// a, b -> a, !a, b, !b, a & b, a | b, a ^ b
+fn logic_cell() -> MergeLayers<2, 10, 7> {
+    let layer1 = Wiring::<2, 10>::create([0, 0, 1, 1, 0, 1, 0, 1, 0, 1]);
+    let layer2 = ConcatDifferentShapeBlocks::<4, 6, 4, 3>::create(
+        Box::new(ConcatBlocks::<2, 2, 2>::create_from_fn(pass_not)),
+        Box::new(ConcatBlocks::<2, 1, 3>::create([
+            Box::new(And::<2>::new()),
+            Box::new(Or::<2>::new()),
+            Box::new(XOR::<2>::new()),
+        ])),
+    );
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+}
+
+// 足し算の符号付きの溢れ, a, y, 和 の最上位bitと繰り上がりから
+// 最上位bitへの繰り上がり a ^ y ^ 和 と最後の繰り上がりが違えば溢れている
+fn carry_overflow() -> MergeLayers<4, 2, 1> {
+    let layer1 = ConcatBlocks::<2, 1, 2>::create_from_fn(XOR::<2>::new);
+    MergeLayers::create(Box::new(layer1), Box::new(XOR::<2>::new()))
+}
+
+// 全部の演算で使う線をまとめる, opcode(4), キャリー, a(N), b(N) ->
+// デコードしたopcode(16), キャリー, !キャリー, bitごとに (a, !a, b, !b, a & b, a | b, a ^ b), 0
+fn signals<const N: usize>() -> MergeLayers<{5 + 2 * N}, {16 + (2 + (7 * N + 1))}, {19 + 7 * N}> where
+    [(); 5 + 2 * N]: Sized,
+    [(); 5 + 2 * N + 0]: Sized,
+    [(); 5 + 2 * N + 1]: Sized,
+    [(); 2 * N]: Sized,
+    [(); 7 * N]: Sized,
+    [(); 2 * N + 1]: Sized,
+    [(); 7 * N + 1]: Sized,
+    [(); 2 + (2 * N + 1)]: Sized,
+    [(); 2 + (7 * N + 1)]: Sized,
+    [(); 4 + (2 + (2 * N + 1))]: Sized,
+    [(); 16 + (2 + (7 * N + 1))]: Sized,
+    [(); 19 + 7 * N]: Sized,
+{
+    let zero = 5 + 2 * N;
+    let mut table = [zero; 4 + (2 + (2 * N + 1))];
+    table[..6].copy_from_slice(&[0, 1, 2, 3, 4, 4]);
+    for i in 0..N {
+        table[6 + 2 * i] = 5 + i;
+        table[7 + 2 * i] = 5 + N + i;
+    }
+    let layer1 = Wiring::<{5 + 2 * N + 1}, {4 + (2 + (2 * N + 1))}>::create(table);
+    let layer2 = ConcatDifferentShapeBlocks::<4, {2 + (2 * N + 1)}, 16, {2 + (7 * N + 1)}>::create(
+        Box::new(BitDecoder::<4>::new()),
+        Box::new(ConcatDifferentShapeBlocks::<2, {2 * N + 1}, 2, {7 * N + 1}>::create(
+            Box::new(pass_not()),
+            Box::new(ConcatDifferentShapeBlocks::<{2 * N}, 1, {7 * N}, 1>::create(
+                Box::new(ConcatBlocks::<2, 7, N>::create_from_fn(logic_cell)),
+                Box::new(Wiring::<1, 1>::buffer()),
+            )),
+        )),
+    );
+    let out_wrapper = Wiring::<{16 + (2 + (7 * N + 1))}, {19 + 7 * N}>::wrapper();
+    MergeLayers::create(Box::new(with_zero::<{5 + 2 * N}>()), Box::new(layer1))
+        .connect_to(Box::new(layer2))
+        .connect_to(Box::new(out_wrapper))
+}
+
+// 足し算の入り口, 選ぶ組(6) * (繰り上がり, y(N)), a(N) -> 和(N), 繰り上がり, !繰り上がり, 溢れ
+// a + y + 繰り上がり の一つの足し算で, yと繰り上がりの選び方を変えて全部の算術演算をする
+fn operand_adder<const N: usize>() -> MergeLayers<{12 * (N + 1) + N}, {(N + 1) + 2}, {N + 3}> where
+    [(); 12 * (N + 1)]: Sized,
+    [(); 12 * (N + 1) + N]: Sized,
+    [(); 1 * (N + 1)]: Sized,
+    [(); 1 * (N + 1) + N]: Sized,
+    [(); 2 * N]: Sized,
+    [(); 1 + 2 * N]: Sized,
+    [(); (1 + 2 * N) + 2]: Sized,
+    [(); 1 * N + 1]: Sized,
+    [(); (1 * N + 1) + 2]: Sized,
+    [(); N + 1]: Sized,
+    [(); (N + 1) + 5]: Sized,
+    [(); (N + 1) + 2]: Sized,
+    [(); N + 3]: Sized,
+{
+    let layer1 = ConcatDifferentShapeBlocks::<{12 * (N + 1)}, N, {1 * (N + 1)}, N>::create(
+        Box::new(ConcatBlocks::<12, 1, {N + 1}>::create_from_fn(and_or::<6>)),
+        Box::new(Wiring::<N, N>::buffer()),
+    );
+    // 繰り上がり, (a0, y0), (a1, y1), ..., 最上位のa, y
+    let (carry, y, a) = (0, |i| 1 + i, |i| N + 1 + i);
+    let mut table = [carry; (1 + 2 * N) + 2];
+    for i in 0..N {
+        table[1 + 2 * i] = a(i);
+        table[2 + 2 * i] = y(i);
+    }
+    table[1 + 2 * N] = a(N - 1);
+    table[2 + 2 * N] = y(N - 1);
+    let layer2 = Wiring::<{1 * (N + 1) + N}, {(1 + 2 * N) + 2}>::create(table);
+    let layer3 = ConcatDifferentShapeBlocks::<{1 + 2 * N}, 2, {1 * N + 1}, 2>::create(
+        Box::new(RecurrentBlock::<1, 2, 1, N>::create_from_fn(FullAdder::new)),
+        Box::new(Wiring::<2, 2>::buffer()),
+    );
+    // 和, 繰り上がり, 繰り上がり, (a, y, 和 の最上位bit, 繰り上がり)
+    let (carry, a, y) = (N, N + 1, N + 2);
+    let mut table = [carry; (N + 1) + 5];
+    for (i, v) in table.iter_mut().enumerate().take(N) {
+        *v = i;
+    }
+    table[N + 2..].copy_from_slice(&[a, y, N - 1, carry]);
+    let layer4 = Wiring::<{(1 * N + 1) + 2}, {(N + 1) + 5}>::create(table);
+    let layer5 = ConcatDifferentShapeBlocks::<{N + 1}, 5, {N + 1}, 2>::create(
+        Box::new(Wiring::<{N + 1}, {N + 1}>::buffer()),
+        Box::new(ConcatDifferentShapeBlocks::<1, 4, 1, 1>::create(
+            Box::new(Not::new()),
+            Box::new(carry_overflow()),
+        )),
+    );
+    let out_wrapper = Wiring::<{(N + 1) + 2}, {N + 3}>::wrapper();
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(layer3))
+        .connect_to(Box::new(layer4))
+        .connect_to(Box::new(layer5))
+        .connect_to(Box::new(out_wrapper))
+}
+
+// 結果の各bit, フラグ用の値の各bit, キャリー, 溢れ をopcodeで選ぶ組(16)
+// -> 結果(N), ゼロ, キャリー, 負, 溢れ
+// フラグ用の値はCmpのときだけ結果と違って差になる
+fn select_output<const N: usize>() -> MergeLayers<{32 * (2 * N + 2)}, {N + (N + 3)}, {N + 4}> where
+    [(); 32 * (2 * N + 2)]: Sized,
+    [(); 1 * (2 * N + 2)]: Sized,
+    [(); N + (N + 3)]: Sized,
+    [(); N + 3]: Sized,
+    [(); N + 4]: Sized,
+{
+    let layer1 = ConcatBlocks::<32, 1, {2 * N + 2}>::create_from_fn(and_or::<16>);
+    // 結果, フラグ用の値, キャリー, フラグ用の値の最上位bit, 溢れ
+    let mut table = [0; N + (N + 3)];
+    for (i, v) in table.iter_mut().enumerate() {
+        *v = match i {
+            _ if i < 2 * N + 1 => i,
+            _ if i == 2 * N + 1 => 2 * N - 1,
+            _ => 2 * N + 1,
+        };
+    }
+    let layer2 = Wiring::<{1 * (2 * N + 2)}, {N + (N + 3)}>::create(table);
+    let layer3 = ConcatDifferentShapeBlocks::<N, {N + 3}, N, 4>::create(
+        Box::new(Wiring::<N, N>::buffer()),
+        Box::new(ConcatDifferentShapeBlocks::<N, 3, 1, 3>::create(
+            Box::new(is_zero::<N>()),
+            Box::new(Wiring::<3, 3>::buffer()),
+        )),
+    );
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(layer3))
+}
+
+// Nbitの算術論理演算器
+// 入力は opcode(4), キャリー, a(N), b(N) の順, 出力は 結果(N), ゼロ, キャリー, 負, 溢れ の順
+// 全部の演算を並べて計算し, デコードしたopcodeで結果とフラグを選ぶ
+// 論理演算ではキャリーと溢れは0, シフトでは溢れは0
+pub struct Alu<const N: usize> where
+    [(); 5 + 2 * N]: Sized,
+    [(); 32 * (2 * N + 2)]: Sized,
+    [(); N + 4]: Sized,
+{
+    alu: MergeLayers<{5 + 2 * N}, {32 * (2 * N + 2)}, {N + 4}>,
+}
+
+impl<const N: usize> Component<{5 + 2 * N}, {N + 4}> for Alu<N> where
+    [(); 5 + 2 * N]: Sized,
+    [(); 32 * (2 * N + 2)]: Sized,
+    [(); N + 4]: Sized,
+{
+    fn eval(&self, input: [bool; 5 + 2 * N]) -> [bool; N + 4] {
+        self.alu.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.alu.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.alu.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.alu.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.alu.netlist(netlist, input)
+    }
+}
+
+impl<const N: usize> Alu<N> where
+    [(); 5 + 2 * N]: Sized,
+    [(); 5 + 2 * N + 0]: Sized,
+    [(); 5 + 2 * N + 1]: Sized,
+    [(); 2 * N]: Sized,
+    [(); 7 * N]: Sized,
+    [(); 2 * N + 1]: Sized,
+    [(); 7 * N + 1]: Sized,
+    [(); 2 + (2 * N + 1)]: Sized,
+    [(); 2 + (7 * N + 1)]: Sized,
+    [(); 4 + (2 + (2 * N + 1))]: Sized,
+    [(); 16 + (2 + (7 * N + 1))]: Sized,
+    [(); 19 + 7 * N]: Sized,
+    [(); 12 * (N + 1)]: Sized,
+    [(); 12 * (N + 1) + N]: Sized,
+    [(); (19 + 7 * N) + (12 * (N + 1) + N)]: Sized,
+    [(); (19 + 7 * N) + (N + 3)]: Sized,
+    [(); 1 * (N + 1)]: Sized,
+    [(); 1 * (N + 1) + N]: Sized,
+    [(); 1 + 2 * N]: Sized,
+    [(); (1 + 2 * N) + 2]: Sized,
+    [(); 1 * N + 1]: Sized,
+    [(); (1 * N + 1) + 2]: Sized,
+    [(); N + 1]: Sized,
+    [(); (N + 1) + 5]: Sized,
+    [(); (N + 1) + 2]: Sized,
+    [(); N + 3]: Sized,
+    [(); 22 + 8 * N]: Sized,
+    [(); 32 * (2 * N + 2)]: Sized,
+    [(); 1 * (2 * N + 2)]: Sized,
+    [(); N + (N + 3)]: Sized,
+    [(); N + 4]: Sized,
+{
+    pub fn new() -> Self {
+        use AluOp::*;
+        // signalsの出力の中の位置, デコードしたopcodeは値の位置にある
+        let (carry_in, not_carry_in, zero) = (16, 17, 18 + 7 * N);
+        let bit = |i: usize, k: usize| 18 + 7 * i + k;
+        let (a, not_a, b, not_b) = (|i| bit(i, 0), |i| bit(i, 1), |i| bit(i, 2), |i| bit(i, 3));
+        let (and, or, xor) = (|i| bit(i, 4), |i| bit(i, 5), |i| bit(i, 6));
+
+        // 足し算の繰り上がりとyを選ぶ, 使わない組は0につなぐ
+        let mut table = [zero; (19 + 7 * N) + (12 * (N + 1) + N)];
+        for (i, v) in table.iter_mut().enumerate().take(19 + 7 * N) {
+            *v = i;
+        }
+        let operands = table[(19 + 7 * N)..].chunks_mut(12);
+        for (i, chunk) in operands.take(N + 1).enumerate() {
+            // 0番目は繰り上がり, Decは全bit 1を足す
+            let pairs = match i {
+                0 => vec![(Adc, carry_in), (Sub, Sub as usize), (Sbc, not_carry_in), (Cmp, Cmp as usize), (Inc, Inc as usize)],
+                _ => vec![(Add, b(i - 1)), (Adc, b(i - 1)), (Sub, not_b(i - 1)), (Sbc, not_b(i - 1)), (Cmp, not_b(i - 1)), (Dec, Dec as usize)],
+            };
+            for (pair, (op, source)) in chunk.chunks_mut(2).zip(pairs) {
+                pair.copy_from_slice(&[op as usize, source]);
+            }
+        }
+        for i in 0..N {
+            table[(19 + 7 * N) + 12 * (N + 1) + i] = a(i);
+        }
+        let layer1 = Wiring::<{19 + 7 * N}, {(19 + 7 * N) + (12 * (N + 1) + N)}>::create(table);
+        let layer2 = ConcatDifferentShapeBlocks::<{19 + 7 * N}, {12 * (N + 1) + N}, {19 + 7 * N}, {N + 3}>::create(
+            Box::new(Wiring::<{19 + 7 * N}, {19 + 7 * N}>::buffer()),
+            Box::new(operand_adder::<N>()),
+        );
+        let in_wrapper = Wiring::<{(19 + 7 * N) + (N + 3)}, {22 + 8 * N}>::wrapper();
+
+        // 足し算の出力の位置
+        let (sum, carry, not_carry, overflow) = (|i| 19 + 7 * N + i, 19 + 8 * N, 20 + 8 * N, 21 + 8 * N);
+        // opcodeの順に候補を並べる, Cmpの結果だけフラグ用の値と違う
+        let result = |i: usize, flag: bool| {
+            let mut sources = [zero; 16];
+            for op in AluOp::ALL {
+                sources[op as usize] = match op {
+                    Add | Adc | Sub | Sbc | Inc | Dec => sum(i),
+                    And => and(i),
+                    Or => or(i),
+                    Xor => xor(i),
+                    Not => not_a(i),
+                    Shl => i.checked_sub(1).map_or(zero, a),
+                    Shr => (i + 1 < N).then_some(i + 1).map_or(zero, a),
+                    Rol => a((i + N - 1) % N),
+                    Ror => a((i + 1) % N),
+                    Cmp => if flag { sum(i) } else { a(i) },
+                };
+            }
+            sources
+        };
+        let mut carry_sources = [zero; 16];
+        let mut overflow_sources = [zero; 16];
+        for op in AluOp::ALL {
+            carry_sources[op as usize] = match op {
+                Add | Adc | Inc => carry,
+                Sub | Sbc | Dec | Cmp => not_carry,
+                Shl | Rol => a(N - 1),
+                Shr | Ror => a(0),
+                And | Or | Xor | Not => zero,
+            };
+            if matches!(op, Add | Adc | Sub | Sbc | Inc | Dec | Cmp) {
+                overflow_sources[op as usize] = overflow;
+            }
+        }
+        let mut table = [0; 32 * (2 * N + 2)];
+        for (i, chunk) in table.chunks_mut(32).enumerate() {
+            let sources = match i {
+                _ if i < N => result(i, false),
+                _ if i < 2 * N => result(i - N, true),
+                _ if i == 2 * N => carry_sources,
+                _ => overflow_sources,
+            };
+            for (op, (pair, source)) in chunk.chunks_mut(2).zip(sources).enumerate() {
+                pair.copy_from_slice(&[op, source]);
+            }
+        }
+        let layer3 = Wiring::<{22 + 8 * N}, {32 * (2 * N + 2)}>::create(table);
+
+        let alu = MergeLayers::create(Box::new(signals::<N>()), Box::new(layer1))
+            .connect_to(Box::new(layer2))
+            .connect_to(Box::new(in_wrapper))
+            .connect_to(Box::new(layer3))
+            .connect_to(Box::new(select_output::<N>()));
+        Self { alu }
+    }
+}
+
+#[cfg(test)]
+fn alu_input<const N: usize>(op: AluOp, carry: bool, a: usize, b: usize) -> [bool; 5 + 2 * N] {
+    use crate::num_bit_converter::*;
+
+    let op = bit_to_num(op.bits());
+    num_to_bit(op + ((carry as usize) << 4) + (a << 5) + (b << (5 + N)))
+}
+
+// Aluの答え, 結果, ゼロ, キャリー, 負, 溢れ
+#[cfg(test)]
+fn alu_reference(n: usize, op: AluOp, carry: bool, a: usize, b: usize) -> (usize, bool, bool, bool, bool) {
+    use AluOp::*;
+    let mask = (1 << n) - 1;
+    let sign = |v: usize| (v >> (n - 1)) & 1 == 1;
+    let signed = |v: usize| v as i64 - if sign(v) { 1 << n } else { 0 };
+    let fits = |v: i64| -(1 << (n - 1)) <= v && v < (1 << (n - 1));
+    // 足し算なら a + b + c, 引き算なら a - b - c として, 結果, キャリー, 溢れ
+    let add = |b: usize, c: usize| {
+        let value = a + b + c;
+        (value & mask, value > mask, !fits(signed(a) + signed(b) + c as i64))
+    };
+    let sub = |b: usize, c: usize| {
+        let value = a.wrapping_sub(b + c);
+        (value & mask, a < b + c, !fits(signed(a) - signed(b) - c as i64))
+    };
+    let c = carry as usize;
+    let (value, carry, overflow, flag_value) = match op {
+        Add | Adc | Inc => {
+            let (value, carry, overflow) = match op {
+                Add => add(b, 0),
+                Adc => add(b, c),
+                _ => add(0, 1),
+            };
+            (value, carry, overflow, value)
+        }
+        Sub | Sbc | Dec | Cmp => {
+            let (value, carry, overflow) = match op {
+                Sub | Cmp => sub(b, 0),
+                Sbc => sub(b, c),
+                _ => sub(0, 1),
+            };
+            (if op == Cmp { a } else { value }, carry, overflow, value)
+        }
+        And => (a & b, false, false, a & b),
+        Or => (a | b, false, false, a | b),
+        Xor => (a ^ b, false, false, a ^ b),
+        Not => (!a & mask, false, false, !a & mask),
+        Shl => ((a << 1) & mask, sign(a), false, (a << 1) & mask),
+        Shr => (a >> 1, a & 1 == 1, false, a >> 1),
+        Rol => (((a << 1) | (a >> (n - 1))) & mask, sign(a), false, ((a << 1) | (a >> (n - 1))) & mask),
+        Ror => ((a >> 1) | ((a & 1) << (n - 1)), a & 1 == 1, false, (a >> 1) | ((a & 1) << (n - 1))),
+    };
+    (value, flag_value == 0, carry, sign(flag_value), overflow)
+}
+
+#[cfg(test)]
+fn check_alu<const N: usize>(alu: &Alu<N>, op: AluOp, carry: bool, a: usize, b: usize) where
+    [(); 5 + 2 * N]: Sized,
+    [(); 32 * (2 * N + 2)]: Sized,
+    [(); N + 4]: Sized,
+{
+    use crate::num_bit_converter::*;
+    let output = alu.eval(alu_input::<N>(op, carry, a, b));
+    let actual = (bit_to_num(output) & ((1 << N) - 1), output[N], output[N + 1], output[N + 2], output[N + 3]);
+    assert_eq!(actual, alu_reference(N, op, carry, a, b), "{:?} {} {} {}", op, carry, a, b);
+}
+
+#[test]
+fn alu_test() {
+    // 4bitは全部の組み合わせ
+    let alu = Alu::<4>::new();
+    for op in AluOp::ALL {
+        for carry in [false, true] {
+            for a in 0..16 {
+                for b in 0..16 {
+                    check_alu(&alu, op, carry, a, b);
+                }
+            }
+        }
+    }
+    // 8bitはaを全部と境目になるbをいくつか
+    let alu = Alu::<8>::new();
+    for op in AluOp::ALL {
+        for carry in [false, true] {
+            for a in 0..256 {
+                for b in [0x7f, 0x80, a, 0x5a ^ a] {
+                    check_alu(&alu, op, carry, a, b);
+                }
+            }
+        }
+    }
+    // 使わないopcodeは0を出す
+    let output = alu.eval(num_to_bit(15 + (3 << 5)));
+    assert_eq!(output, num_to_bit(1 << 8));
+}
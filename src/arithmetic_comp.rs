@@ -1,4 +1,5 @@
 use crate::core::*;
+use crate::netlist::{Net, Netlist};
 use crate::basic_comp::*;
 
 pub struct HalfAdder {
@@ -8,6 +9,21 @@ impl Component<2, 2> for HalfAdder {
     fn eval(&self, input: [bool; 2]) -> [bool; 2] {
         self.adder.eval(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.adder.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.adder.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.adder.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.adder.netlist(netlist, input)
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
 }
 
 impl HalfAdder {
@@ -39,6 +55,21 @@ impl Component<3, 2> for FullAdder {
     fn eval(&self, input: [bool; 3]) -> [bool; 2] {
         self.adder.eval(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.adder.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.adder.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.adder.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.adder.netlist(netlist, input)
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
 }
 
 impl FullAdder {
@@ -80,6 +111,18 @@ impl Component<17, 9> for EightBitFullAdder {
     fn eval(&self, input: [bool; 17]) -> [bool; 9] {
         self.adder.eval(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.adder.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.adder.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.adder.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.adder.netlist(netlist, input)
+    }
 }
 impl EightBitFullAdder {
     pub fn new() -> Self {
@@ -133,6 +176,18 @@ where
     fn eval(&self, input: [bool; 16 * N]) -> [bool; 8 * N] {
         self.adder.eval(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.adder.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.adder.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.adder.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.adder.netlist(netlist, input)
+    }
 }
 
 impl<const N: usize> NByteAdder<N>
@@ -142,7 +197,7 @@ where
     [(); 0 + 16 * N]: Sized,
     [(); 8 * N + 1]: Sized,
 {
-    fn new() -> Self {
+    pub fn new() -> Self {
         let in_wrapper = Wiring::<{16 * N}, {0 + 16 * N}>::wrapper();
         let layer1 = Wiring::<{16 * N}, {16 * N}>::zip_with_chunk::<8>();
         let layer1 = ConcatDifferentShapeBlocks::create(
@@ -189,6 +244,18 @@ impl Component<16, 8> for EightBitSubtractor {
     fn eval(&self, input: [bool; 16]) -> [bool; 8] {
         self.subtractor.eval(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.subtractor.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.subtractor.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.subtractor.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.subtractor.netlist(netlist, input)
+    }
 }
 
 impl EightBitSubtractor {
@@ -236,17 +303,173 @@ fn subtractor_test() {
     }
 }
 
-struct Comparator {
+// 引く数を反転して足す組, a, b -> a, !b
+pub(crate) fn pass_not() -> ConcatBlocks<1, 1, 2> {
+    ConcatBlocks::create([Box::new(Buffer::new()), Box::new(Not::new())])
+}
+
+// 符号付きの溢れ, a, b, 差 の最上位bitから (a ^ b) & (a ^ 差)
+fn sub_overflow() -> MergeLayers<4, 2, 1> {
+    let layer1 = ConcatBlocks::<2, 1, 2>::create_from_fn(XOR::<2>::new);
+    MergeLayers::create(Box::new(layer1), Box::new(And::<2>::new()))
+}
+
+// Nbitの引き算 a - b - 借り
+// 入力は a(N), b(N), 借り の順, 出力は 差(N), 借り, 符号付きの溢れ, 符号 の順
+// 借りを下位の出力からつなげばN bitずつ繋いで長くできる
+pub struct Subtractor<const N: usize> where
+    [(); 2 * N + 1]: Sized,
+    [(); N + 6]: Sized,
+    [(); N + 3]: Sized,
+{
+    subtractor: MergeLayers<{2 * N + 1}, {N + 6}, {N + 3}>,
+}
+
+impl<const N: usize> Component<{2 * N + 1}, {N + 3}> for Subtractor<N> where
+    [(); 2 * N + 1]: Sized,
+    [(); N + 6]: Sized,
+    [(); N + 3]: Sized,
+{
+    fn eval(&self, input: [bool; 2 * N + 1]) -> [bool; N + 3] {
+        self.subtractor.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.subtractor.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.subtractor.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.subtractor.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.subtractor.netlist(netlist, input)
+    }
+}
+
+impl<const N: usize> Subtractor<N> where
+    [(); 2 * N + 1]: Sized,
+    [(); N + 6]: Sized,
+    [(); N + 3]: Sized,
+    [(); 2 * N]: Sized,
+    [(); 1 + 2 * N]: Sized,
+    [(); (1 + 2 * N) + 2]: Sized,
+    [(); 1 * N + 1]: Sized,
+    [(); (1 * N + 1) + 2]: Sized,
+{
+    pub fn new() -> Self {
+        // a - b - 借り = a + !b + !借り, 借りは最後の繰り上がりの反転
+        // 借り, (a0, b0), (a1, b1), ..., 最上位のa, b
+        let mut table = [0; (1 + 2 * N) + 2];
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = match i {
+                0 => 2 * N,
+                _ if i < 1 + 2 * N && i % 2 == 1 => i / 2,
+                _ if i < 1 + 2 * N => N + i / 2 - 1,
+                _ if i == 1 + 2 * N => N - 1,
+                _ => 2 * N - 1,
+            };
+        }
+        let layer1 = Wiring::<{2 * N + 1}, {(1 + 2 * N) + 2}>::create(table);
+        let layer2 = ConcatDifferentShapeBlocks::<{1 + 2 * N}, 2, {1 + 2 * N}, 2>::create(
+            Box::new(ConcatDifferentShapeBlocks::<1, {2 * N}, 1, {2 * N}>::create(
+                Box::new(Not::new()),
+                Box::new(ConcatBlocks::<2, 2, N>::create_from_fn(pass_not)),
+            )),
+            Box::new(Wiring::<2, 2>::buffer()),
+        );
+        let layer3 = ConcatDifferentShapeBlocks::<{1 + 2 * N}, 2, {1 * N + 1}, 2>::create(
+            Box::new(RecurrentBlock::<1, 2, 1, N>::create_from_fn(FullAdder::new)),
+            Box::new(Wiring::<2, 2>::buffer()),
+        );
+        // 差, 繰り上がり, (a, b, a, 差) の最上位bit, 差の最上位bit
+        let mut table = [0; N + 6];
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = match i {
+                _ if i <= N => i,
+                _ if i == N + 1 || i == N + 3 => N + 1,
+                _ if i == N + 2 => N + 2,
+                _ => N - 1,
+            };
+        }
+        let layer4 = Wiring::<{(1 * N + 1) + 2}, {N + 6}>::create(table);
+        let flags = ConcatDifferentShapeBlocks::<5, 1, 2, 1>::create(
+            Box::new(ConcatDifferentShapeBlocks::<1, 4, 1, 1>::create(
+                Box::new(Not::new()),
+                Box::new(sub_overflow()),
+            )),
+            Box::new(Buffer::new()),
+        );
+        let layer5 = ConcatDifferentShapeBlocks::<N, 6, N, 3>::create(
+            Box::new(Wiring::<N, N>::buffer()),
+            Box::new(flags),
+        );
+        let subtractor = MergeLayers::create(Box::new(layer1), Box::new(layer2))
+            .connect_to(Box::new(layer3))
+            .connect_to(Box::new(layer4))
+            .connect_to(Box::new(layer5));
+        Self { subtractor }
+    }
+}
+
+#[test]
+fn n_bit_subtractor_test() {
+    use crate::num_bit_converter::*;
+
+    // 8bitのすべての組み合わせを借りあり/なしで確かめる
+    let sub = Subtractor::<8>::new();
+    for borrow in 0..2 {
+        for a in 0..256 {
+            for b in 0..256 {
+                let output = sub.eval(num_to_bit((borrow << 16) + (b << 8) + a));
+                let diff = (a as i64 - b as i64 - borrow as i64).rem_euclid(256) as usize;
+                let signed = a as u8 as i8 as i64 - b as u8 as i8 as i64 - borrow as i64;
+                assert_eq!(bit_to_num(output) & 0xff, diff, "{} - {} - {}", a, b, borrow);
+                assert_eq!(output[8], a < b + borrow);
+                assert_eq!(output[9], !(-128..=127).contains(&signed));
+                assert_eq!(output[10], diff >= 128);
+            }
+        }
+    }
+
+    // 4bitを2つ繋いで8bitの引き算にする
+    let low = Subtractor::<4>::new();
+    let high = Subtractor::<4>::new();
+    for a in 0..256 {
+        for b in (0..256).step_by(7) {
+            let l = low.eval(num_to_bit(((b & 0xf) << 4) + (a & 0xf)));
+            let h = high.eval(num_to_bit(((l[4] as usize) << 8) + ((b >> 4) << 4) + (a >> 4)));
+            let diff = (bit_to_num(l) & 0xf) + ((bit_to_num(h) & 0xf) << 4);
+            assert_eq!(diff, (a + 256 - b) % 256);
+            assert_eq!(h[4], a < b);
+        }
+    }
+}
+
+// 1bitの比較, a, b -> a > b, a == b, a < b
+struct BitComparator {
     comp: MergeLayers<2, 4, 3>,
 }
 
-impl Component<2, 3> for Comparator {
+impl Component<2, 3> for BitComparator {
     fn eval(&self, input: [bool; 2]) -> [bool; 3] {
         self.comp.eval(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.comp.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.comp.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.comp.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.comp.netlist(netlist, input)
+    }
 }
 
-impl Comparator {
+impl BitComparator {
     fn new() -> Self {
         let layer1 = ConcatBlocks::create(
             [Branch::<2>::new(); 2].map(|b| Box::new(b) as Box<dyn Component<1, 2>>)
@@ -291,7 +514,7 @@ impl Comparator {
 
 #[test]
 fn comparator_test() {
-    let comp = Comparator::new();
+    let comp = BitComparator::new();
     assert_eq!(comp.eval([false, false]), [false, true, false]);
     assert_eq!(comp.eval([true, false]), [true, false, false]);
     assert_eq!(comp.eval([false, true]), [false, false, true]);
@@ -306,6 +529,18 @@ impl Component<19, 3> for EightBitComparator {
     fn eval(&self, input: [bool; 19]) -> [bool; 3] {
         self.comp.eval(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.comp.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.comp.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.comp.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.comp.netlist(netlist, input)
+    }
 }
 
 impl EightBitComparator {
@@ -319,7 +554,7 @@ impl EightBitComparator {
                 MergeLayers::create(Box::new(layer1), Box::new(layer2))
             };
 
-            let cur_bit_comp = Comparator::new();
+            let cur_bit_comp = BitComparator::new();
             let recur_bit = ConcatBlocks::create(
                 [Buffer::new(); 3].map(|c| Box::new(c) as Box<dyn Component<1, 1>>)
             );
@@ -379,104 +614,249 @@ fn eight_bit_comparator_test() {
     }
 }
 
-struct EightBitMultiplier {
-    multiplier: MergeLayers<16, 80, 16>,
-}
-
-impl Component<16, 16> for EightBitMultiplier {
-    fn eval(&self, input: [bool; 16]) -> [bool; 16] {
-        self.multiplier.eval(input)
+// 上位と下位の比較結果をまとめる, 上位(3), 下位(3) -> 大, 等, 小
+// 上位が等しいときだけ下位の結果を使う
+fn compare_merge() -> MergeLayers<6, 5, 3> {
+    let layer1 = Wiring::<6, 8>::create([0, 1, 3, 1, 4, 1, 5, 2]);
+    let layer2 = ConcatDifferentShapeBlocks::<7, 1, 4, 1>::create(
+        Box::new(ConcatDifferentShapeBlocks::<1, 6, 1, 3>::create(
+            Box::new(Buffer::new()),
+            Box::new(ConcatBlocks::<2, 1, 3>::create_from_fn(And::<2>::new)),
+        )),
+        Box::new(Buffer::new()),
+    );
+    let layer3 = ConcatDifferentShapeBlocks::<2, 3, 1, 2>::create(
+        Box::new(Or::<2>::new()),
+        Box::new(ConcatDifferentShapeBlocks::<1, 2, 1, 1>::create(
+            Box::new(Buffer::new()),
+            Box::new(Or::<2>::new()),
+        )),
+    );
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(layer3))
+}
+
+// 下位から順に比べるときの1bit分, 下位の結果(3), a, b -> 結果(3)
+fn compare_cell() -> MergeLayers<5, 6, 3> {
+    let layer1 = ConcatDifferentShapeBlocks::<3, 2, 3, 3>::create(
+        Box::new(Wiring::<3, 3>::buffer()),
+        Box::new(BitComparator::new()),
+    );
+    let layer2 = Wiring::<6, 6>::create([3, 4, 5, 0, 1, 2]);
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(compare_merge()))
+}
+
+// 比べるものがないときの結果, 等しい
+fn equal_seed() -> ConcatDifferentShapeBlocks<0, 0, 2, 1> {
+    ConcatDifferentShapeBlocks::create(
+        Box::new(ConcatDifferentShapeBlocks::<0, 0, 1, 1>::create(
+            Box::new(Constant::<0, 1, false>::new()),
+            Box::new(Constant::<0, 1, true>::new()),
+        )),
+        Box::new(Constant::<0, 1, false>::new()),
+    )
+}
+
+// a(N), b(N) を bitごとの (a, b) に並べる
+// 符号付きでは最上位bitを入れ替える, 符号が違うときだけ大小が逆になるのでこれで足りる
+fn compare_pairs<const N: usize>(signed: bool) -> Wiring<{2 * N}, {2 * N}> {
+    let mut table = [0; 2 * N];
+    for (bit, pair) in table.chunks_mut(2).enumerate() {
+        if signed && bit == N - 1 {
+            pair.copy_from_slice(&[N + bit, bit]);
+        } else {
+            pair.copy_from_slice(&[bit, N + bit]);
+        }
     }
+    Wiring::create(table)
 }
 
-const BYTE: usize = 8;
+// Nbitの比較, 入力は a(N), b(N), 出力は a > b, a == b, a < b
+// 下位のbitから順に結果を伝えるので段数はNに比例する
+pub struct Comparator<const N: usize> where
+    [(); 2 * N]: Sized,
+    [(); 0 * N + 3]: Sized,
+{
+    comp: MergeLayers<{2 * N}, {0 * N + 3}, 3>,
+}
 
-impl EightBitMultiplier {
-    fn dummy(num1: usize, num2: usize) -> usize {
-        use crate::num_bit_converter::*;
-        let num1_bit = num_to_bit::<BYTE>(num1);
-        num1_bit.iter().enumerate()
-            .map(|(i, &b)| if b {num2 << i} else {0})
-            .sum()
+impl<const N: usize> Component<{2 * N}, 3> for Comparator<N> where
+    [(); 2 * N]: Sized,
+    [(); 0 * N + 3]: Sized,
+{
+    fn eval(&self, input: [bool; 2 * N]) -> [bool; 3] {
+        self.comp.eval(input)
     }
-    fn new() -> Self {
-        let one_bit_multiplier = || {
-            // 先頭ビットは最後まで0
-            let shifter = Wiring::<{2 * BYTE}, {2 * BYTE}>::rotate_right::<1>();
-            let buffer = ConcatBlocks::create(
-                [Buffer::new(); BYTE].map(|c| Box::new(c) as Box<dyn Component<1, 1>>)
-            );
-            let padding = Constant::<0, {2 * BYTE - BYTE}, false>::new();
-            // let in_wrapper = Wiring::<8, 8>::wrapper();
-            let cur_input = ConcatDifferentShapeBlocks::create(
-                Box::new(buffer),
-                Box::new(padding)
-            );
-            let all_input = ConcatDifferentShapeBlocks::create(
-                Box::new(shifter),
-                Box::new(cur_input)
-            );
-            let adder = NByteAdder::<2>::new();
-            MergeLayers::create(Box::new(all_input), Box::new(adder))
-        };
+    fn children(&self) -> Vec<Child<'_>> {
+        self.comp.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.comp.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.comp.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.comp.netlist(netlist, input)
+    }
+}
 
-        let multiplier = RecurrentBlock::<{2 * BYTE}, 8, 0, BYTE>::create_from_fn(
-            one_bit_multiplier
+impl<const N: usize> Comparator<N> where
+    [(); 2 * N]: Sized,
+    [(); 0 * N + 3]: Sized,
+    [(); 0 + 2 * N]: Sized,
+    [(); 3 + 2 * N]: Sized,
+{
+    // 符号なし
+    pub fn new() -> Self {
+        Self::create(false)
+    }
+    // 2の補数の符号付き
+    pub fn signed() -> Self {
+        Self::create(true)
+    }
+    fn create(signed: bool) -> Self {
+        let in_wrapper = Wiring::<{2 * N}, {0 + 2 * N}>::wrapper();
+        let layer1 = ConcatDifferentShapeBlocks::<0, {2 * N}, 3, {2 * N}>::create(
+            Box::new(equal_seed()),
+            Box::new(compare_pairs::<N>(signed)),
         );
+        let layer2 = RecurrentBlock::<3, 2, 0, N>::create_from_fn(compare_cell);
+        let out_wrapper = Wiring::<{0 * N + 3}, 3>::wrapper();
+        let comp = MergeLayers::create(Box::new(in_wrapper), Box::new(layer1))
+            .connect_to(Box::new(layer2))
+            .connect_to(Box::new(out_wrapper));
+        Self { comp }
+    }
+}
 
-        let rev = Wiring::<BYTE, BYTE>::reverse();
-        let add_switch_branch = MergeLayers::<BYTE, BYTE, {BYTE * BYTE}>::create(
-            Box::new(rev),
-            Box::new(ConcatBlocks::<1, BYTE, BYTE>::create_from_fn(Branch::<BYTE>::new))
-        );
+// 距離distance下のbitまでの結果とまとめる, 下がなければ等しいとする
+fn compare_level<const N: usize>(distance: usize) -> MergeLayers<{3 * N}, {6 * N}, {3 * N}> where
+    [(); 3 * N]: Sized,
+    [(); 3 * N + 0]: Sized,
+    [(); 3 * N + 3]: Sized,
+    [(); 6 * N]: Sized,
+{
+    let mut table = [0; 6 * N];
+    for (bit, inputs) in table.chunks_mut(6).enumerate() {
+        let lower = bit.checked_sub(distance).map_or(3 * N, |j| 3 * j);
+        for k in 0..3 {
+            inputs[k] = 3 * bit + k;
+            inputs[3 + k] = lower + k;
+        }
+    }
+    let in_wrapper = Wiring::<{3 * N}, {3 * N + 0}>::wrapper();
+    let layer1 = ConcatDifferentShapeBlocks::<{3 * N}, 0, {3 * N}, 3>::create(
+        Box::new(Wiring::<{3 * N}, {3 * N}>::buffer()),
+        Box::new(equal_seed()),
+    );
+    let layer2 = Wiring::<{3 * N + 3}, {6 * N}>::create(table);
+    let layer3 = ConcatBlocks::<6, 3, N>::create_from_fn(compare_merge);
+    MergeLayers::create(Box::new(in_wrapper), Box::new(layer1))
+        .connect_to(Box::new(layer2))
+        .connect_to(Box::new(layer3))
+}
+
+// Comparatorと同じ入出力で, bitごとの結果を距離1, 2, 4, ... とまとめていく
+// 段数はlog2(N)に比例する
+pub struct PrefixComparator<const N: usize> where
+    [(); 2 * N]: Sized,
+    [(); 3 * N]: Sized,
+{
+    comp: MergeLayers<{2 * N}, {3 * N}, 3>,
+}
 
-        let multiplicand_branch = {
-            let branch = ConcatBlocks::<1, BYTE, BYTE>::create_from_fn(Branch::<BYTE>::new);
-            let unzip = Wiring::<{BYTE * BYTE}, {BYTE * BYTE}>::unzip::<BYTE>();
-            MergeLayers::create(Box::new(branch), Box::new(unzip))
-        };
-        let zip_input_and_switch = {
-            let c = ConcatBlocks::create(
-                [Box::new(multiplicand_branch), Box::new(add_switch_branch)]
-            );
-            MergeLayers::create(
-                Box::new(c),
-                Box::new(Wiring::<{2 * BYTE * BYTE}, {2 * BYTE * BYTE}>::zip::<{BYTE * BYTE}>())
-            )
-        };
-        let switched = MergeLayers::create(
-            Box::new(zip_input_and_switch),
-            Box::new(ConcatBlocks::<2, 1, {BYTE * BYTE}>::create_from_fn(And::<2>::new)),
-        );
-        let init_hidden_state = Constant::<0, {2 * BYTE}, false>::new();
-        let input = ConcatDifferentShapeBlocks::create(
-            Box::new(init_hidden_state),
-            Box::new(switched)
-        );
-        let multiplier = MergeLayers::<{2 * BYTE}, {2 * BYTE + BYTE * BYTE}, {2 * BYTE}>::debug(
-            Box::new(input),
-            Box::new(multiplier)
-        );
+impl<const N: usize> Component<{2 * N}, 3> for PrefixComparator<N> where
+    [(); 2 * N]: Sized,
+    [(); 3 * N]: Sized,
+{
+    fn eval(&self, input: [bool; 2 * N]) -> [bool; 3] {
+        self.comp.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.comp.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.comp.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.comp.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.comp.netlist(netlist, input)
+    }
+}
 
-        Self {multiplier}
+impl<const N: usize> PrefixComparator<N> where
+    [(); 2 * N]: Sized,
+    [(); 3 * N]: Sized,
+    [(); 3 * N + 0]: Sized,
+    [(); 3 * N + 3]: Sized,
+    [(); 6 * N]: Sized,
+{
+    pub fn new() -> Self {
+        Self::create(false)
+    }
+    pub fn signed() -> Self {
+        Self::create(true)
+    }
+    fn create(signed: bool) -> Self {
+        let layer1 = ConcatBlocks::<2, 3, N>::create_from_fn(BitComparator::new);
+        let mut prefix: Box<dyn Component<{3 * N}, {3 * N}>> =
+            Box::new(Wiring::<{3 * N}, {3 * N}>::buffer());
+        let mut distance = 1;
+        while distance < N {
+            prefix = Box::new(MergeLayers::create(prefix, Box::new(compare_level::<N>(distance))));
+            distance *= 2;
+        }
+        // 最上位bitにすべてのbitの結果がまとまる
+        let last = Wiring::<{3 * N}, 3>::create([3 * N - 3, 3 * N - 2, 3 * N - 1]);
+        let comp = MergeLayers::create(Box::new(compare_pairs::<N>(signed)), Box::new(layer1))
+            .connect_to(prefix)
+            .connect_to(Box::new(last));
+        Self { comp }
     }
 }
 
 #[test]
-#[ignore = "this test takes too long time"]
-fn eight_bit_multiplier_test() {
+fn n_bit_comparator_test() {
     use crate::num_bit_converter::*;
 
-    let mul = EightBitMultiplier::new();
+    let expected = |a: i64, b: i64| [a > b, a == b, a < b];
+    let unsigned = Comparator::<8>::new();
+    let signed = Comparator::<8>::signed();
+    let prefix = PrefixComparator::<8>::new();
+    let prefix_signed = PrefixComparator::<8>::signed();
+    for a in 0..256 {
+        for b in 0..256 {
+            let input = num_to_bit((b << 8) + a);
+            let (sa, sb) = (a as u8 as i8 as i64, b as u8 as i8 as i64);
+            assert_eq!(unsigned.eval(input), expected(a as i64, b as i64), "{} {}", a, b);
+            assert_eq!(prefix.eval(input), expected(a as i64, b as i64), "{} {}", a, b);
+            assert_eq!(signed.eval(input), expected(sa, sb), "{} {}", sa, sb);
+            assert_eq!(prefix_signed.eval(input), expected(sa, sb), "{} {}", sa, sb);
+        }
+    }
 
-    for i in 0..256 {
-        for j in 0..256 {
-            let input = num_to_bit((i << 8) + j);
-            assert_eq!(bit_to_num(mul.eval(input)), i * j);
-            assert_eq!(
-                EightBitMultiplier::dummy(i, j),
-                i * j
-            );
+    // 2の累乗でない幅と1bit
+    let comp = PrefixComparator::<5>::signed();
+    for a in 0..32 {
+        for b in 0..32 {
+            let (sa, sb) = ((a as i64) - ((a as i64 & 16) << 1), (b as i64) - ((b as i64 & 16) << 1));
+            assert_eq!(comp.eval(num_to_bit((b << 5) + a)), expected(sa, sb));
         }
     }
+    assert_eq!(Comparator::<1>::new().eval([true, false]), [true, false, false]);
+    assert_eq!(Comparator::<1>::signed().eval([true, false]), [false, false, true]);
+    assert_eq!(PrefixComparator::<1>::new().eval([false, false]), [false, true, false]);
+}
+
+#[test]
+fn comparator_depth_test() {
+    use crate::optimize::simplify;
+
+    let ripple = simplify(&Comparator::<32>::new()).unwrap().0.netlist().metrics();
+    let prefix = simplify(&PrefixComparator::<32>::new()).unwrap().0.netlist().metrics();
+    assert!(prefix.depth * 2 < ripple.depth, "{:?} {:?}", prefix, ripple);
 }
@@ -0,0 +1,336 @@
+use crate::core::*;
+use crate::netlist::{Net, Netlist};
+use crate::basic_comp::*;
+use crate::decoder::BitDecoder;
+use crate::flip_flop::select;
+use crate::multiplier::with_zero;
+use crate::num_bit_converter::num_to_bit;
+
+// BarrelShifterの動作, 左はbit0からbitN-1に向かう方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarrelMode {
+    // 空いたところには0を入れる
+    ShiftLeft,
+    ShiftRight,
+    // 空いたところには最上位bitを入れる
+    ArithmeticShiftRight,
+    // 左に回す
+    Rotate,
+    RotateRight,
+}
+
+impl BarrelMode {
+    pub fn bits(self) -> [bool; 3] {
+        num_to_bit(self as usize)
+    }
+}
+
+// mode(3), 最上位bit -> 右向き, 回す, 空いたところに入れる値
+fn mode_flags() -> MergeLayers<4, 7, 3> {
+    let layer1 = ConcatDifferentShapeBlocks::<3, 1, 8, 1>::create(
+        Box::new(BitDecoder::<3>::new()),
+        Box::new(Buffer::new()),
+    );
+    // ShiftRight, ArithmeticShiftRight, RotateRight | Rotate, RotateRight | ArithmeticShiftRight, 最上位bit
+    let layer2 = Wiring::<9, 7>::create([1, 2, 4, 3, 4, 2, 8]);
+    let layer3 = ConcatDifferentShapeBlocks::<3, 4, 1, 2>::create(
+        Box::new(Or::<3>::new()),
+        Box::new(ConcatDifferentShapeBlocks::<2, 2, 1, 1>::create(
+            Box::new(Or::<2>::new()),
+            Box::new(And::<2>::new()),
+        )),
+    );
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(layer3))
+}
+
+// 右向き, 値(N) -> 右向きなら並びを逆にした値
+// 右へのシフトと右に回すのは, 逆に並べて左にシフトし, また逆に並べて戻す
+fn reverse_if<const N: usize>() -> MergeLayers<{1 + N}, {3 * N}, {1 * N}> where
+    [(); 1 + N]: Sized,
+    [(); 3 * N]: Sized,
+    [(); 1 * N]: Sized,
+{
+    let mut table = [0; 3 * N];
+    for (i, chunk) in table.chunks_mut(3).enumerate() {
+        chunk.copy_from_slice(&[0, N - i, 1 + i]);
+    }
+    let layer1 = Wiring::<{1 + N}, {3 * N}>::create(table);
+    let layer2 = ConcatBlocks::<3, 1, N>::create_from_fn(select);
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+}
+
+// シフトする, 回す, 回したときのbit, 空いたところに入れる値, そのままのbit -> 次のbit
+fn shift_cell() -> MergeLayers<5, 3, 1> {
+    let layer1 = ConcatDifferentShapeBlocks::<1, 4, 1, 2>::create(
+        Box::new(Buffer::new()),
+        Box::new(ConcatDifferentShapeBlocks::<3, 1, 1, 1>::create(
+            Box::new(select()),
+            Box::new(Buffer::new()),
+        )),
+    );
+    MergeLayers::create(Box::new(layer1), Box::new(select()))
+}
+
+// 段の間を流れる線は シフト量(log2(N)), 右向き, 回す, 空いたところに入れる値, 値(N), キャリー
+// 値は右向きなら逆に並べてあり, キャリーは値の最上位bitの上にもう1bitあるものとして一緒に左にシフトする
+
+// mode(3), シフト量(log2(N)), 値(N) -> 段の間の線
+fn prepare<const N: usize>() -> MergeLayers<{3 + log2(N) + N}, {(log2(N) + 3) + (1 * N + 1)}, {log2(N) + N + 4}> where
+    [(); 3 + log2(N) + N]: Sized,
+    [(); 3 + log2(N) + N + 0]: Sized,
+    [(); 3 + log2(N) + N + 1]: Sized,
+    [(); log2(N) + N + 1]: Sized,
+    [(); 3 + (log2(N) + N + 1)]: Sized,
+    [(); 4 + (log2(N) + N + 1)]: Sized,
+    [(); log2(N) + 3]: Sized,
+    [(); 1 + N]: Sized,
+    [(); (1 + N) + 1]: Sized,
+    [(); (log2(N) + 3) + ((1 + N) + 1)]: Sized,
+    [(); 3 * N]: Sized,
+    [(); 1 * N]: Sized,
+    [(); 1 * N + 1]: Sized,
+    [(); (log2(N) + 3) + (1 * N + 1)]: Sized,
+    [(); log2(N) + N + 4]: Sized,
+{
+    let l = log2(N);
+    // mode, 最上位bit, 残りはそのまま
+    let mut table = [0; 4 + (log2(N) + N + 1)];
+    for (i, v) in table.iter_mut().enumerate() {
+        *v = match i {
+            _ if i < 3 => i,
+            3 => 2 + l + N,
+            _ => i - 1,
+        };
+    }
+    let layer1 = Wiring::<{3 + log2(N) + N + 1}, {4 + (log2(N) + N + 1)}>::create(table);
+    let layer2 = ConcatDifferentShapeBlocks::<4, {log2(N) + N + 1}, 3, {log2(N) + N + 1}>::create(
+        Box::new(mode_flags()),
+        Box::new(Wiring::<{log2(N) + N + 1}, {log2(N) + N + 1}>::buffer()),
+    );
+    // シフト量, 右向き, 回す, 空いたところに入れる値, 右向き, 値, 0
+    let (right, rotate, fill, amount, value, zero) = (0, 1, 2, |j| 3 + j, |i| 3 + l + i, 3 + l + N);
+    let mut table = [zero; (log2(N) + 3) + ((1 + N) + 1)];
+    for (j, v) in table.iter_mut().enumerate().take(l) {
+        *v = amount(j);
+    }
+    table[l..(l + 4)].copy_from_slice(&[right, rotate, fill, right]);
+    for i in 0..N {
+        table[l + 4 + i] = value(i);
+    }
+    let layer3 = Wiring::<{3 + (log2(N) + N + 1)}, {(log2(N) + 3) + ((1 + N) + 1)}>::create(table);
+    let layer4 = ConcatDifferentShapeBlocks::<{log2(N) + 3}, {(1 + N) + 1}, {log2(N) + 3}, {1 * N + 1}>::create(
+        Box::new(Wiring::<{log2(N) + 3}, {log2(N) + 3}>::buffer()),
+        Box::new(ConcatDifferentShapeBlocks::<{1 + N}, 1, {1 * N}, 1>::create(
+            Box::new(reverse_if::<N>()),
+            Box::new(Wiring::<1, 1>::buffer()),
+        )),
+    );
+    let out_wrapper = Wiring::<{(log2(N) + 3) + (1 * N + 1)}, {log2(N) + N + 4}>::wrapper();
+    MergeLayers::create(Box::new(with_zero::<{3 + log2(N) + N}>()), Box::new(layer1))
+        .connect_to(Box::new(layer2))
+        .connect_to(Box::new(layer3))
+        .connect_to(Box::new(layer4))
+        .connect_to(Box::new(out_wrapper))
+}
+
+// シフト量のk bit目が1なら 2^k だけ左にシフトする段
+fn barrel_stage<const N: usize>(k: usize) -> MergeLayers<{log2(N) + N + 4}, {(log2(N) + 3) + 1 * (N + 1)}, {log2(N) + N + 4}> where
+    [(); log2(N) + N + 4]: Sized,
+    [(); log2(N) + 3]: Sized,
+    [(); 5 * (N + 1)]: Sized,
+    [(); 1 * (N + 1)]: Sized,
+    [(); (log2(N) + 3) + 5 * (N + 1)]: Sized,
+    [(); (log2(N) + 3) + 1 * (N + 1)]: Sized,
+{
+    let l = log2(N);
+    let (rotate, fill, value) = (l + 1, l + 2, |i| l + 3 + i);
+    // 2^k < N なので, はみ出すのは下のbitに入ってくるところだけ
+    let distance = 1 << k;
+    let mut table = [0; (log2(N) + 3) + 5 * (N + 1)];
+    for (i, v) in table.iter_mut().enumerate().take(l + 3) {
+        *v = i;
+    }
+    // キャリーも含めてbitごとに シフトする, 回す, 回したときのbit, 空いたところに入れる値, そのままのbit
+    for (i, chunk) in table[(l + 3)..].chunks_mut(5).enumerate() {
+        let (rotated, filled) = match i.checked_sub(distance) {
+            Some(source) => (value(source), value(source)),
+            None => (value(i + N - distance), fill),
+        };
+        chunk.copy_from_slice(&[k, rotate, rotated, filled, value(i)]);
+    }
+    let layer1 = Wiring::<{log2(N) + N + 4}, {(log2(N) + 3) + 5 * (N + 1)}>::create(table);
+    let layer2 = ConcatDifferentShapeBlocks::<{log2(N) + 3}, {5 * (N + 1)}, {log2(N) + 3}, {1 * (N + 1)}>::create(
+        Box::new(Wiring::<{log2(N) + 3}, {log2(N) + 3}>::buffer()),
+        Box::new(ConcatBlocks::<5, 1, {N + 1}>::create_from_fn(shift_cell)),
+    );
+    let out_wrapper = Wiring::<{(log2(N) + 3) + 1 * (N + 1)}, {log2(N) + N + 4}>::wrapper();
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(out_wrapper))
+}
+
+// 段の間の線 -> 値(N), キャリー
+fn finish<const N: usize>() -> MergeLayers<{log2(N) + N + 4}, {1 * N + 1}, {N + 1}> where
+    [(); log2(N) + N + 4]: Sized,
+    [(); 1 + N]: Sized,
+    [(); (1 + N) + 1]: Sized,
+    [(); 3 * N]: Sized,
+    [(); 1 * N]: Sized,
+    [(); 1 * N + 1]: Sized,
+    [(); N + 1]: Sized,
+{
+    let l = log2(N);
+    let mut table = [0; (1 + N) + 1];
+    table[0] = l;
+    for (i, v) in table.iter_mut().enumerate().skip(1) {
+        *v = l + 2 + i;
+    }
+    let layer1 = Wiring::<{log2(N) + N + 4}, {(1 + N) + 1}>::create(table);
+    let layer2 = ConcatDifferentShapeBlocks::<{1 + N}, 1, {1 * N}, 1>::create(
+        Box::new(reverse_if::<N>()),
+        Box::new(Wiring::<1, 1>::buffer()),
+    );
+    let out_wrapper = Wiring::<{1 * N + 1}, {N + 1}>::wrapper();
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(out_wrapper))
+}
+
+// 実行時に決めた量だけシフトする
+// 入力は mode(3), シフト量(log2(N)), 値(N) の順, 出力は シフトした値(N), キャリー
+// シフト量のbitごとに 1, 2, 4, ... bitずらす段を重ねるので, 段の数はlog2(N)
+// キャリーは最後に押し出されたbitで, シフト量が0なら0, 回したときは回り込んだbit
+pub struct BarrelShifter<const N: usize> where
+    [(); 3 + log2(N) + N]: Sized,
+    [(); log2(N) + N + 4]: Sized,
+    [(); N + 1]: Sized,
+{
+    shifter: MergeLayers<{3 + log2(N) + N}, {log2(N) + N + 4}, {N + 1}>,
+}
+
+impl<const N: usize> Component<{3 + log2(N) + N}, {N + 1}> for BarrelShifter<N> where
+    [(); 3 + log2(N) + N]: Sized,
+    [(); log2(N) + N + 4]: Sized,
+    [(); N + 1]: Sized,
+{
+    fn eval(&self, input: [bool; 3 + log2(N) + N]) -> [bool; N + 1] {
+        self.shifter.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.shifter.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.shifter.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.shifter.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.shifter.netlist(netlist, input)
+    }
+}
+
+impl<const N: usize> BarrelShifter<N> where
+    [(); 3 + log2(N) + N]: Sized,
+    [(); 3 + log2(N) + N + 0]: Sized,
+    [(); 3 + log2(N) + N + 1]: Sized,
+    [(); log2(N) + N + 1]: Sized,
+    [(); 3 + (log2(N) + N + 1)]: Sized,
+    [(); 4 + (log2(N) + N + 1)]: Sized,
+    [(); log2(N) + 3]: Sized,
+    [(); 1 + N]: Sized,
+    [(); (1 + N) + 1]: Sized,
+    [(); (log2(N) + 3) + ((1 + N) + 1)]: Sized,
+    [(); 3 * N]: Sized,
+    [(); 1 * N]: Sized,
+    [(); 1 * N + 1]: Sized,
+    [(); (log2(N) + 3) + (1 * N + 1)]: Sized,
+    [(); log2(N) + N + 4]: Sized,
+    [(); 5 * (N + 1)]: Sized,
+    [(); 1 * (N + 1)]: Sized,
+    [(); (log2(N) + 3) + 5 * (N + 1)]: Sized,
+    [(); (log2(N) + 3) + 1 * (N + 1)]: Sized,
+    [(); N + 1]: Sized,
+{
+    pub fn new() -> Self {
+        let stages = (0..log2(N)).fold(
+            Box::new(Wiring::<{log2(N) + N + 4}, {log2(N) + N + 4}>::buffer())
+                as Box<dyn Component<{log2(N) + N + 4}, {log2(N) + N + 4}>>,
+            |stages, k| Box::new(MergeLayers::create(stages, Box::new(barrel_stage::<N>(k)))),
+        );
+        let shifter = MergeLayers::create(Box::new(prepare::<N>()), stages)
+            .connect_to(Box::new(finish::<N>()));
+        Self { shifter }
+    }
+}
+
+#[cfg(test)]
+fn barrel_input<const N: usize>(mode: BarrelMode, amount: usize, value: usize) -> [bool; 3 + log2(N) + N] {
+    use crate::num_bit_converter::*;
+
+    let mode = bit_to_num(mode.bits());
+    num_to_bit(mode + (amount << 3) + (value << (3 + log2(N))))
+}
+
+// シフトした値とキャリー
+#[cfg(test)]
+fn barrel_reference(n: usize, mode: BarrelMode, amount: usize, value: usize) -> (usize, bool) {
+    let mask = (1 << n) - 1;
+    let bit = |v: i64, i: usize| (v >> i) & 1 == 1;
+    let (shifted, carry) = match mode {
+        BarrelMode::ShiftLeft => ((value << amount) & mask, amount <= n && bit(value as i64, n - amount)),
+        BarrelMode::ShiftRight => (value >> amount, bit(value as i64, amount.wrapping_sub(1))),
+        BarrelMode::ArithmeticShiftRight => {
+            let signed = value as i64 - if bit(value as i64, n - 1) { 1 << n } else { 0 };
+            ((signed >> amount) as usize & mask, bit(signed, amount.wrapping_sub(1)))
+        }
+        BarrelMode::Rotate => {
+            let rotated = ((value << (amount % n)) | (value >> (n - amount % n))) & mask;
+            (rotated, rotated & 1 == 1)
+        }
+        // 回り込んだbitは最上位に来る
+        BarrelMode::RotateRight => {
+            let rotated = ((value >> (amount % n)) | (value << (n - amount % n))) & mask;
+            (rotated, bit(rotated as i64, n - 1))
+        }
+    };
+    (shifted, amount != 0 && carry)
+}
+
+// すべてのmode, シフト量, 値を試す
+#[cfg(test)]
+fn check_shifter<const N: usize>(shifter: &BarrelShifter<N>) where
+    [(); 3 + log2(N) + N]: Sized,
+    [(); log2(N) + N + 4]: Sized,
+    [(); N + 1]: Sized,
+{
+    use crate::num_bit_converter::*;
+    use BarrelMode::*;
+
+    for mode in [ShiftLeft, ShiftRight, ArithmeticShiftRight, Rotate, RotateRight] {
+        for amount in 0..pow2(log2(N)) {
+            for value in 0..pow2(N) {
+                let output = shifter.eval(barrel_input::<N>(mode, amount, value));
+                let actual = (bit_to_num(output) & (pow2(N) - 1), output[N]);
+                assert_eq!(actual, barrel_reference(N, mode, amount, value), "{:?} {} {}", mode, amount, value);
+            }
+        }
+    }
+}
+
+#[test]
+fn barrel_shifter_test() {
+    check_shifter(&BarrelShifter::<8>::new());
+    // 2の冪でなければシフト量はNを超えられる
+    check_shifter(&BarrelShifter::<5>::new());
+    check_shifter(&BarrelShifter::<1>::new());
+}
+
+#[test]
+fn barrel_shifter_depth_test() {
+    use crate::optimize::simplify;
+
+    // 8倍の幅でも段は3つ増えるだけ
+    let narrow = simplify(&BarrelShifter::<8>::new()).unwrap().0.netlist().metrics();
+    let wide = simplify(&BarrelShifter::<64>::new()).unwrap().0.netlist().metrics();
+    assert!(wide.depth < 2 * narrow.depth, "{:?} {:?}", narrow, wide);
+}
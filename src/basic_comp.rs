@@ -1,4 +1,5 @@
 use crate::core::*;
+use crate::netlist::{Gate, Net, Netlist};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Constant<const I: usize, const O: usize, const D: bool> {}
@@ -6,6 +7,13 @@ impl<const I: usize, const O: usize, const D: bool> Component<I, O> for Constant
     fn eval(&self, input: [bool; I]) -> [bool; O] {
         [D; O]
     }
+    fn netlist(&self, netlist: &mut Netlist, _input: &[Net]) -> Option<Vec<Net>> {
+        let constant = netlist.add(Gate::Const(D));
+        Some(vec![constant; O])
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
 }
 impl<const I: usize, const O: usize, const D: bool> Constant<I, O, D> {
     pub fn new() -> Self {
@@ -31,6 +39,12 @@ impl<const I: usize> Component<I, 1> for And<I> {
     fn eval(&self, input: [bool; I]) -> [bool; 1] {
         [input.into_iter().all(|b| b)]
     }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        Some(vec![netlist.add(Gate::And(input.to_vec()))])
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
 }
 impl<const I: usize> And<I> {
     pub fn new() -> Self {
@@ -55,6 +69,12 @@ impl<const I: usize> Component<I, 1> for Or<I> {
     fn eval(&self, input: [bool; I]) -> [bool; 1] {
         [input.into_iter().any(|b| b)]
     }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        Some(vec![netlist.add(Gate::Or(input.to_vec()))])
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
 }
 impl<const I: usize> Or<I> {
     pub fn new() -> Self {
@@ -79,6 +99,12 @@ impl Component<1, 1> for Not {
     fn eval(&self, input: [bool; 1]) -> [bool; 1] {
         [!input[0]]
     }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        Some(vec![netlist.add(Gate::Not(input[0]))])
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
 }
 impl Not {
     pub fn new() -> Self {
@@ -100,6 +126,15 @@ impl Component<1, 1> for Buffer {
     fn eval(&self, input: [bool; 1]) -> [bool; 1] {
         [input[0]]
     }
+    fn wiring_table(&self) -> Option<Vec<usize>> {
+        Some(vec![0])
+    }
+    fn netlist(&self, _netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        Some(vec![input[0]])
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
 }
 impl Buffer {
     pub fn new() -> Self {
@@ -121,6 +156,15 @@ impl<const O: usize> Component<1, O> for Branch<O> {
     fn eval(&self, input: [bool; 1]) -> [bool; O] {
         [input[0]; O]
     }
+    fn wiring_table(&self) -> Option<Vec<usize>> {
+        Some(vec![0; O])
+    }
+    fn netlist(&self, _netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        Some(vec![input[0]; O])
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
 }
 impl<const O: usize> Branch<O> {
     pub fn new() -> Self {
@@ -142,6 +186,21 @@ impl<const I: usize> Component<I, 1> for NAND<I> {
     fn eval(&self, input: [bool; I]) -> [bool; 1] {
         self.nand.eval(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.nand.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.nand.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.nand.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        Some(vec![netlist.add(Gate::Nand(input.to_vec()))])
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
 }
 impl<const I: usize> NAND<I> {
     pub fn new() -> Self {
@@ -168,6 +227,21 @@ impl<const I: usize> Component<I, 1> for XOR<I> {
     fn eval(&self, input: [bool; I]) -> [bool; 1] {
         self.xor.eval(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.xor.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.xor.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.xor.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.xor.netlist(netlist, input)
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
 }
 impl<const I: usize> XOR<I> 
 where
@@ -216,6 +290,21 @@ where
     fn eval(&self, input: [bool; N * P]) -> [bool; P] {
         self.adapter.eval(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.adapter.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.adapter.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.adapter.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.adapter.netlist(netlist, input)
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
 }
 
 impl <const P: usize, const N: usize> Adapter<P, N>
@@ -1,4 +1,7 @@
 use crate::core::*;
+use crate::basic_comp::*;
+use crate::memory::MemoryCell;
+use crate::flip_flop::{DFlipFlop, TFlipFlop};
 
 pub struct Clock {
     s: bool,
@@ -17,6 +20,12 @@ impl Component<0, 1> for Clock {
         self.s = !self.s;
         return [self.s];
     }
+    fn state(&self) -> Vec<bool> {
+        vec![self.s]
+    }
+    fn set_state(&mut self, state: &[bool]) {
+        self.s = state[0];
+    }
 }
 
 #[test]
@@ -51,6 +60,12 @@ impl Component<1, 1> for DetectClockWake {
         self.s = input[0];
         return [output];
     }
+    fn state(&self) -> Vec<bool> {
+        vec![self.s]
+    }
+    fn set_state(&mut self, state: &[bool]) {
+        self.s = state[0];
+    }
 }
 
 #[test]
@@ -64,3 +79,343 @@ fn detect_clock_wake_test() {
     assert_eq!([false], clock_wake.eval_mut([false]));
 
 }
+
+// クロックの立ち下がりの時Trueを返す
+pub struct DetectClockFall {
+    detector: MergeLayers<1, 1, 1>,
+}
+
+impl Component<1, 1> for DetectClockFall {
+    fn eval(&self, input: [bool; 1]) -> [bool; 1] {
+        self.detector.eval(input)
+    }
+    fn eval_mut(&mut self, input: [bool; 1]) -> [bool; 1] {
+        self.detector.eval_mut(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.detector.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.detector.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.detector.fuse_children()
+    }
+}
+
+impl DetectClockFall {
+    pub fn new() -> Self {
+        // 反転したクロックの立ち上がり
+        let detector = MergeLayers::create(
+            Box::new(Not::new()),
+            Box::new(DetectClockWake::new()),
+        );
+        Self { detector }
+    }
+}
+
+#[test]
+fn detect_clock_fall_test() {
+    let mut clock_fall = DetectClockFall::new();
+
+    assert_eq!([false], clock_fall.eval_mut([true]));
+    assert_eq!([true], clock_fall.eval_mut([false]));
+    assert_eq!([false], clock_fall.eval_mut([false]));
+    assert_eq!([false], clock_fall.eval_mut([true]));
+    assert_eq!([true], clock_fall.eval_mut([false]));
+}
+
+// クロックの立ち上がりと立ち下がりの両方でTrueを返す
+pub struct DetectClockEdge {
+    detector: MergeLayers<1, 2, 1>,
+}
+
+impl Component<1, 1> for DetectClockEdge {
+    fn eval(&self, input: [bool; 1]) -> [bool; 1] {
+        self.detector.eval(input)
+    }
+    fn eval_mut(&mut self, input: [bool; 1]) -> [bool; 1] {
+        self.detector.eval_mut(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.detector.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.detector.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.detector.fuse_children()
+    }
+}
+
+impl DetectClockEdge {
+    pub fn new() -> Self {
+        let layer1 = ConcatBlocks::create([
+            Box::new(DetectClockWake::new()) as Box<dyn Component<1, 1>>,
+            Box::new(DetectClockFall::new()),
+        ]);
+        let detector = MergeLayers::create(Box::new(Branch::<2>::new()), Box::new(layer1))
+            .connect_to(Box::new(Or::<2>::new()));
+        Self { detector }
+    }
+}
+
+#[test]
+fn detect_clock_edge_test() {
+    let mut clock = Clock::new();
+    let mut edge = DetectClockEdge::new();
+
+    // 最初の1は立ち上がりとみなさないので, その後は毎回変化を検出する
+    assert_eq!([false], edge.eval_mut(clock.eval_mut([])));
+    for _ in 0..10 {
+        assert_eq!([true], edge.eval_mut(clock.eval_mut([])));
+    }
+    assert_eq!([false], edge.eval_mut([true]));
+}
+
+// クロックを2, 4, ..., 2^N分周したものを出す
+// 各段はTフリップフロップで, 前の段の!Qの立ち上がり(Qの立ち下がり)で反転する
+pub struct ClockDivider<const N: usize> where
+    [(); 1 * N + 1]: Sized,
+{
+    divider: MergeLayers<1, {1 * N + 1}, N>,
+}
+
+impl<const N: usize> Component<1, N> for ClockDivider<N> where
+    [(); 1 * N + 1]: Sized,
+{
+    fn eval(&self, input: [bool; 1]) -> [bool; N] {
+        self.divider.eval(input)
+    }
+    fn eval_mut(&mut self, input: [bool; 1]) -> [bool; N] {
+        self.divider.eval_mut(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.divider.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.divider.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.divider.fuse_children()
+    }
+}
+
+impl<const N: usize> ClockDivider<N> where
+    [(); 1 * N + 1]: Sized,
+    [(); 1 + 3 * N]: Sized,
+{
+    pub fn new() -> Self {
+        // clock, 1, 0
+        let layer1 = ConcatDifferentShapeBlocks::<1, 0, 1, 2>::create(
+            Box::new(Buffer::new()),
+            Box::new(ConcatDifferentShapeBlocks::<0, 0, 1, 1>::create(
+                Box::new(Constant::<0, 1, true>::new()),
+                Box::new(Constant::<0, 1, false>::new()),
+            )),
+        );
+        // clock, 各段の t, set, reset
+        let mut table = [0; 1 + 3 * N];
+        for (i, v) in table.iter_mut().enumerate().skip(1) {
+            *v = if i % 3 == 1 { 1 } else { 2 };
+        }
+        let layer2 = Wiring::<3, {1 + 3 * N}>::create(table);
+        let layer3 = RecurrentBlock::<1, 3, 1, N>::create_from_fn(TFlipFlop::new).named("stages");
+        let layer4 = Wiring::<{1 * N + 1}, N>::cut();
+        let divider = MergeLayers::create(Box::new(layer1), Box::new(layer2))
+            .connect_to(Box::new(layer3))
+            .connect_to(Box::new(layer4));
+        Self { divider }
+    }
+}
+
+#[test]
+fn clock_divider_test() {
+    let mut clock = Clock::new();
+    let mut divider = ClockDivider::<3>::new();
+
+    // 最初の立ち上がりは数えないので, 2回目の立ち上がりから数える
+    let mut rises = [0; 3];
+    let mut prev = [false; 3];
+    for tick in 0..64 {
+        let c = clock.eval_mut([])[0];
+        let output = divider.eval_mut([c]);
+        let expected = if tick < 2 { 0 } else { (tick - 2) / 2 + 1 };
+        for (i, &o) in output.iter().enumerate() {
+            // 2^(i + 1)回の立ち上がりで1周する
+            assert_eq!(o, expected % (1 << (i + 1)) >= (1 << i), "tick {} stage {}", tick, i);
+            if o && !prev[i] {
+                rises[i] += 1;
+            }
+        }
+        prev = output;
+    }
+    assert_eq!(rises, [16, 8, 4]);
+    assert!(crate::hierarchy::find(&divider, "stages[2].master").is_some());
+}
+
+// P相の重ならないクロック, 入力のクロックが1の間に順に1相ずつ1になる
+// 立ち下がりで次の相に進むので, 相の間には必ずクロックが0の期間がはさまる
+pub struct PhaseClock<const P: usize> where
+    [(); 5 * P]: Sized,
+    [(); 2 * P]: Sized,
+{
+    // 1になっている相だけ1のリング
+    ring: Box<dyn Component<{5 * P}, {2 * P}>>,
+}
+
+pub type TwoPhaseClock = PhaseClock<2>;
+pub type FourPhaseClock = PhaseClock<4>;
+
+impl<const P: usize> Component<1, P> for PhaseClock<P> where
+    [(); 5 * P]: Sized,
+    [(); 2 * P]: Sized,
+{
+    fn eval(&self, input: [bool; 1]) -> [bool; P] {
+        let ring = self.ring.eval(self.ring_input(input[0]));
+        Self::output(input[0], &ring)
+    }
+    fn eval_mut(&mut self, input: [bool; 1]) -> [bool; P] {
+        let ring_input = self.ring_input(input[0]);
+        let ring = self.ring.eval_mut(ring_input);
+        Self::output(input[0], &ring)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        vec![(None, &self.ring as &dyn Node)]
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        vec![(None, &mut self.ring as &mut dyn Node)]
+    }
+}
+
+impl<const P: usize> PhaseClock<P> where
+    [(); 5 * P]: Sized,
+    [(); 2 * P]: Sized,
+{
+    pub fn new() -> Self {
+        let ring = ConcatBlocks::<5, 2, P>::create_from_fn(DFlipFlop::new).named("ring");
+        let mut clock = Self { ring: Box::new(ring) };
+        // 0相目から始める
+        let mut set_first = [false; 5 * P];
+        set_first[3] = true;
+        clock.ring.eval_mut(set_first);
+        clock
+    }
+    // 各相のフリップフロップへの clock, d, enable, set, reset, 反転したクロックで1つずつ回す
+    fn ring_input(&self, clock: bool) -> [bool; 5 * P] {
+        let current = self.ring.eval([false; 5 * P]);
+        let mut ring_input = [false; 5 * P];
+        for (phase, chunk) in ring_input.chunks_mut(5).enumerate() {
+            chunk[0] = !clock;
+            chunk[1] = current[2 * ((phase + P - 1) % P)];
+            chunk[2] = true;
+        }
+        ring_input
+    }
+    fn output(clock: bool, ring: &[bool]) -> [bool; P] {
+        let mut output = [false; P];
+        for (phase, v) in output.iter_mut().enumerate() {
+            *v = clock && ring[2 * phase];
+        }
+        output
+    }
+}
+
+#[test]
+fn phase_clock_test() {
+    let mut clock = Clock::new();
+    let mut four = FourPhaseClock::new();
+    let mut two = TwoPhaseClock::new();
+    for tick in 0..40 {
+        let c = clock.eval_mut([])[0];
+        let output = four.eval_mut([c]);
+        // 2tickごとに次の相, 間の1tickはどれも0
+        let mut expected = [false; 4];
+        if tick % 2 == 0 {
+            expected[(tick / 2) % 4] = true;
+        }
+        assert_eq!(output, expected, "tick {}", tick);
+
+        let output = two.eval_mut([c]);
+        assert_eq!(output, [tick % 4 == 0, tick % 4 == 2], "tick {}", tick);
+    }
+}
+
+// enableが0の間はクロックを止める
+// enableはクロックが0の間にラッチに取り込むので, クロックが1の途中で変えても出力が欠けたりしない
+pub struct ClockGate {
+    gate: MergeLayers<2, 2, 1>,
+}
+
+impl Component<2, 1> for ClockGate {
+    fn eval(&self, input: [bool; 2]) -> [bool; 1] {
+        self.gate.eval(input)
+    }
+    fn eval_mut(&mut self, input: [bool; 2]) -> [bool; 1] {
+        self.gate.eval_mut(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.gate.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.gate.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.gate.fuse_children()
+    }
+}
+
+impl ClockGate {
+    pub fn new() -> Self {
+        // 1, clock, enable
+        let layer1 = ConcatDifferentShapeBlocks::<0, 2, 1, 2>::create(
+            Box::new(Constant::<0, 1, true>::new()),
+            Box::new(Wiring::<2, 2>::buffer()),
+        );
+        // clock, ラッチの read, write, value
+        let layer2 = Wiring::<3, 4>::create([1, 0, 1, 2]);
+        let layer3 = ConcatBlocks::create([
+            Box::new(Buffer::new()) as Box<dyn Component<1, 1>>,
+            Box::new(Buffer::new()),
+            Box::new(Not::new()),
+            Box::new(Buffer::new()),
+        ]);
+        let layer4 = ConcatDifferentShapeBlocks::<1, 3, 1, 1>::create(
+            Box::new(Buffer::new()),
+            Box::new(Named::create("latch", Box::new(MemoryCell::new()))),
+        );
+        let gate = MergeLayers::create(Box::new(layer1), Box::new(layer2))
+            .connect_to(Box::new(layer3))
+            .connect_to(Box::new(layer4))
+            .connect_to(Box::new(And::<2>::new()));
+        Self { gate }
+    }
+}
+
+#[test]
+fn clock_gate_test() {
+    let mut gate = ClockGate::new();
+
+    assert_eq!([false], gate.eval_mut([false, false]));
+    assert_eq!([false], gate.eval_mut([true, false]));
+    // クロックが1の間にenableを変えても効かない
+    assert_eq!([false], gate.eval_mut([true, true]));
+    assert_eq!([false], gate.eval_mut([false, true]));
+    assert_eq!([true], gate.eval_mut([true, true]));
+    assert_eq!([true], gate.eval_mut([true, false]));
+    assert_eq!([false], gate.eval_mut([false, false]));
+    assert_eq!([false], gate.eval_mut([true, false]));
+
+    // 分周器の前に入れて止めている間は進まない
+    let mut clock = Clock::new();
+    let mut gate = ClockGate::new();
+    let mut divider = ClockDivider::<2>::new();
+    let mut outputs = Vec::new();
+    for tick in 0..24 {
+        let c = clock.eval_mut([])[0];
+        let gated = gate.eval_mut([c, !(8..16).contains(&tick)]);
+        outputs.push(divider.eval_mut(gated)[0]);
+    }
+    assert_eq!(outputs[8..16], [outputs[8]; 8]);
+    assert!(crate::hierarchy::find(&gate, "latch").is_some());
+}
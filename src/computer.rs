@@ -1,17 +1,21 @@
 use crate::core::*;
+use crate::netlist::{Net, Netlist};
 use crate::basic_comp::*;
 use crate::decoder::BitDecoder;
 use crate::memory::*;
+use crate::register_file::RegisterFile;
+use crate::counter::Counter;
 use crate::clock::*;
 use crate::arithmetic_comp::*;
 
 const MEMORY_ADDR_SIZE: usize = 16;
 
-type MainMemory = Memory<MEMORY_ADDR_SIZE, 8>;
+// 全部をゲートで作ると重いので書き込まれたバイトだけ作る
+type MainMemory = SparseMemory<MEMORY_ADDR_SIZE, 8>;
 
-type ProgramCounter = MemoryByte<MEMORY_ADDR_SIZE>;
-type AddressRegistar = [MemoryByte<MEMORY_ADDR_SIZE>; 8];
-type GeneralRegistar = [MemoryByte<8>; 8];
+type ProgramCounter = Counter<MEMORY_ADDR_SIZE>;
+type AddressRegistar = RegisterFile<3, MEMORY_ADDR_SIZE>;
+type GeneralRegistar = RegisterFile<3, 8>;
 type FlagRegistar = MemoryCell;
 
 type InstDecoder = BitDecoder<8>;
@@ -89,6 +93,12 @@ impl Component<3, 1> for DummyCell {
         }
         [self.s && input[0]]
     }
+    fn state(&self) -> Vec<bool> {
+        vec![self.s]
+    }
+    fn set_state(&mut self, state: &[bool]) {
+        self.s = state[0];
+    }
 }
 impl DummyCell {
     fn new() -> Self {
@@ -113,6 +123,18 @@ where
     fn eval_mut(&mut self, input: [bool; 2]) -> [bool; N] {
         self.counter.eval_mut(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.counter.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.counter.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.counter.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.counter.netlist(netlist, input)
+    }
 }
 
 impl<const N: usize> MicroProgramCounter<N>
@@ -210,3 +232,26 @@ fn micro_program_counter_test() {
         assert_eq!(counter.eval_mut([false, true]), expect);
     }
 }
+
+#[test]
+fn micro_program_counter_step_test() {
+    use crate::encoder::PriorityEncoder;
+    use crate::num_bit_converter::*;
+
+    // 1本だけ立っている線を段の番号にする, 最後の段を過ぎると全部0になって番号は無効
+    let mut counter = MicroProgramCounter::<4>::new();
+    let encoder = PriorityEncoder::<2>::new();
+    let step = |output: [bool; 4]| bit_to_num(encoder.eval(output));
+    for input in [[false, false], [true, false], [false, true]] {
+        assert_eq!(step(counter.eval_mut(input)), 0);
+    }
+    assert_eq!(step(counter.eval_mut([true, true])), 4);
+    assert_eq!(step(counter.eval_mut([false, true])), 4);
+    for i in 1..6 {
+        let expected = if i < 4 { i + 4 } else { 0 };
+        assert_eq!(step(counter.eval_mut([true, false])), expected);
+        for input in [[true, true], [false, false], [false, true]] {
+            assert_eq!(step(counter.eval_mut(input)), expected);
+        }
+    }
+}
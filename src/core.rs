@@ -1,9 +1,212 @@
+use std::cell::{Cell, OnceCell, RefCell};
+use std::rc::Rc;
+use crate::hierarchy::{attach_all_state, collect_state, restore_state};
+use crate::netlist::{FlatCircuit, Net, Netlist};
+
 pub trait Component<const I: usize, const O: usize> {
     fn eval(&self, input: [bool; I]) -> [bool; O];
     // メモリなどで内部状態を変更しながら評価する
     fn eval_mut(&mut self, input: [bool; I]) -> [bool; O] {
         self.eval(input)
     }
+
+    // 以下は階層をたどるためのもの
+    // インスタンス名, 名前のない部品はパスに現れない
+    fn name(&self) -> Option<&str> {
+        None
+    }
+    // 子の部品, ConcatBlocksのように同じ形を並べるものは添字をつける
+    fn children(&self) -> Vec<Child<'_>> {
+        Vec::new()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        Vec::new()
+    }
+    // フリップフロップなどが持っている内部状態
+    fn state(&self) -> Vec<bool> {
+        Vec::new()
+    }
+    fn set_state(&mut self, _state: &[bool]) {}
+    // 子を除いた自身が内部状態を持たないことの宣言
+    // stateが空でも状態を隠している部品があるので, Flyweightは宣言のない部品の回路を共有しない
+    fn stateless(&self) -> bool {
+        false
+    }
+    // 内部状態(stateと同じ並び)をstatesの後ろへ移し, 以後はそこを読み書きする. 移せる部品はtrue
+    // Flyweightが回路を一つにして, 状態だけをインスタンスごとに入れ替えるのに使う
+    fn attach_state(&mut self, _states: &SharedState) -> bool {
+        false
+    }
+    // Namedの出力線の値
+    fn probe(&self) -> Option<Vec<bool>> {
+        None
+    }
+    // 出力線を固定する, Noneで解除. 固定できないものはfalse
+    fn force(&mut self, _value: Option<&[bool]>) -> bool {
+        false
+    }
+
+    // 全体がただの配線ならその表
+    fn wiring_table(&self) -> Option<Vec<usize>> {
+        None
+    }
+    // 子がすべて配線なら一つの表に, MergeLayersなら隣り合う配線をまとめる
+    // 子の中はoptimize::fuse_wiringsがたどる
+    fn fuse_children(&mut self) {}
+    // まとめた後の前後の端にある配線, 入れ子になったMergeLayersで隣の配線とまとめるのに使う
+    fn leading_wiring(&self) -> Option<Vec<usize>> {
+        None
+    }
+    fn trailing_wiring(&self) -> Option<Vec<usize>> {
+        None
+    }
+    // 前後の端の配線を除いた部分の評価, leading_wiringかtrailing_wiringがあるときだけ呼ばれる
+    fn eval_core(&self, _input: &[bool]) -> Vec<bool> {
+        unreachable!("no wiring at either end")
+    }
+    fn eval_core_mut(&mut self, input: &[bool]) -> Vec<bool> {
+        self.eval_core(input)
+    }
+    fn netlist_core(&self, _netlist: &mut Netlist, _input: &[Net]) -> Option<Vec<Net>> {
+        None
+    }
+    // 評価のたびに通る配線の層の数
+    fn wiring_layers(&self) -> usize {
+        match self.wiring_table() {
+            Some(_) => 1,
+            None => self.children().iter().map(|(_, child)| child.wiring_layers()).sum(),
+        }
+    }
+    // inputの線をつないだゲートをnetlistに追加して出力の線を返す
+    // ゲートに展開できないもの(内部状態を持つものなど)はNone
+    fn netlist(&self, _netlist: &mut Netlist, _input: &[Net]) -> Option<Vec<Net>> {
+        None
+    }
+}
+
+// 入出力の幅が違う部品をまとめて扱うための型消去した見方
+pub trait Node {
+    fn inputs(&self) -> usize;
+    fn outputs(&self) -> usize;
+    // 幅が合わないときはpanic
+    fn eval_dyn(&self, input: &[bool]) -> Vec<bool>;
+    fn eval_mut_dyn(&mut self, input: &[bool]) -> Vec<bool>;
+    fn name(&self) -> Option<&str>;
+    fn children(&self) -> Vec<Child<'_>>;
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>>;
+    fn state(&self) -> Vec<bool>;
+    fn set_state(&mut self, state: &[bool]);
+    fn stateless(&self) -> bool;
+    fn attach_state(&mut self, states: &SharedState) -> bool;
+    fn probe(&self) -> Option<Vec<bool>>;
+    fn force(&mut self, value: Option<&[bool]>) -> bool;
+    fn wiring_table(&self) -> Option<Vec<usize>>;
+    fn fuse_children(&mut self);
+    fn leading_wiring(&self) -> Option<Vec<usize>>;
+    fn trailing_wiring(&self) -> Option<Vec<usize>>;
+    fn eval_core(&self, input: &[bool]) -> Vec<bool>;
+    fn eval_core_mut(&mut self, input: &[bool]) -> Vec<bool>;
+    fn netlist_core(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>>;
+    fn wiring_layers(&self) -> usize;
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>>;
+    // 同じ幅のゲートの回路に置き換える, 幅が合わなければfalse
+    fn replace_with(&mut self, netlist: Netlist) -> bool;
+}
+
+pub type Child<'a> = (Option<usize>, &'a dyn Node);
+pub type ChildMut<'a> = (Option<usize>, &'a mut dyn Node);
+
+// Flyweightで共有した回路の内部状態の置き場, 評価するインスタンスの状態をここへ入れる
+pub type SharedState = Rc<RefCell<Vec<bool>>>;
+
+impl<const I: usize, const O: usize> Node for Box<dyn Component<I, O>> {
+    fn inputs(&self) -> usize {
+        I
+    }
+    fn outputs(&self) -> usize {
+        O
+    }
+    fn eval_dyn(&self, input: &[bool]) -> Vec<bool> {
+        let mut fixed = [false; I];
+        fixed.copy_from_slice(input);
+        self.eval(fixed).to_vec()
+    }
+    fn eval_mut_dyn(&mut self, input: &[bool]) -> Vec<bool> {
+        let mut fixed = [false; I];
+        fixed.copy_from_slice(input);
+        self.eval_mut(fixed).to_vec()
+    }
+    fn name(&self) -> Option<&str> {
+        Component::name(&**self)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        Component::children(&**self)
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        Component::children_mut(&mut **self)
+    }
+    fn state(&self) -> Vec<bool> {
+        Component::state(&**self)
+    }
+    fn set_state(&mut self, state: &[bool]) {
+        Component::set_state(&mut **self, state)
+    }
+    fn stateless(&self) -> bool {
+        Component::stateless(&**self)
+    }
+    fn attach_state(&mut self, states: &SharedState) -> bool {
+        Component::attach_state(&mut **self, states)
+    }
+    fn probe(&self) -> Option<Vec<bool>> {
+        Component::probe(&**self)
+    }
+    fn force(&mut self, value: Option<&[bool]>) -> bool {
+        Component::force(&mut **self, value)
+    }
+    fn wiring_table(&self) -> Option<Vec<usize>> {
+        Component::wiring_table(&**self)
+    }
+    fn fuse_children(&mut self) {
+        Component::fuse_children(&mut **self)
+    }
+    fn leading_wiring(&self) -> Option<Vec<usize>> {
+        Component::leading_wiring(&**self)
+    }
+    fn trailing_wiring(&self) -> Option<Vec<usize>> {
+        Component::trailing_wiring(&**self)
+    }
+    fn eval_core(&self, input: &[bool]) -> Vec<bool> {
+        Component::eval_core(&**self, input)
+    }
+    fn eval_core_mut(&mut self, input: &[bool]) -> Vec<bool> {
+        Component::eval_core_mut(&mut **self, input)
+    }
+    fn netlist_core(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        Component::netlist_core(&**self, netlist, input)
+    }
+    fn wiring_layers(&self) -> usize {
+        Component::wiring_layers(&**self)
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        Component::netlist(&**self, netlist, input)
+    }
+    fn replace_with(&mut self, netlist: Netlist) -> bool {
+        match FlatCircuit::<I, O>::create(netlist) {
+            Some(flat) => {
+                *self = Box::new(flat);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn apply_table<const I: usize, const O: usize>(table: &[usize], input: [bool; I]) -> [bool; O] {
+    let mut output = [false; O];
+    for (v, &i) in output.iter_mut().zip(table) {
+        *v = input[i];
+    }
+    output
 }
 
 pub struct DebugLayer<const N: usize> {}
@@ -12,6 +215,9 @@ impl<const N: usize> Component<N, N> for DebugLayer<N> {
         println!("{:?}", input);
         input
     }
+    fn netlist(&self, _netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        Some(input.to_vec())
+    }
 }
 impl<const N: usize> DebugLayer<N> {
     pub fn new() -> Self {
@@ -19,21 +225,218 @@ impl<const N: usize> DebugLayer<N> {
     }
 }
 
+// MergeLayersを平らにしたときの一段
+enum Step {
+    Table(Vec<usize>),
+    First,
+    Second,
+    // 子のMergeLayersから前後の端の配線を除いたもの
+    FirstCore,
+    SecondCore,
+}
+
+// 子が配線ならその表, 端に配線のある子なら (配線, 中身, 配線) に分ける
+fn layer_steps(layer: &dyn Node, whole: Step, core: Step) -> Vec<Step> {
+    if let Some(table) = layer.wiring_table() {
+        return vec![Step::Table(table)];
+    }
+    let (leading, trailing) = (layer.leading_wiring(), layer.trailing_wiring());
+    if leading.is_none() && trailing.is_none() {
+        return vec![whole];
+    }
+    leading.map(Step::Table)
+        .into_iter()
+        .chain([core])
+        .chain(trailing.map(Step::Table))
+        .collect()
+}
+
+// 前後の端の配線を除いた段
+fn core_steps(steps: &[Step]) -> &[Step] {
+    let start = matches!(steps.first(), Some(Step::Table(_))) as usize;
+    let end = steps.len() - matches!(steps.last(), Some(Step::Table(_))) as usize;
+    &steps[start..end]
+}
+
+fn core_layers(layer: &dyn Node) -> usize {
+    layer.wiring_layers()
+        - layer.leading_wiring().is_some() as usize
+        - layer.trailing_wiring().is_some() as usize
+}
+
+fn run_steps(
+    steps: &[Step],
+    layer1: &dyn Node,
+    layer2: &dyn Node,
+    input: Vec<bool>,
+) -> Vec<bool> {
+    steps.iter().fold(input, |x, step| match step {
+        Step::Table(table) => table.iter().map(|&i| x[i]).collect(),
+        Step::First => layer1.eval_dyn(&x),
+        Step::Second => layer2.eval_dyn(&x),
+        Step::FirstCore => layer1.eval_core(&x),
+        Step::SecondCore => layer2.eval_core(&x),
+    })
+}
+
+fn run_steps_mut(
+    steps: &[Step],
+    layer1: &mut dyn Node,
+    layer2: &mut dyn Node,
+    input: Vec<bool>,
+) -> Vec<bool> {
+    steps.iter().fold(input, |x, step| match step {
+        Step::Table(table) => table.iter().map(|&i| x[i]).collect(),
+        Step::First => layer1.eval_mut_dyn(&x),
+        Step::Second => layer2.eval_mut_dyn(&x),
+        Step::FirstCore => layer1.eval_core_mut(&x),
+        Step::SecondCore => layer2.eval_core_mut(&x),
+    })
+}
+
+fn netlist_steps(
+    steps: &[Step],
+    layer1: &dyn Node,
+    layer2: &dyn Node,
+    netlist: &mut Netlist,
+    input: &[Net],
+) -> Option<Vec<Net>> {
+    let mut x = input.to_vec();
+    for step in steps {
+        x = match step {
+            Step::Table(table) => table.iter().map(|&i| x[i]).collect(),
+            Step::First => layer1.netlist(netlist, &x)?,
+            Step::Second => layer2.netlist(netlist, &x)?,
+            Step::FirstCore => layer1.netlist_core(netlist, &x)?,
+            Step::SecondCore => layer2.netlist_core(netlist, &x)?,
+        };
+    }
+    Some(x)
+}
+
 pub struct MergeLayers<const I: usize, const M: usize, const O: usize> {
     layer1: Box<dyn Component<I, M>>,
     layer2: Box<dyn Component<M, O>>,
+    name: Option<String>,
+    // 両方の層が配線だったときにまとめた表
+    fused: Option<Vec<usize>>,
+    // 入れ子になったMergeLayersをまたいで隣り合う配線をまとめた段
+    // connect_toでつないだ鎖は左に入れ子になるので, 子の端の配線と自分の層の配線をここでまとめる
+    plan: Option<Vec<Step>>,
 }
 impl<const I: usize, const M: usize, const O: usize> Component<I, O> for MergeLayers<I, M, O> {
     fn eval(&self, input: [bool; I]) -> [bool; O] {
+        if let Some(table) = &self.fused {
+            return apply_table(table, input);
+        }
+        if let Some(steps) = &self.plan {
+            let mut output = [false; O];
+            output.copy_from_slice(&run_steps(steps, &self.layer1, &self.layer2, input.to_vec()));
+            return output;
+        }
         self.layer2.eval(self.layer1.eval(input))
     }
     fn eval_mut(&mut self, input: [bool; I]) -> [bool; O] {
+        if let Some(table) = &self.fused {
+            return apply_table(table, input);
+        }
+        if let Some(steps) = &self.plan {
+            let mut output = [false; O];
+            output.copy_from_slice(&run_steps_mut(steps, &mut self.layer1, &mut self.layer2, input.to_vec()));
+            return output;
+        }
         self.layer2.eval_mut(self.layer1.eval_mut(input))
     }
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        vec![(None, &self.layer1), (None, &self.layer2)]
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        vec![(None, &mut self.layer1), (None, &mut self.layer2)]
+    }
+    fn wiring_table(&self) -> Option<Vec<usize>> {
+        self.fused.clone()
+    }
+    // 両方の層を段に分けて並べ, 隣り合う配線を一つの表にする
+    fn fuse_children(&mut self) {
+        let mut steps = layer_steps(&self.layer1, Step::First, Step::FirstCore);
+        for step in layer_steps(&self.layer2, Step::Second, Step::SecondCore) {
+            if let (Some(Step::Table(first)), Step::Table(second)) = (steps.last_mut(), &step) {
+                *first = second.iter().map(|&i| first[i]).collect();
+                continue;
+            }
+            steps.push(step);
+        }
+        match steps.as_slice() {
+            [Step::Table(table)] => self.fused = Some(table.clone()),
+            [Step::First, Step::Second] => {}
+            _ => self.plan = Some(steps),
+        }
+    }
+    fn leading_wiring(&self) -> Option<Vec<usize>> {
+        match self.plan.as_deref()?.first() {
+            Some(Step::Table(table)) => Some(table.clone()),
+            _ => None,
+        }
+    }
+    fn trailing_wiring(&self) -> Option<Vec<usize>> {
+        match self.plan.as_deref()?.last() {
+            Some(Step::Table(table)) => Some(table.clone()),
+            _ => None,
+        }
+    }
+    fn eval_core(&self, input: &[bool]) -> Vec<bool> {
+        let steps = core_steps(self.plan.as_deref().unwrap_or(&[]));
+        run_steps(steps, &self.layer1, &self.layer2, input.to_vec())
+    }
+    fn eval_core_mut(&mut self, input: &[bool]) -> Vec<bool> {
+        let steps = core_steps(self.plan.as_deref().unwrap_or(&[]));
+        run_steps_mut(steps, &mut self.layer1, &mut self.layer2, input.to_vec())
+    }
+    fn netlist_core(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        let steps = core_steps(self.plan.as_deref().unwrap_or(&[]));
+        netlist_steps(steps, &self.layer1, &self.layer2, netlist, input)
+    }
+    fn wiring_layers(&self) -> usize {
+        if self.fused.is_some() {
+            return 1;
+        }
+        match &self.plan {
+            Some(steps) => steps.iter()
+                .map(|step| match step {
+                    Step::Table(_) => 1,
+                    Step::First => self.layer1.wiring_layers(),
+                    Step::Second => self.layer2.wiring_layers(),
+                    Step::FirstCore => core_layers(&self.layer1),
+                    Step::SecondCore => core_layers(&self.layer2),
+                })
+                .sum(),
+            None => self.layer1.wiring_layers() + self.layer2.wiring_layers(),
+        }
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        if let Some(table) = &self.fused {
+            return Some(table.iter().map(|&i| input[i]).collect());
+        }
+        if let Some(steps) = &self.plan {
+            return netlist_steps(steps, &self.layer1, &self.layer2, netlist, input);
+        }
+        let middle = self.layer1.netlist(netlist, input)?;
+        self.layer2.netlist(netlist, &middle)
+    }
 }
 impl<const I: usize, const M: usize, const O: usize> MergeLayers<I, M, O> {
     pub fn create(layer1: Box<dyn Component<I, M>>, layer2: Box<dyn Component<M, O>>) -> Self {
-        Self { layer1, layer2 }
+        Self { layer1, layer2, name: None, fused: None, plan: None }
+    }
+    pub fn named(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
     }
     pub fn debug(layer1: Box<dyn Component<I, M>>, layer2: Box<dyn Component<M, O>>) -> Self {
         let debug_layer = Box::new(DebugLayer::<M>::new());
@@ -54,10 +457,28 @@ impl<const I: usize, const M: usize, const O: usize> MergeLayers<I, M, O> {
         self.connect_to(debug_layer)
             .connect_to(next_layer)
     }
+    // 最後の層が配線ならつなぐ配線と合成して層を増やさない
+    pub fn connect_wiring<const P: usize>(self, next: Wiring<O, P>) -> MergeLayers<I, M, P> {
+        let layer2: Box<dyn Component<M, P>> = match self.layer2.wiring_table() {
+            Some(table) => {
+                let mut composed = [0; P];
+                composed.iter_mut()
+                    .zip(next.table)
+                    .for_each(|(v, i)| *v = table[i]);
+                Box::new(Wiring::<M, P>::create(composed))
+            }
+            None => Box::new(MergeLayers::create(self.layer2, Box::new(next))),
+        };
+        let mut merged = MergeLayers::create(self.layer1, layer2);
+        merged.name = self.name;
+        merged
+    }
 }
 
 pub struct ConcatBlocks<const I: usize, const O: usize, const N: usize> {
     blocks: [Box<dyn Component<I, O>>; N],
+    name: Option<String>,
+    fused: Option<Vec<usize>>,
 }
 
 impl<const I: usize, const O: usize, const N: usize> Component<{ I * N }, { O * N }>
@@ -67,6 +488,9 @@ where
     [(); O * N]: Sized,
 {
     fn eval_mut(&mut self, input: [bool; I * N]) -> [bool; O * N] {
+        if let Some(table) = &self.fused {
+            return apply_table(table, input);
+        }
         let inputs = Self::split_input(input);
         let mut outputs = [[false; O]; N];
         for ((result, block), val) in outputs.iter_mut().zip(self.blocks.iter_mut()).zip(inputs) {
@@ -78,6 +502,9 @@ where
         Self::merge_output(outputs)
     }
     fn eval(&self, input: [bool; I * N]) -> [bool; O * N] {
+        if let Some(table) = &self.fused {
+            return apply_table(table, input);
+        }
         let inputs = Self::split_input(input);
         let mut outputs = [[false; O]; N];
         for ((result, block), val) in outputs.iter_mut().zip(self.blocks.iter()).zip(inputs) {
@@ -88,17 +515,77 @@ where
         }
         Self::merge_output(outputs)
     }
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.blocks.iter()
+            .enumerate()
+            .map(|(i, b)| (Some(i), b as &dyn Node))
+            .collect()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.blocks.iter_mut()
+            .enumerate()
+            .map(|(i, b)| (Some(i), b as &mut dyn Node))
+            .collect()
+    }
+    fn wiring_table(&self) -> Option<Vec<usize>> {
+        self.fused.clone()
+    }
+    fn fuse_children(&mut self) {
+        let tables = self.blocks.iter()
+            .map(|b| b.wiring_table())
+            .collect::<Option<Vec<_>>>();
+        if let Some(tables) = tables {
+            self.fused = Some(
+                tables.iter()
+                    .enumerate()
+                    .flat_map(|(n, table)| table.iter().map(move |&i| n * I + i))
+                    .collect()
+            );
+        }
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        if let Some(table) = &self.fused {
+            return Some(table.iter().map(|&i| input[i]).collect());
+        }
+        let mut output = Vec::with_capacity(O * N);
+        for (block, block_input) in self.blocks.iter().zip(input.chunks(I)) {
+            output.extend(block.netlist(netlist, block_input)?);
+        }
+        Some(output)
+    }
 }
 impl<const I: usize, const O: usize, const N: usize> ConcatBlocks<I, O, N> {
     pub fn create(blocks: [Box<dyn Component<I, O>>; N]) -> Self {
-        Self { blocks }
+        Self { blocks, name: None, fused: None }
+    }
+    pub fn named(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
     }
     pub fn create_from_fn<T>(f: fn() -> T) -> Self
     where
         T: Component<I, O> + Sized + 'static
     {
         let blocks = [0; N].map(|_| Box::new(f()) as Box<dyn Component<I, O>>);
-        Self { blocks }
+        Self { blocks, name: None, fused: None }
+    }
+    // create_from_fnと同じだが, 回路は一つだけ作ってFlyweightで共有し, 内部状態だけをブロックごとに持つ
+    // 共有できない部品ならcreate_from_fnと同じ
+    pub fn create_shared<T>(f: fn() -> T) -> Self
+    where
+        T: Component<I, O> + Sized + 'static
+    {
+        let Some(first) = Flyweight::create(f) else {
+            return Self::create_from_fn(f);
+        };
+        let blocks = [0; N].map(|_| Box::new(first.share()) as Box<dyn Component<I, O>>);
+        Self { blocks, name: None, fused: None }
     }
     fn split_input(input: [bool; I * N]) -> [[bool; I]; N] {
         let mut inputs = [[false; I]; N];
@@ -124,29 +611,69 @@ pub struct ConcatDifferentShapeBlocks<
 > {
     block1: Box<dyn Component<I1, O1>>,
     block2: Box<dyn Component<I2, O2>>,
+    name: Option<String>,
+    fused: Option<Vec<usize>>,
 }
 
 impl<const I1: usize, const I2: usize, const O1: usize, const O2: usize>
     Component<{ I1 + I2 }, { O1 + O2 }> for ConcatDifferentShapeBlocks<I1, I2, O1, O2>
 {
     fn eval(&self, input: [bool; I1 + I2]) -> [bool; O1 + O2] {
+        if let Some(table) = &self.fused {
+            return apply_table(table, input);
+        }
         let (input1, input2) = Self::split_input(input);
         let output1 = self.block1.eval(input1);
         let output2 = self.block2.eval(input2);
         Self::merge_output(output1, output2)
     }
     fn eval_mut(&mut self, input: [bool; I1 + I2]) -> [bool; O1 + O2] {
+        if let Some(table) = &self.fused {
+            return apply_table(table, input);
+        }
         let (input1, input2) = Self::split_input(input);
         let output1 = self.block1.eval_mut(input1);
         let output2 = self.block2.eval_mut(input2);
         Self::merge_output(output1, output2)
     }
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        vec![(None, &self.block1), (None, &self.block2)]
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        vec![(None, &mut self.block1), (None, &mut self.block2)]
+    }
+    fn wiring_table(&self) -> Option<Vec<usize>> {
+        self.fused.clone()
+    }
+    fn fuse_children(&mut self) {
+        if let (Some(table1), Some(table2)) = (self.block1.wiring_table(), self.block2.wiring_table()) {
+            self.fused = Some(table1.into_iter().chain(table2.into_iter().map(|i| i + I1)).collect());
+        }
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        if let Some(table) = &self.fused {
+            return Some(table.iter().map(|&i| input[i]).collect());
+        }
+        let mut output = self.block1.netlist(netlist, &input[..I1])?;
+        output.extend(self.block2.netlist(netlist, &input[I1..])?);
+        Some(output)
+    }
 }
 impl<const I1: usize, const I2: usize, const O1: usize, const O2: usize>
     ConcatDifferentShapeBlocks<I1, I2, O1, O2>
 {
     pub fn create(block1: Box<dyn Component<I1, O1>>, block2: Box<dyn Component<I2, O2>>) -> Self {
-        Self { block1, block2 }
+        Self { block1, block2, name: None, fused: None }
+    }
+    pub fn named(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
     }
     fn split_input(input: [bool; I1 + I2]) -> ([bool; I1], [bool; I2]) {
         let mut input1 = [false; I1];
@@ -166,6 +693,293 @@ impl<const I1: usize, const I2: usize, const O1: usize, const O2: usize>
     }
 }
 
+// 部品にインスタンス名をつける
+// 出力線の値を覚えておくのでprobe, forceの対象になる
+pub struct Named<const I: usize, const O: usize> {
+    inner: Box<dyn Component<I, O>>,
+    name: String,
+    last_output: Cell<[bool; O]>,
+    forced: Option<[bool; O]>,
+}
+impl<const I: usize, const O: usize> Component<I, O> for Named<I, O> {
+    fn eval(&self, input: [bool; I]) -> [bool; O] {
+        let output = self.forced.unwrap_or(self.inner.eval(input));
+        self.last_output.set(output);
+        output
+    }
+    fn eval_mut(&mut self, input: [bool; I]) -> [bool; O] {
+        let output = self.inner.eval_mut(input);
+        let output = self.forced.unwrap_or(output);
+        self.last_output.set(output);
+        output
+    }
+    fn name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.inner.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.inner.children_mut()
+    }
+    fn state(&self) -> Vec<bool> {
+        self.inner.state()
+    }
+    fn set_state(&mut self, state: &[bool]) {
+        self.inner.set_state(state)
+    }
+    fn stateless(&self) -> bool {
+        self.inner.stateless()
+    }
+    fn attach_state(&mut self, states: &SharedState) -> bool {
+        self.inner.attach_state(states)
+    }
+    fn probe(&self) -> Option<Vec<bool>> {
+        Some(self.last_output.get().to_vec())
+    }
+    // 名前をつけた線は残したいので自身は配線として扱わない
+    fn fuse_children(&mut self) {
+        self.inner.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.inner.netlist(netlist, input)
+    }
+    fn force(&mut self, value: Option<&[bool]>) -> bool {
+        self.forced = match value {
+            Some(v) if v.len() == O => {
+                let mut forced = [false; O];
+                forced.copy_from_slice(v);
+                Some(forced)
+            }
+            Some(_) => return false,
+            None => None,
+        };
+        true
+    }
+}
+impl<const I: usize, const O: usize> Named<I, O> {
+    pub fn create(name: &str, inner: Box<dyn Component<I, O>>) -> Self {
+        Self {
+            inner,
+            name: name.to_string(),
+            last_output: Cell::new([false; O]),
+            forced: None,
+        }
+    }
+}
+
+// 同じ部品を並べるときに回路は一つだけ作り, 内部状態だけをインスタンスごとに持つ
+// 共有した回路のフリップフロップなどは状態をSharedStateに置くので, 評価の前後でこのインスタンスの状態を出し入れする
+// 子をたどったときはこのインスタンスだけの回路を作って状態を写し, 以後はそれで評価する
+// (パスでたどってprobeやforceがインスタンスごとに効くように)
+// 状態を持たない部品は共有したままで子はたどれない, パスはこの部品まで
+// 状態を持たないと宣言していない部品(attach_stateで状態を移せるものを除く)は共有しない
+pub struct Flyweight<const I: usize, const O: usize> {
+    shared: Rc<SharedCircuit<I, O>>,
+    // このインスタンスの内部状態, 共有した回路のSharedStateと同じ並び
+    state: Vec<bool>,
+    // 最後に評価した入力, 回路を作ったときに評価し直してNamedの出力線の値をそろえる
+    last_input: Cell<Option<[bool; I]>>,
+    own: OnceCell<Box<dyn Component<I, O>>>,
+}
+struct SharedCircuit<const I: usize, const O: usize> {
+    circuit: RefCell<Box<dyn Component<I, O>>>,
+    states: SharedState,
+    name: Option<String>,
+    // インスタンスだけの回路を作るときに使う
+    build: Box<dyn Build<I, O>>,
+}
+trait Build<const I: usize, const O: usize> {
+    fn build(&self) -> Box<dyn Component<I, O>>;
+}
+impl<const I: usize, const O: usize, T: Component<I, O> + 'static> Build<I, O> for fn() -> T {
+    fn build(&self) -> Box<dyn Component<I, O>> {
+        Box::new(self())
+    }
+}
+impl<const I: usize, const O: usize> Component<I, O> for Flyweight<I, O> {
+    fn eval(&self, input: [bool; I]) -> [bool; O] {
+        if let Some(own) = self.own.get() {
+            return own.eval(input);
+        }
+        self.load_state();
+        self.last_input.set(Some(input));
+        self.shared.circuit.borrow().eval(input)
+    }
+    fn eval_mut(&mut self, input: [bool; I]) -> [bool; O] {
+        if let Some(own) = self.own.get_mut() {
+            return own.eval_mut(input);
+        }
+        self.load_state();
+        self.last_input.set(Some(input));
+        let output = self.shared.circuit.borrow_mut().eval_mut(input);
+        self.state.copy_from_slice(&self.shared.states.borrow());
+        output
+    }
+    // パスをたどるときに全インスタンスの名前を見るので, ここでは回路を作らない
+    fn name(&self) -> Option<&str> {
+        self.shared.name.as_deref()
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        match self.own() {
+            Some(own) => own.children(),
+            None => Vec::new(),
+        }
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.own();
+        match self.own.get_mut() {
+            Some(own) => own.children_mut(),
+            None => Vec::new(),
+        }
+    }
+    fn state(&self) -> Vec<bool> {
+        match self.own() {
+            Some(own) => own.state(),
+            None => Vec::new(),
+        }
+    }
+    fn set_state(&mut self, state: &[bool]) {
+        self.own();
+        if let Some(own) = self.own.get_mut() {
+            own.set_state(state)
+        }
+    }
+    // インスタンスだけの回路を作ったものは, 状態がその子の中にある
+    fn stateless(&self) -> bool {
+        self.state.is_empty() || self.own.get().is_some_and(|own| own.stateless())
+    }
+    fn probe(&self) -> Option<Vec<bool>> {
+        self.own().and_then(|own| own.probe())
+    }
+    fn force(&mut self, value: Option<&[bool]>) -> bool {
+        self.own();
+        self.own.get_mut().is_some_and(|own| own.force(value))
+    }
+    fn wiring_table(&self) -> Option<Vec<usize>> {
+        match self.own.get() {
+            Some(own) => own.wiring_table(),
+            None => self.shared.circuit.borrow().wiring_table(),
+        }
+    }
+    fn fuse_children(&mut self) {
+        match self.own.get_mut() {
+            Some(own) => own.fuse_children(),
+            None => self.shared.circuit.borrow_mut().fuse_children(),
+        }
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        match self.own.get() {
+            Some(own) => own.netlist(netlist, input),
+            None => self.shared.circuit.borrow().netlist(netlist, input),
+        }
+    }
+}
+impl<const I: usize, const O: usize> Flyweight<I, O> {
+    // 共有できない部品はNone
+    pub fn create<T: Component<I, O> + 'static>(f: fn() -> T) -> Option<Self> {
+        let mut circuit = Box::new(f()) as Box<dyn Component<I, O>>;
+        let states = Rc::new(RefCell::new(Vec::new()));
+        if !attach_all_state(&mut circuit, &states) {
+            return None;
+        }
+        let state = states.borrow().clone();
+        let name = circuit.name().map(|name| name.to_string());
+        let shared = SharedCircuit { circuit: RefCell::new(circuit), states, name, build: Box::new(f) };
+        Some(Self { shared: Rc::new(shared), state, last_input: Cell::new(None), own: OnceCell::new() })
+    }
+    // 回路を共有する別のインスタンス, 状態はいまのselfのものを写す
+    pub fn share(&self) -> Self {
+        let state = match self.own.get() {
+            Some(own) => collect_state(own),
+            None => self.state.clone(),
+        };
+        Self { shared: Rc::clone(&self.shared), state, last_input: Cell::new(None), own: OnceCell::new() }
+    }
+    fn load_state(&self) {
+        if !self.state.is_empty() {
+            self.shared.states.borrow_mut().copy_from_slice(&self.state);
+        }
+    }
+    // 状態を持つものは, 子をたどるときにこのインスタンスだけの回路を作る
+    fn own(&self) -> Option<&dyn Component<I, O>> {
+        if self.state.is_empty() {
+            return None;
+        }
+        if self.own.get().is_none() {
+            let mut own = self.shared.build.build();
+            restore_state(&mut own, &self.state);
+            if let Some(input) = self.last_input.get() {
+                own.eval(input);
+            }
+            let _ = self.own.set(own);
+        }
+        self.own.get().map(Box::as_ref)
+    }
+}
+
+#[test]
+fn flyweight_test() {
+    use crate::arithmetic_comp::FullAdder;
+    use crate::fast_memory::FastMemory;
+    use crate::hierarchy::probe;
+    use crate::memory::MemoryCell;
+    use crate::num_bit_converter::*;
+
+    // 状態を持つものはインスタンスごとに作られるので子をたどれる
+    // read, write, valueを4つ分, 1番と3番に1を書く
+    let mut cells = ConcatBlocks::<3, 1, 4>::create_shared(MemoryCell::new);
+    let write = [false, true, false, false, true, true, false, true, false, false, true, true];
+    let read = [true, false, false, true, false, false, true, false, false, true, false, false];
+    cells.eval_mut(write);
+    assert_eq!(cells.eval_mut(read), [false, true, false, true]);
+    assert_eq!(cells.eval(read), [false, true, false, true]);
+    assert_eq!(probe(&cells, "[1].ff"), Some(vec![true, false]));
+    assert_eq!(probe(&cells, "[2].ff"), Some(vec![false, true]));
+    assert!(crate::hierarchy::force(&mut cells, "[1].ff", Some(&[false, true])));
+    assert_eq!(cells.eval_mut(read), [false, false, false, true]);
+
+    // 宣言がなく状態も移せないものは共有せず, 別々に作る
+    assert!(Flyweight::create(FastMemory::<1, 1>::new).is_none());
+    let mut memories = ConcatBlocks::<4, 1, 2>::create_shared(FastMemory::<1, 1>::new);
+    memories.eval_mut([true, true, false, true, false, false, false, false]);
+    assert_eq!(memories.eval_mut([true, false, false, false, true, false, false, false]), [true, false]);
+
+    // 状態を持たないものはそのまま共有
+    let shared = RecurrentBlock::<1, 2, 1, 8>::create_shared(FullAdder::new);
+    assert!(shared.children()[0].1.children().is_empty());
+    let copied = RecurrentBlock::<1, 2, 1, 8>::create_from_fn(FullAdder::new);
+    for i in 0..(1 << 17) {
+        let input = num_to_bit::<17>(i);
+        assert_eq!(shared.eval(input), copied.eval(input));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WiringError {
+    // output番目の出力が存在しない入力sourceを指している
+    SourceOutOfRange { output: usize, source: usize, inputs: usize },
+    WidthMismatch { inputs: usize, outputs: usize },
+}
+
+impl std::fmt::Display for WiringError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WiringError::SourceOutOfRange { output, source, inputs } => write!(
+                f,
+                "output {} is wired to input {}, but there are only {} inputs",
+                output, source, inputs
+            ),
+            WiringError::WidthMismatch { inputs, outputs } => write!(
+                f,
+                "{} outputs cannot be taken from {} inputs without duplicating input 0",
+                outputs, inputs
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WiringError {}
 
 #[derive(Debug)]
 pub struct Wiring<const N: usize, const M: usize> {
@@ -179,10 +993,134 @@ impl<const N: usize, const M: usize> Component<N, M> for Wiring<N, M> {
         }
         return output;
     }
+    fn stateless(&self) -> bool {
+        true
+    }
+    fn wiring_table(&self) -> Option<Vec<usize>> {
+        Some(self.table.to_vec())
+    }
+    fn netlist(&self, _netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        Some(self.table.iter().map(|&i| input[i]).collect())
+    }
 }
 impl<const N: usize, const M: usize> Wiring<N, M> {
+    // 範囲外の入力を指しているとeval時ではなくここで落ちる
     pub fn create(table: [usize; M]) -> Self {
-        Self { table }
+        Self::try_create(table).unwrap_or_else(|e| panic!("{}", e))
+    }
+    pub fn try_create(table: [usize; M]) -> Result<Self, WiringError> {
+        match table.iter().enumerate().find(|(_, &source)| source >= N) {
+            Some((output, &source)) => Err(WiringError::SourceOutOfRange {
+                output,
+                source,
+                inputs: N,
+            }),
+            None => Ok(Self { table }),
+        }
+    }
+    pub fn table(&self) -> [usize; M] {
+        self.table
+    }
+}
+impl<const N: usize, const M: usize> Wiring<N, M> {
+    // selfの後にnextをつないだものを一つの配線にする
+    pub fn then<const P: usize>(&self, next: &Wiring<M, P>) -> Wiring<N, P> {
+        let mut table = [0; P];
+        table.iter_mut()
+            .zip(next.table)
+            .for_each(|(v, i)| *v = self.table[i]);
+        Wiring { table }
+    }
+    // start本目からM本を取り出す
+    pub fn slice(start: usize) -> Self {
+        let mut table = [0; M];
+        table.iter_mut()
+            .enumerate()
+            .for_each(|(i, v)| *v = start + i);
+        Self::create(table)
+    }
+    // 同じ入力からselfとotherの出力を交互に並べる
+    pub fn interleave(&self, other: &Self) -> Wiring<N, {2 * M}> {
+        let mut table = [0; 2 * M];
+        table.iter_mut()
+            .enumerate()
+            .for_each(|(i, v)| *v = if i % 2 == 0 { self.table[i / 2] } else { other.table[i / 2] });
+        Wiring { table }
+    }
+    // ConcatDifferentShapeBlocksと同じく入力も出力も並べる
+    pub fn concat<const N2: usize, const M2: usize>(
+        &self,
+        other: &Wiring<N2, M2>,
+    ) -> Wiring<{N + N2}, {M + M2}> {
+        let mut table = [0; M + M2];
+        table.iter_mut()
+            .zip(self.table.into_iter().chain(other.table.into_iter().map(|i| i + N)))
+            .for_each(|(v, i)| *v = i);
+        Wiring { table }
+    }
+    // ConcatBlocksと同じくK個並べる
+    pub fn repeat<const K: usize>(&self) -> Wiring<{N * K}, {M * K}> {
+        let mut table = [0; M * K];
+        table.iter_mut()
+            .enumerate()
+            .for_each(|(i, v)| *v = (i / M) * N + self.table[i % M]);
+        Wiring { table }
+    }
+}
+impl<const N: usize> Wiring<N, N> {
+    // 並べ替えになっていなければNone
+    pub fn inverse(&self) -> Option<Self> {
+        let mut table = [N; N];
+        for (i, &source) in self.table.iter().enumerate() {
+            if table[source] != N {
+                return None;
+            }
+            table[source] = i;
+        }
+        Some(Self { table })
+    }
+}
+
+#[test]
+fn wiring_algebra_test() {
+    use crate::num_bit_converter::*;
+
+    let rotate = Wiring::<8, 8>::rotate_right::<3>();
+    let back = Wiring::<8, 8>::rotate_right::<5>();
+    assert_eq!(rotate.inverse().unwrap().table(), back.table());
+    assert_eq!(rotate.then(&back).table(), Wiring::<8, 8>::buffer().table());
+    assert!(Wiring::<3, 3>::create([0, 0, 1]).inverse().is_none());
+
+    let reverse = Wiring::<8, 8>::reverse();
+    let upper = Wiring::<8, 4>::slice(4);
+    let composed = reverse.then(&upper);
+    for i in 0..256 {
+        let input = num_to_bit::<8>(i);
+        assert_eq!(composed.eval(input), upper.eval(reverse.eval(input)));
+    }
+
+    let lower = Wiring::<8, 4>::slice(0);
+    assert_eq!(lower.interleave(&upper).table(), [0, 4, 1, 5, 2, 6, 3, 7]);
+    assert_eq!(
+        Wiring::<2, 1>::create([1]).concat(&Wiring::<3, 2>::create([2, 0])).table(),
+        [1, 4, 2]
+    );
+    assert_eq!(Wiring::<2, 1>::create([1]).repeat::<3>().table(), [1, 3, 5]);
+}
+
+#[test]
+fn connect_wiring_test() {
+    use crate::num_bit_converter::*;
+
+    let reverse = || Box::new(Wiring::<4, 4>::reverse());
+    let chained = MergeLayers::create(reverse(), reverse())
+        .connect_to(reverse());
+    let fused = MergeLayers::create(reverse(), reverse())
+        .connect_wiring(Wiring::<4, 4>::reverse());
+    assert!(fused.layer2.wiring_table().is_some());
+    for i in 0..16 {
+        let input = num_to_bit::<4>(i);
+        assert_eq!(chained.eval(input), fused.eval(input));
     }
 }
 impl<const N: usize, const M: usize> Wiring<N, M> {
@@ -195,6 +1133,42 @@ impl<const N: usize, const M: usize> Wiring<N, M> {
     pub fn cut() -> Self {
         Self::wrapper()
     }
+    // 幅が本当に同じときだけ
+    pub fn try_wrapper() -> Result<Self, WiringError> {
+        if N != M {
+            return Err(WiringError::WidthMismatch { inputs: N, outputs: M });
+        }
+        Ok(Self::wrapper())
+    }
+    // 出力が入力より多いと余りが0番につながってしまうのでエラー
+    pub fn try_cut() -> Result<Self, WiringError> {
+        if M > N {
+            return Err(WiringError::WidthMismatch { inputs: N, outputs: M });
+        }
+        Ok(Self::cut())
+    }
+}
+
+#[test]
+fn wiring_check_test() {
+    assert!(Wiring::<3, 2>::try_create([2, 0]).is_ok());
+    assert_eq!(
+        Wiring::<3, 4>::try_create([0, 1, 3, 2]).unwrap_err(),
+        WiringError::SourceOutOfRange { output: 2, source: 3, inputs: 3 }
+    );
+    assert!(Wiring::<4, 4>::try_wrapper().is_ok());
+    assert_eq!(
+        Wiring::<4, 5>::try_wrapper().unwrap_err(),
+        WiringError::WidthMismatch { inputs: 4, outputs: 5 }
+    );
+    assert!(Wiring::<4, 2>::try_cut().is_ok());
+    assert!(Wiring::<4, 5>::try_cut().is_err());
+}
+
+#[test]
+#[should_panic(expected = "output 1 is wired to input 5")]
+fn wiring_create_panic_test() {
+    Wiring::<4, 2>::create([0, 5]);
 }
 impl<const N: usize> Wiring<N, N> {
     pub fn buffer() -> Self {
@@ -251,6 +1225,7 @@ where
     [(); O + S]: Sized,
 {
     blocks: [Box<dyn Component<{S + I}, {O + S}>>; N],
+    name: Option<String>,
 }
 
 impl<
@@ -310,6 +1285,39 @@ where
 
         result
     }
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.blocks.iter()
+            .enumerate()
+            .map(|(i, b)| (Some(i), b as &dyn Node))
+            .collect()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.blocks.iter_mut()
+            .enumerate()
+            .map(|(i, b)| (Some(i), b as &mut dyn Node))
+            .collect()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        let mut acc = input[..S].to_vec();
+        let mut result = Vec::with_capacity(Out * N + S);
+        for (i, block) in self.blocks.iter().enumerate() {
+            let block_input = acc.iter()
+                .chain(input[(S + i * In)..(S + (i + 1) * In)].iter())
+                .copied()
+                .collect::<Vec<_>>();
+            let block_output = block.netlist(netlist, &block_input)?;
+            result.extend_from_slice(&block_output[..Out]);
+            acc = block_output[Out..].to_vec();
+        }
+        result.extend(acc);
+        Some(result)
+    }
 }
 
 impl<const S: usize, const I: usize, const O: usize, const N: usize> RecurrentBlock<S, I, O, N>
@@ -320,15 +1328,34 @@ where
     [(); O * N + S]: Sized,
 {
     pub fn create(blocks: [Box<dyn Component<{S + I}, {O + S}>>; N]) -> Self {
-        Self { blocks}
+        Self { blocks, name: None }
     }
     pub fn create_from_fn<T: Component<{S + I}, {O + S}> + Sized + 'static>(f: fn() -> T) -> Self {
         Self {
-            blocks: [0; N].map(|_| Box::new(f()) as Box<dyn Component<{S+I}, {O+S}>>)
+            blocks: [0; N].map(|_| Box::new(f()) as Box<dyn Component<{S+I}, {O+S}>>),
+            name: None,
+        }
+    }
+    pub fn create_shared<T: Component<{S + I}, {O + S}> + Sized + 'static>(f: fn() -> T) -> Self {
+        let Some(first) = Flyweight::create(f) else {
+            return Self::create_from_fn(f);
+        };
+        Self {
+            blocks: [0; N].map(|_| Box::new(first.share()) as Box<dyn Component<{S+I}, {O+S}>>),
+            name: None,
         }
     }
+    pub fn named(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
 }
 
 pub const fn pow2(n: usize) -> usize {
     2_usize.pow(n as u32)
 }
+
+// n通りを区別するのに必要なbit数, pow2(log2(n)) >= n になる一番小さい値
+pub const fn log2(n: usize) -> usize {
+    n.next_power_of_two().trailing_zeros() as usize
+}
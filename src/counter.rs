@@ -0,0 +1,261 @@
+use crate::core::*;
+use crate::basic_comp::*;
+use crate::arithmetic_comp::FullAdder;
+use crate::flip_flop::*;
+
+// Nbitの2進カウンタ, クロックの立ち上がりで1つ進むか戻る
+// 入力は clock, enable, load, down, 値(N) の順, 出力は 今の値(N), terminal count
+// loadは同期式でenableに関係なく値を取り込む, loadとenableがどちらも0なら保持
+// terminal countはenableのときに, 上りなら全bit 1, 下りなら全bit 0 で1になる
+// next, terminal, bits の名前でたどれる
+pub struct Counter<const N: usize> where
+    [(); 2 + 2 * N]: Sized,
+    [(); 1 * N]: Sized,
+    [(); 2 + N]: Sized,
+    [(); 1 + 1 * N]: Sized,
+    [(); 5 * N]: Sized,
+    [(); 2 * N]: Sized,
+{
+    // down, load, 値, 今の値 -> 次の値
+    next: Box<dyn Component<{2 + 2 * N}, N>>,
+    // enable, down, 今の値 -> terminal count
+    terminal: Box<dyn Component<{2 + N}, 1>>,
+    bits: Box<dyn Component<{5 * N}, {2 * N}>>,
+}
+
+impl<const N: usize> Component<{4 + N}, {N + 1}> for Counter<N> where
+    [(); 2 + 2 * N]: Sized,
+    [(); 1 * N]: Sized,
+    [(); 2 + N]: Sized,
+    [(); 1 + 1 * N]: Sized,
+    [(); 5 * N]: Sized,
+    [(); 2 * N]: Sized,
+    [(); 4 + N]: Sized,
+    [(); N + 1]: Sized,
+{
+    fn eval(&self, input: [bool; 4 + N]) -> [bool; N + 1] {
+        let bits_input = self.bits_input(&input);
+        let bits = self.bits.eval(bits_input);
+        self.output(&input, &bits)
+    }
+    fn eval_mut(&mut self, input: [bool; 4 + N]) -> [bool; N + 1] {
+        let bits_input = self.bits_input(&input);
+        let bits = self.bits.eval_mut(bits_input);
+        self.output(&input, &bits)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        vec![
+            (None, &self.next as &dyn Node),
+            (None, &self.terminal as &dyn Node),
+            (None, &self.bits as &dyn Node),
+        ]
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        vec![
+            (None, &mut self.next as &mut dyn Node),
+            (None, &mut self.terminal as &mut dyn Node),
+            (None, &mut self.bits as &mut dyn Node),
+        ]
+    }
+}
+
+impl<const N: usize> Counter<N> where
+    [(); 2 + 2 * N]: Sized,
+    [(); 1 * N]: Sized,
+    [(); 2 + N]: Sized,
+    [(); 1 + 1 * N]: Sized,
+    [(); 5 * N]: Sized,
+    [(); 2 * N]: Sized,
+    [(); 0 + (2 + 2 * N)]: Sized,
+    [(); 2 + (2 + 2 * N)]: Sized,
+    [(); 1 + 2 * N]: Sized,
+    [(); 1 * N + 1]: Sized,
+    [(); (1 + 2 * N) + (1 + N)]: Sized,
+    [(); (1 * N + 1) + (1 + N)]: Sized,
+    [(); 3 * N]: Sized,
+{
+    pub fn new() -> Self {
+        // 上りは 今の値 + 0 + 1, 下りは 今の値 + 11..10 + 1 で1つ戻る
+        let next = {
+            let in_wrapper = Wiring::<{2 + 2 * N}, {0 + (2 + 2 * N)}>::wrapper();
+            // 1, 0, down, load, 値, 今の値
+            let layer1 = ConcatDifferentShapeBlocks::<0, {2 + 2 * N}, 2, {2 + 2 * N}>::create(
+                Box::new(ConcatDifferentShapeBlocks::<0, 0, 1, 1>::create(
+                    Box::new(Constant::<0, 1, true>::new()),
+                    Box::new(Constant::<0, 1, false>::new()),
+                )),
+                Box::new(Wiring::<{2 + 2 * N}, {2 + 2 * N}>::buffer()),
+            );
+            // 桁上げ, (今の値0, 0), (今の値1, down), ..., load, 値
+            let mut table = [0; (1 + 2 * N) + (1 + N)];
+            for (i, v) in table.iter_mut().enumerate() {
+                *v = match i {
+                    0 => 0,
+                    _ if i < 1 + 2 * N && i % 2 == 1 => 4 + N + i / 2,
+                    2 => 1,
+                    _ if i < 1 + 2 * N => 2,
+                    _ => 3 + (i - (1 + 2 * N)),
+                };
+            }
+            let layer2 = Wiring::<{2 + (2 + 2 * N)}, {(1 + 2 * N) + (1 + N)}>::create(table);
+            let layer3 = ConcatDifferentShapeBlocks::<{1 + 2 * N}, {1 + N}, {1 * N + 1}, {1 + N}>::create(
+                Box::new(RecurrentBlock::<1, 2, 1, N>::create_from_fn(FullAdder::new)),
+                Box::new(Wiring::<{1 + N}, {1 + N}>::buffer()),
+            );
+            // bitごとに load, 値, 足した結果 と並べて選ぶ
+            let mut table = [0; 3 * N];
+            for (i, v) in table.iter_mut().enumerate() {
+                let bit = i / 3;
+                *v = match i % 3 {
+                    0 => N + 1,
+                    1 => N + 2 + bit,
+                    _ => bit,
+                };
+            }
+            let layer4 = Wiring::<{(1 * N + 1) + (1 + N)}, {3 * N}>::create(table);
+            let layer5 = ConcatBlocks::<3, 1, N>::create_from_fn(select);
+            let out_wrapper = Wiring::<{1 * N}, N>::wrapper();
+            MergeLayers::create(Box::new(in_wrapper), Box::new(layer1))
+                .connect_to(Box::new(layer2))
+                .connect_to(Box::new(layer3))
+                .connect_to(Box::new(layer4))
+                .connect_to(Box::new(layer5))
+                .connect_to(Box::new(out_wrapper))
+        };
+        // enable & (今の値0 ^ down) & (今の値1 ^ down) & ...
+        let terminal = {
+            let mut table = [0; 1 + 2 * N];
+            for (i, v) in table.iter_mut().enumerate() {
+                *v = match i {
+                    0 => 0,
+                    _ if i % 2 == 1 => 2 + i / 2,
+                    _ => 1,
+                };
+            }
+            let layer1 = Wiring::<{2 + N}, {1 + 2 * N}>::create(table);
+            let layer2 = ConcatDifferentShapeBlocks::<1, {2 * N}, 1, {1 * N}>::create(
+                Box::new(Buffer::new()),
+                Box::new(ConcatBlocks::<2, 1, N>::create_from_fn(XOR::<2>::new)),
+            );
+            MergeLayers::create(Box::new(layer1), Box::new(layer2))
+                .connect_to(Box::new(And::<{1 + 1 * N}>::new()))
+        };
+        let bits = ConcatBlocks::<5, 2, N>::create_from_fn(DFlipFlop::new).named("bits");
+        Self {
+            next: Box::new(next.named("next")),
+            terminal: Box::new(terminal.named("terminal")),
+            bits: Box::new(bits),
+        }
+    }
+}
+
+impl<const N: usize> Counter<N> where
+    [(); 2 + 2 * N]: Sized,
+    [(); 1 * N]: Sized,
+    [(); 2 + N]: Sized,
+    [(); 1 + 1 * N]: Sized,
+    [(); 5 * N]: Sized,
+    [(); 2 * N]: Sized,
+    [(); N + 1]: Sized,
+{
+    // 今の値, クロックを0にして何も取り込まなければスレーブの値がそのまま出る
+    pub fn value(&self) -> [bool; N] {
+        let bits = self.bits.eval([false; 5 * N]);
+        let mut value = [false; N];
+        for (i, v) in value.iter_mut().enumerate() {
+            *v = bits[2 * i];
+        }
+        value
+    }
+    // 各bitのフリップフロップへの clock, d, enable, set, reset
+    fn bits_input(&self, input: &[bool]) -> [bool; 5 * N] {
+        let (clock, enable, load, down) = (input[0], input[1], input[2], input[3]);
+        let mut next_input = [false; 2 + 2 * N];
+        next_input[0] = down;
+        next_input[1] = load;
+        next_input[2..(2 + N)].copy_from_slice(&input[4..]);
+        next_input[(2 + N)..].copy_from_slice(&self.value());
+        let next = self.next.eval(next_input);
+        let mut bits_input = [false; 5 * N];
+        for (chunk, d) in bits_input.chunks_mut(5).zip(next) {
+            chunk[0] = clock;
+            chunk[1] = d;
+            chunk[2] = enable || load;
+        }
+        bits_input
+    }
+    fn output(&self, input: &[bool], bits: &[bool]) -> [bool; N + 1] {
+        let mut terminal_input = [false; 2 + N];
+        terminal_input[0] = input[1];
+        terminal_input[1] = input[3];
+        let mut output = [false; N + 1];
+        for i in 0..N {
+            output[i] = bits[2 * i];
+            terminal_input[2 + i] = bits[2 * i];
+        }
+        output[N] = self.terminal.eval(terminal_input)[0];
+        output
+    }
+}
+
+#[test]
+fn counter_test() {
+    use crate::num_bit_converter::*;
+
+    // clock, enable, load, down, 値
+    let input = |clock: bool, enable: bool, load: bool, down: bool, value: usize| {
+        num_to_bit::<8>(clock as usize + ((enable as usize) << 1) + ((load as usize) << 2) + ((down as usize) << 3) + (value << 4))
+    };
+    let mut counter = Counter::<4>::new();
+    assert_eq!(counter.value(), [false; 4]);
+
+    // 上りで2周して折り返す, 15のときだけterminal count
+    for i in 1..=32 {
+        counter.eval_mut(input(false, true, false, false, 0));
+        let output = counter.eval_mut(input(true, true, false, false, 0));
+        assert_eq!(bit_to_num(output) % 16, i % 16);
+        assert_eq!(output[4], i % 16 == 15);
+    }
+    // enableが0なら進まない
+    counter.eval_mut(input(false, false, false, false, 0));
+    assert_eq!(bit_to_num(counter.eval_mut(input(true, false, false, false, 0))), 0);
+
+    // 下りは0から15に戻る, 0のときだけterminal count
+    for i in 1..=32 {
+        counter.eval_mut(input(false, true, false, true, 0));
+        let output = counter.eval_mut(input(true, true, false, true, 0));
+        assert_eq!(bit_to_num(output) % 16, (32 - i) % 16);
+        assert_eq!(output[4], i % 16 == 0);
+    }
+
+    // loadはenableが0でも立ち上がりで取り込む, クロックが1のままなら変わらない
+    counter.eval_mut(input(false, false, true, false, 0b1011));
+    assert_eq!(bit_to_num(counter.value()), 0);
+    counter.eval_mut(input(true, false, true, false, 0b1011));
+    assert_eq!(bit_to_num(counter.value()), 0b1011);
+    counter.eval_mut(input(true, true, true, false, 0b0110));
+    assert_eq!(bit_to_num(counter.value()), 0b1011);
+    counter.eval_mut(input(false, true, false, false, 0));
+    counter.eval_mut(input(true, true, false, false, 0));
+    assert_eq!(bit_to_num(counter.value()), 0b1100);
+}
+
+#[test]
+fn counter_with_clock_test() {
+    use crate::clock::Clock;
+    use crate::num_bit_converter::*;
+
+    // クロックを2回進めるごとに1つ上がる
+    let mut clock = Clock::new();
+    let mut counter = Counter::<8>::new();
+    let mut input = [false; 12];
+    input[1] = true;
+    for i in 0..600 {
+        input[0] = clock.eval_mut([])[0];
+        let output = counter.eval_mut(input);
+        assert_eq!(bit_to_num::<9>(output) % 256, (i / 2) % 256);
+    }
+    for path in ["next", "terminal", "bits[7].slave"] {
+        assert!(crate::hierarchy::find(&counter, path).is_some(), "{}", path);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::core::*;
+use crate::netlist::{Net, Netlist};
 use crate::basic_comp::*;
 
 pub struct BitDecoder<const N: usize>
@@ -15,6 +16,18 @@ where
     fn eval(&self, input: [bool; N]) -> [bool; pow2(N)] {
         self.decoder.eval(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.decoder.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.decoder.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.decoder.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.decoder.netlist(netlist, input)
+    }
 }
 impl<const N: usize> BitDecoder<N>
 where
@@ -104,3 +117,22 @@ fn decoder_test() {
         assert_eq!(decoder.eval(num_to_bit::<8>(i)), expected_output);
     }
 }
+
+#[test]
+fn decoder_fuse_wirings_test() {
+    use crate::num_bit_converter::*;
+    use crate::optimize::fuse_wirings;
+
+    // connect_toでつないだ鎖の中の配線が入れ子をまたいでまとまる
+    let original = BitDecoder::<4>::new();
+    let mut fused = BitDecoder::<4>::new();
+    fuse_wirings(&mut fused);
+    let (before, after) = (original.decoder.wiring_layers(), fused.decoder.wiring_layers());
+    // 入力からNotの手前まで, Notと並ぶBuffer, Andの手前, 出力の4つ
+    assert!(after < before, "{} -> {}", before, after);
+    assert_eq!(after, 4);
+    for i in 0..16 {
+        let input = num_to_bit::<4>(i);
+        assert_eq!(fused.eval(input), original.eval(input));
+    }
+}
@@ -0,0 +1,564 @@
+use crate::core::*;
+use crate::netlist::{Net, Netlist};
+use crate::basic_comp::*;
+use crate::arithmetic_comp::Subtractor;
+use crate::flip_flop::{select, DFlipFlop};
+use crate::multiplier::with_zero;
+use crate::shift_register::ShiftRegister;
+
+// 引き戻し法の1桁, 余り(N), 割る数(N), 割られる数の次のbit -> 商のbit, 余り(N), 割る数(N)
+// 余りを1bit上げて次のbitを入れ, 割る数を引けたら引いた値を, 引けなければ元の値を残す
+fn divide_stage<const N: usize>() -> MergeLayers<{2 * N + 1}, {1 + (1 * N + N)}, {1 + 2 * N}> where
+    [(); 2 * N + 1]: Sized,
+    [(); 2 * N + 1 + 0]: Sized,
+    [(); 2 * N + 1 + 1]: Sized,
+    [(); 2 * (N + 1) + 1]: Sized,
+    [(); (2 * (N + 1) + 1) + 2 * N]: Sized,
+    [(); (N + 1) + 3]: Sized,
+    [(); (N + 1) + 6]: Sized,
+    [(); ((N + 1) + 3) + 2 * N]: Sized,
+    [(); 3 * N + N]: Sized,
+    [(); 1 + (3 * N + N)]: Sized,
+    [(); 1 * N + N]: Sized,
+    [(); 1 + (1 * N + N)]: Sized,
+    [(); 1 + 2 * N]: Sized,
+    [(); 2 * N]: Sized,
+    [(); 3 * N]: Sized,
+    [(); 1 * N]: Sized,
+    [(); 2 * (N + 1)]: Sized,
+    [(); 1 + 2 * (N + 1)]: Sized,
+    [(); (1 + 2 * (N + 1)) + 2]: Sized,
+    [(); 1 * (N + 1) + 1]: Sized,
+    [(); (1 * (N + 1) + 1) + 2]: Sized,
+{
+    let (remainder, divisor, next, zero) = (|i| i, |i| N + i, 2 * N, 2 * N + 1);
+    // 引き算の a = (余り << 1) | 次のbit, b = 割る数, 借り = 0, 後ろに 引く前の値(N), 割る数(N)
+    let mut table = [zero; (2 * (N + 1) + 1) + 2 * N];
+    table[0] = next;
+    for i in 0..N {
+        table[1 + i] = remainder(i);
+        table[N + 1 + i] = divisor(i);
+        table[2 * (N + 1) + 1 + i] = if i == 0 { next } else { remainder(i - 1) };
+        table[2 * (N + 1) + 1 + N + i] = divisor(i);
+    }
+    let layer1 = Wiring::<{2 * N + 1 + 1}, {(2 * (N + 1) + 1) + 2 * N}>::create(table);
+    let layer2 = ConcatDifferentShapeBlocks::<{2 * (N + 1) + 1}, {2 * N}, {(N + 1) + 3}, {2 * N}>::create(
+        Box::new(Subtractor::<{N + 1}>::new()),
+        Box::new(Wiring::<{2 * N}, {2 * N}>::buffer()),
+    );
+    // 借り, bitごとに (借り, 引く前, 引いた後), 割る数
+    let (borrow, shifted, diff) = (N + 1, |i| N + 4 + i, |i| i);
+    let mut table = [0; 1 + (3 * N + N)];
+    table[0] = borrow;
+    for i in 0..N {
+        table[1 + 3 * i..1 + 3 * i + 3].copy_from_slice(&[borrow, shifted(i), diff(i)]);
+        table[1 + 3 * N + i] = 2 * N + 4 + i;
+    }
+    let layer3 = Wiring::<{((N + 1) + 3) + 2 * N}, {1 + (3 * N + N)}>::create(table);
+    let layer4 = ConcatDifferentShapeBlocks::<1, {3 * N + N}, 1, {1 * N + N}>::create(
+        Box::new(Not::new()),
+        Box::new(ConcatDifferentShapeBlocks::<{3 * N}, N, {1 * N}, N>::create(
+            Box::new(ConcatBlocks::<3, 1, N>::create_from_fn(select)),
+            Box::new(Wiring::<N, N>::buffer()),
+        )),
+    );
+    let out_wrapper = Wiring::<{1 + (1 * N + N)}, {1 + 2 * N}>::wrapper();
+    MergeLayers::create(Box::new(with_zero::<{2 * N + 1}>()), Box::new(layer1))
+        .connect_to(Box::new(layer2))
+        .connect_to(Box::new(layer3))
+        .connect_to(Box::new(layer4))
+        .connect_to(Box::new(out_wrapper))
+}
+
+// 全bitが0のとき1
+pub(crate) fn is_zero<const N: usize>() -> MergeLayers<N, 1, 1> {
+    MergeLayers::create(Box::new(Or::<N>::new()), Box::new(Not::new()))
+}
+
+// Nbitの符号なし割り算, 入力は a(N), b(N), 出力は 商(N), 余り(N), 0で割った
+// 上のbitから引き戻し法の桁をN個つなぐ, 0で割ると商は全bit 1, 余りは a になる
+pub struct Divider<const N: usize> where
+    [(); 2 * N]: Sized,
+    [(); 2 * N + 1]: Sized,
+    [(); (1 * N + 2 * N) + 1]: Sized,
+{
+    divider: MergeLayers<{2 * N}, {(1 * N + 2 * N) + 1}, {2 * N + 1}>,
+}
+
+impl<const N: usize> Component<{2 * N}, {2 * N + 1}> for Divider<N> where
+    [(); 2 * N]: Sized,
+    [(); 2 * N + 1]: Sized,
+    [(); (1 * N + 2 * N) + 1]: Sized,
+{
+    fn eval(&self, input: [bool; 2 * N]) -> [bool; 2 * N + 1] {
+        self.divider.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.divider.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.divider.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.divider.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.divider.netlist(netlist, input)
+    }
+}
+
+impl<const N: usize> Divider<N> where
+    [(); 2 * N]: Sized,
+    [(); 2 * N + 0]: Sized,
+    [(); 2 * N + 1]: Sized,
+    [(); 2 * N + 1 + 0]: Sized,
+    [(); 2 * N + 1 + 1]: Sized,
+    [(); 2 * N + 1 * N]: Sized,
+    [(); (2 * N + 1 * N) + N]: Sized,
+    [(); 1 * N + 2 * N]: Sized,
+    [(); (1 * N + 2 * N) + 1]: Sized,
+    [(); 2 * (N + 1) + 1]: Sized,
+    [(); (2 * (N + 1) + 1) + 2 * N]: Sized,
+    [(); (N + 1) + 3]: Sized,
+    [(); (N + 1) + 6]: Sized,
+    [(); ((N + 1) + 3) + 2 * N]: Sized,
+    [(); 3 * N + N]: Sized,
+    [(); 1 + (3 * N + N)]: Sized,
+    [(); 1 * N + N]: Sized,
+    [(); 1 + (1 * N + N)]: Sized,
+    [(); 1 + 2 * N]: Sized,
+    [(); 3 * N]: Sized,
+    [(); 1 * N]: Sized,
+    [(); 2 * (N + 1)]: Sized,
+    [(); 1 + 2 * (N + 1)]: Sized,
+    [(); (1 + 2 * (N + 1)) + 2]: Sized,
+    [(); 1 * (N + 1) + 1]: Sized,
+    [(); (1 * (N + 1) + 1) + 2]: Sized,
+{
+    pub fn new() -> Self {
+        // 余りの初期値0, 割る数, 割られる数を上のbitから, 0の判定用の割る数
+        let zero = 2 * N;
+        let mut table = [zero; (2 * N + 1 * N) + N];
+        for i in 0..N {
+            table[N + i] = N + i;
+            table[2 * N + i] = N - 1 - i;
+            table[3 * N + i] = N + i;
+        }
+        let layer1 = Wiring::<{2 * N + 1}, {(2 * N + 1 * N) + N}>::create(table);
+        let layer2 = ConcatDifferentShapeBlocks::<{2 * N + 1 * N}, N, {1 * N + 2 * N}, 1>::create(
+            Box::new(RecurrentBlock::<{2 * N}, 1, 1, N>::create_from_fn(divide_stage::<N>)),
+            Box::new(is_zero::<N>()),
+        );
+        // 商は上のbitから出てくるので逆順に
+        let mut table = [0; 2 * N + 1];
+        for i in 0..N {
+            table[i] = N - 1 - i;
+            table[N + i] = N + i;
+        }
+        table[2 * N] = 3 * N;
+        let layer3 = Wiring::<{(1 * N + 2 * N) + 1}, {2 * N + 1}>::create(table);
+        let divider = MergeLayers::create(Box::new(with_zero::<{2 * N}>()), Box::new(layer1))
+            .connect_to(Box::new(layer2))
+            .connect_to(Box::new(layer3));
+        Self { divider }
+    }
+}
+
+// start, busy -> 商のシフトレジスタのmode(3), 余りと桁のレジスタのenable
+// startならLoad, startでなくbusyならShiftLeft, どちらでもなければHold, enableは start | busy
+fn divider_control() -> MergeLayers<2, 7, 4> {
+    // start, busy, 0
+    let layer1 = with_zero::<2>();
+    // start | start, 0, busy | 0 | start, busy
+    let layer2 = Wiring::<3, 7>::create([0, 0, 2, 1, 2, 0, 1]);
+    let layer3 = ConcatDifferentShapeBlocks::<1, 6, 1, 3>::create(
+        Box::new(Buffer::new()),
+        Box::new(ConcatDifferentShapeBlocks::<3, 3, 1, 2>::create(
+            Box::new(select()),
+            Box::new(ConcatDifferentShapeBlocks::<1, 2, 1, 1>::create(
+                Box::new(Buffer::new()),
+                Box::new(Or::<2>::new()),
+            )),
+        )),
+    );
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(layer3))
+}
+
+// start, 今の桁(N) -> startなら全bit 1, でなければ1bit上げて空いたbit0に0を入れる
+fn next_step<const N: usize>() -> MergeLayers<{1 + N}, {1 * N}, N> where
+    [(); 1 + N]: Sized,
+    [(); 1 + N + 0]: Sized,
+    [(); 1 + N + 1]: Sized,
+    [(); 2 * N]: Sized,
+    [(); 1 * N]: Sized,
+{
+    let zero = N + 1;
+    let mut table = [0; 2 * N];
+    for i in 0..N {
+        table[2 * i + 1] = if i == 0 { zero } else { i };
+    }
+    let layer1 = Wiring::<{1 + N + 1}, {2 * N}>::create(table);
+    let layer2 = ConcatBlocks::<2, 1, N>::create_from_fn(Or::<2>::new);
+    let out_wrapper = Wiring::<{1 * N}, N>::wrapper();
+    MergeLayers::create(Box::new(with_zero::<{1 + N}>()), Box::new(layer1))
+        .connect_to(Box::new(layer2))
+        .connect_to(Box::new(out_wrapper))
+}
+
+// start, 引いた結果(N) -> startなら0, でなければ引いた結果
+fn next_remainder<const N: usize>() -> MergeLayers<{1 + N}, {1 * N}, N> where
+    [(); 1 + N]: Sized,
+    [(); 1 + N + 0]: Sized,
+    [(); 1 + N + 1]: Sized,
+    [(); 3 * N]: Sized,
+    [(); 1 * N]: Sized,
+{
+    let zero = N + 1;
+    let mut table = [0; 3 * N];
+    for i in 0..N {
+        table[3 * i + 1] = zero;
+        table[3 * i + 2] = 1 + i;
+    }
+    let layer1 = Wiring::<{1 + N + 1}, {3 * N}>::create(table);
+    let layer2 = ConcatBlocks::<3, 1, N>::create_from_fn(select);
+    let out_wrapper = Wiring::<{1 * N}, N>::wrapper();
+    MergeLayers::create(Box::new(with_zero::<{1 + N}>()), Box::new(layer1))
+        .connect_to(Box::new(layer2))
+        .connect_to(Box::new(out_wrapper))
+}
+
+// 1クロックに1桁ずつ進める割り算
+// 入力は clock, start, a(N), b(N) の順, 出力は 商(N), 余り(N), 終わった, 0で割った
+// startのある立ち上がりでaを商のシフトレジスタに入れ, 続くN回の立ち上がりで
+// 商の最上位bitを余りに送りながら左シフトし, 空いたbit0に商のbitを入れる, bは終わるまで保つ
+pub struct SequentialDivider<const N: usize> where
+    [(); 2 * N + 1]: Sized,
+    [(); 1 + 2 * N]: Sized,
+    [(); 1 + N]: Sized,
+    [(); 5 + N]: Sized,
+    [(); N + 1]: Sized,
+    [(); 5 * N]: Sized,
+    [(); 2 * N]: Sized,
+{
+    // 余り, 割る数, 商の最上位bit -> 商のbit, 次の余り, 割る数
+    stage: Box<dyn Component<{2 * N + 1}, {1 + 2 * N}>>,
+    zero: Box<dyn Component<N, 1>>,
+    control: Box<dyn Component<2, 4>>,
+    next_remainder: Box<dyn Component<{1 + N}, N>>,
+    next_step: Box<dyn Component<{1 + N}, N>>,
+    quotient: Box<dyn Component<{5 + N}, {N + 1}>>,
+    remainder: Box<dyn Component<{5 * N}, {2 * N}>>,
+    // startで全bit 1にし, 1クロックごとに0を下から入れる, 最上位bitが1の間は動いている
+    step: Box<dyn Component<{5 * N}, {2 * N}>>,
+}
+
+impl<const N: usize> Component<{2 + 2 * N}, {2 * N + 2}> for SequentialDivider<N> where
+    [(); 2 * N + 1]: Sized,
+    [(); 1 + 2 * N]: Sized,
+    [(); 1 + N]: Sized,
+    [(); 5 + N]: Sized,
+    [(); N + 1]: Sized,
+    [(); 5 * N]: Sized,
+    [(); 2 * N]: Sized,
+    [(); 2 + 2 * N]: Sized,
+    [(); 2 * N + 2]: Sized,
+{
+    fn eval(&self, input: [bool; 2 + 2 * N]) -> [bool; 2 * N + 2] {
+        let (quotient, remainder, step) = self.registers_input(&input);
+        let quotient = self.quotient.eval(quotient);
+        let remainder = self.remainder.eval(remainder);
+        let step = self.step.eval(step);
+        self.output(&input, &quotient, &remainder, &step)
+    }
+    fn eval_mut(&mut self, input: [bool; 2 + 2 * N]) -> [bool; 2 * N + 2] {
+        let registers_input = self.registers_input(&input);
+        self.eval_registers_mut(&input, registers_input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        vec![
+            (None, &self.stage as &dyn Node),
+            (None, &self.zero as &dyn Node),
+            (None, &self.control as &dyn Node),
+            (None, &self.next_remainder as &dyn Node),
+            (None, &self.next_step as &dyn Node),
+            (None, &self.quotient as &dyn Node),
+            (None, &self.remainder as &dyn Node),
+            (None, &self.step as &dyn Node),
+        ]
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        vec![
+            (None, &mut self.stage as &mut dyn Node),
+            (None, &mut self.zero as &mut dyn Node),
+            (None, &mut self.control as &mut dyn Node),
+            (None, &mut self.next_remainder as &mut dyn Node),
+            (None, &mut self.next_step as &mut dyn Node),
+            (None, &mut self.quotient as &mut dyn Node),
+            (None, &mut self.remainder as &mut dyn Node),
+            (None, &mut self.step as &mut dyn Node),
+        ]
+    }
+}
+
+impl<const N: usize> SequentialDivider<N> where
+    [(); 2 * N + 1]: Sized,
+    [(); 1 + (1 * N + N)]: Sized,
+    [(); 1 + 2 * N]: Sized,
+    [(); 1 + N]: Sized,
+    [(); 1 + N + 0]: Sized,
+    [(); 1 + N + 1]: Sized,
+    [(); 5 + N]: Sized,
+    [(); N + 1]: Sized,
+    [(); 5 * N]: Sized,
+    [(); 2 * N]: Sized,
+    [(); 2 * N + 1 + 0]: Sized,
+    [(); 2 * N + 1 + 1]: Sized,
+    [(); 2 * (N + 1) + 1]: Sized,
+    [(); (2 * (N + 1) + 1) + 2 * N]: Sized,
+    [(); (N + 1) + 3]: Sized,
+    [(); (N + 1) + 6]: Sized,
+    [(); ((N + 1) + 3) + 2 * N]: Sized,
+    [(); 3 * N + N]: Sized,
+    [(); 1 + (3 * N + N)]: Sized,
+    [(); 1 * N + N]: Sized,
+    [(); 3 * N]: Sized,
+    [(); 1 * N]: Sized,
+    [(); 2 * (N + 1)]: Sized,
+    [(); 1 + 2 * (N + 1)]: Sized,
+    [(); (1 + 2 * (N + 1)) + 2]: Sized,
+    [(); 1 * (N + 1) + 1]: Sized,
+    [(); (1 * (N + 1) + 1) + 2]: Sized,
+    [(); 5 + 2 * N]: Sized,
+    [(); 11 * (N + 1)]: Sized,
+    [(); 5 * (N + 1)]: Sized,
+    [(); 2 * (N + 1)]: Sized,
+{
+    pub fn new() -> Self {
+        Self {
+            stage: Box::new(divide_stage::<N>().named("stage")),
+            zero: Box::new(is_zero::<N>().named("zero")),
+            control: Box::new(divider_control().named("control")),
+            next_remainder: Box::new(next_remainder::<N>().named("next_remainder")),
+            next_step: Box::new(next_step::<N>().named("next_step")),
+            quotient: Box::new(Named::create("quotient", Box::new(ShiftRegister::<N>::new()))),
+            remainder: Box::new(ConcatBlocks::<5, 2, N>::create_from_fn(DFlipFlop::new).named("remainder")),
+            step: Box::new(ConcatBlocks::<5, 2, N>::create_from_fn(DFlipFlop::new).named("step")),
+        }
+    }
+}
+
+impl<const N: usize> SequentialDivider<N> where
+    [(); 2 * N + 1]: Sized,
+    [(); 1 + 2 * N]: Sized,
+    [(); 1 + N]: Sized,
+    [(); 5 + N]: Sized,
+    [(); N + 1]: Sized,
+    [(); 5 * N]: Sized,
+    [(); 2 * N]: Sized,
+    [(); 2 * N + 2]: Sized,
+{
+    // まだ桁が残っている
+    pub fn busy(&self) -> bool {
+        self.step.eval([false; 5 * N])[2 * (N - 1)]
+    }
+    // クロックを0, 1と入れて1周期進める, inputのclockは使わない
+    // 0の間はレジスタの出力が変わらないので, 0のときに用意した入力のままクロックだけ1にする
+    pub fn cycle(&mut self, input: [bool; 2 + 2 * N]) -> [bool; 2 * N + 2] {
+        let mut input = input;
+        input[0] = false;
+        let (mut quotient, mut remainder, mut step) = self.registers_input(&input);
+        self.eval_registers_mut(&input, (quotient, remainder, step));
+        input[0] = true;
+        quotient[0] = true;
+        for chunk in remainder.chunks_mut(5).chain(step.chunks_mut(5)) {
+            chunk[0] = true;
+        }
+        self.eval_registers_mut(&input, (quotient, remainder, step))
+    }
+    fn eval_registers_mut(
+        &mut self,
+        input: &[bool],
+        (quotient, remainder, step): ([bool; 5 + N], [bool; 5 * N], [bool; 5 * N]),
+    ) -> [bool; 2 * N + 2] {
+        let quotient = self.quotient.eval_mut(quotient);
+        let remainder = self.remainder.eval_mut(remainder);
+        let step = self.step.eval_mut(step);
+        self.output(input, &quotient, &remainder, &step)
+    }
+    // 各レジスタへの入力, 今の値からクロックが0の間に次の値を用意する
+    fn registers_input(&self, input: &[bool]) -> ([bool; 5 + N], [bool; 5 * N], [bool; 5 * N]) {
+        let (clock, start) = (input[0], input[1]);
+        let (a, b) = (&input[2..(2 + N)], &input[(2 + N)..]);
+        let quotient = self.quotient.eval([false; 5 + N]);
+        let remainder = self.remainder.eval([false; 5 * N]);
+        let step = self.step.eval([false; 5 * N]);
+        let [load, shift_left, mode, enable] = self.control.eval([start, step[2 * (N - 1)]]);
+
+        let mut stage_input = [false; 2 * N + 1];
+        for i in 0..N {
+            stage_input[i] = remainder[2 * i];
+        }
+        stage_input[N..(2 * N)].copy_from_slice(b);
+        stage_input[2 * N] = quotient[N - 1];
+        let stage = self.stage.eval(stage_input);
+        let mut next_remainder_input = [start; 1 + N];
+        next_remainder_input[1..].copy_from_slice(&stage[1..(1 + N)]);
+        let next_remainder = self.next_remainder.eval(next_remainder_input);
+        let mut next_step_input = [start; 1 + N];
+        for i in 0..N {
+            next_step_input[1 + i] = step[2 * i];
+        }
+        let next_step = self.next_step.eval(next_step_input);
+
+        let mut quotient_input = [false; 5 + N];
+        quotient_input[..5].copy_from_slice(&[clock, load, shift_left, mode, stage[0]]);
+        quotient_input[5..].copy_from_slice(a);
+        let mut remainder_input = [false; 5 * N];
+        let mut step_input = [false; 5 * N];
+        for (i, (r, s)) in remainder_input.chunks_mut(5).zip(step_input.chunks_mut(5)).enumerate() {
+            r[..3].copy_from_slice(&[clock, next_remainder[i], enable]);
+            s[..3].copy_from_slice(&[clock, next_step[i], enable]);
+        }
+        (quotient_input, remainder_input, step_input)
+    }
+    fn output(&self, input: &[bool], quotient: &[bool], remainder: &[bool], step: &[bool]) -> [bool; 2 * N + 2] {
+        let mut b = [false; N];
+        b.copy_from_slice(&input[(2 + N)..]);
+        let mut output = [false; 2 * N + 2];
+        for i in 0..N {
+            output[i] = quotient[i];
+            output[N + i] = remainder[2 * i];
+        }
+        output[2 * N] = !step[2 * (N - 1)];
+        output[2 * N + 1] = self.zero.eval(b)[0];
+        output
+    }
+}
+
+#[cfg(test)]
+// 商, 余り, ..., 0で割った の出力を確かめる
+fn check_quotient<const N: usize>(output: &[bool], a: usize, b: usize) {
+    use crate::num_bit_converter::*;
+
+    let mask = (1 << N) - 1;
+    let mut bits = [false; 64];
+    bits[..output.len()].copy_from_slice(output);
+    let value = bit_to_num(bits);
+    let (quotient, remainder) = (value & mask, (value >> N) & mask);
+    // 0で割ると商は全bit 1, 余りはa
+    let expected = a.checked_div(b).map_or((mask, a), |q| (q, a % b));
+    assert_eq!((quotient, remainder), expected, "{} / {}", a, b);
+    assert_eq!(output[output.len() - 1], b == 0);
+}
+
+#[test]
+fn divider_test() {
+    use crate::num_bit_converter::*;
+
+    let divider = Divider::<8>::new();
+    for a in 0..256 {
+        for b in 0..256 {
+            check_quotient::<8>(&divider.eval(num_to_bit((b << 8) + a)), a, b);
+        }
+    }
+    let divider = Divider::<1>::new();
+    assert_eq!(divider.eval([true, true]), [true, false, false]);
+    assert_eq!(divider.eval([true, false]), [true, true, true]);
+}
+
+#[cfg(test)]
+// 1クロック進める, cycleならSequentialDivider::cycleで, でなければclockを0, 1と入れて
+fn drive<const N: usize>(divider: &mut SequentialDivider<N>, cycle: bool, input: [bool; 2 + 2 * N]) -> [bool; 2 * N + 2] where
+    [(); 2 * N + 1]: Sized,
+    [(); 1 + N]: Sized,
+    [(); 1 + 2 * N]: Sized,
+    [(); 5 + N]: Sized,
+    [(); N + 1]: Sized,
+    [(); 5 * N]: Sized,
+    [(); 2 * N]: Sized,
+    [(); 2 + 2 * N]: Sized,
+    [(); 2 * N + 2]: Sized,
+{
+    if cycle {
+        return divider.cycle(input);
+    }
+    let mut input = input;
+    input[0] = false;
+    divider.eval_mut(input);
+    input[0] = true;
+    divider.eval_mut(input)
+}
+
+#[cfg(test)]
+fn divide_sequentially<const N: usize>(divider: &mut SequentialDivider<N>, cycle: bool, a: usize, b: usize) -> [bool; 2 * N + 2] where
+    [(); 2 * N + 1]: Sized,
+    [(); 1 + N]: Sized,
+    [(); 1 + 2 * N]: Sized,
+    [(); 5 + N]: Sized,
+    [(); N + 1]: Sized,
+    [(); 5 * N]: Sized,
+    [(); 2 * N]: Sized,
+    [(); 2 + 2 * N]: Sized,
+    [(); 2 * N + 2]: Sized,
+{
+    use crate::num_bit_converter::*;
+
+    let mut input = num_to_bit::<{2 + 2 * N}>(2 + (a << 2) + (b << (2 + N)));
+    drive(divider, cycle, input);
+    input[1] = false;
+    // startのあとちょうどNクロックで終わる
+    for _ in 0..(N - 1) {
+        assert!(!drive(divider, cycle, input)[2 * N]);
+    }
+    let output = drive(divider, cycle, input);
+    assert!(output[2 * N]);
+    output
+}
+
+#[test]
+fn divider_control_test() {
+    use crate::shift_register::ShiftMode::*;
+
+    let control = divider_control();
+    for (start, busy, mode) in [(false, false, Hold), (false, true, ShiftLeft), (true, false, Load), (true, true, Load)] {
+        let output = control.eval([start, busy]);
+        assert_eq!(output[..3], mode.bits(), "start: {}, busy: {}", start, busy);
+        assert_eq!(output[3], start || busy);
+    }
+}
+
+#[test]
+fn sequential_divider_test() {
+    use crate::num_bit_converter::*;
+
+    // 半周期ずつ動かしても1周期ずつ動かしても同じ
+    let mut divider = SequentialDivider::<4>::new();
+    let mut cycled = SequentialDivider::<4>::new();
+    assert!(!divider.busy());
+    for a in 0..16 {
+        for b in 0..16 {
+            let output = divide_sequentially(&mut divider, false, a, b);
+            check_quotient::<4>(&output, a, b);
+            assert_eq!(divide_sequentially(&mut cycled, true, a, b), output);
+        }
+    }
+
+    // 終わったあとは最後の 15 / 15 の結果を保つ
+    let output = divider.eval_mut([false; 10]);
+    assert_eq!(bit_to_num(output) & 0xff, 1);
+    assert!(!divider.busy());
+    for path in ["stage", "zero", "control", "next_remainder", "next_step", "remainder[3].slave", "step[3].slave", "quotient.bits[0].master"] {
+        assert!(crate::hierarchy::find(&divider, path).is_some(), "{}", path);
+    }
+}
+
+#[test]
+fn sequential_divider_exhaustive_test() {
+    let mut divider = SequentialDivider::<8>::new();
+    for a in 0..256 {
+        for b in 0..256 {
+            check_quotient::<8>(&divide_sequentially(&mut divider, true, a, b), a, b);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::core::*;
+use crate::netlist::{Net, Netlist};
+use crate::basic_comp::*;
+use crate::divider::is_zero;
+use crate::multiplier::with_zero;
+
+// BitDecoderの逆, 2^N本のうち1本だけが1のときその番号をNbitで出す
+// 出力のj bit目は番号のj bit目が1の線のOR, 1が複数あるとそれらの番号のORになる
+pub struct Encoder<const N: usize> where
+    [(); pow2(N)]: Sized,
+    [(); 1 * N]: Sized,
+{
+    encoder: MergeLayers<{pow2(N)}, {1 * N}, N>,
+}
+
+impl<const N: usize> Component<{pow2(N)}, N> for Encoder<N> where
+    [(); pow2(N)]: Sized,
+    [(); 1 * N]: Sized,
+{
+    fn eval(&self, input: [bool; pow2(N)]) -> [bool; N] {
+        self.encoder.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.encoder.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.encoder.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.encoder.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.encoder.netlist(netlist, input)
+    }
+}
+
+impl<const N: usize> Encoder<N> where
+    [(); pow2(N)]: Sized,
+    [(); 1 * N]: Sized,
+    [(); pow2(N - 1)]: Sized,
+    [(); pow2(N - 1) * N]: Sized,
+{
+    pub fn new() -> Self {
+        // bitごとに, そのbitが1の番号を小さい順に2^(N-1)本
+        let mut table = [0; pow2(N - 1) * N];
+        for (bit, chunk) in table.chunks_mut(pow2(N - 1)).enumerate() {
+            let ones = (0..pow2(N)).filter(|i| i & (1 << bit) != 0);
+            for (v, i) in chunk.iter_mut().zip(ones) {
+                *v = i;
+            }
+        }
+        let layer1 = Wiring::<{pow2(N)}, {pow2(N - 1) * N}>::create(table);
+        let layer2 = ConcatBlocks::<{pow2(N - 1)}, 1, N>::create_from_fn(Or::<{pow2(N - 1)}>::new);
+        let out_wrapper = Wiring::<{1 * N}, N>::wrapper();
+        let encoder = MergeLayers::create(Box::new(layer1), Box::new(layer2))
+            .connect_to(Box::new(out_wrapper));
+        Self { encoder }
+    }
+}
+
+// その線, それより上の線(K) -> その線が1で上の線がすべて0
+fn highest_cell<const K: usize>() -> MergeLayers<{1 + K}, 2, 1> where
+    [(); 1 + K]: Sized,
+{
+    let layer1 = ConcatDifferentShapeBlocks::<1, K, 1, 1>::create(
+        Box::new(Buffer::new()),
+        Box::new(is_zero::<K>()),
+    );
+    MergeLayers::create(Box::new(layer1), Box::new(And::<2>::new()))
+}
+
+// 2^N本のうち1になっている一番上の線の番号をNbitで出す
+// 入力は 線(2^N), 出力は 番号(N), どれかが1 の順, すべて0なら番号は0
+// 上の線がすべて0の線だけを残して1本にしてからEncoderに通す
+// 割り込みの優先順位を決めたり, 2^N - 1 - 番号 で上から続く0の数を数えたりするのに使う
+pub struct PriorityEncoder<const N: usize> where
+    [(); pow2(N)]: Sized,
+    [(); pow2(N) + 1]: Sized,
+    [(); N + 1]: Sized,
+{
+    encoder: MergeLayers<{pow2(N)}, {pow2(N) + 1}, {N + 1}>,
+}
+
+impl<const N: usize> Component<{pow2(N)}, {N + 1}> for PriorityEncoder<N> where
+    [(); pow2(N)]: Sized,
+    [(); pow2(N) + 1]: Sized,
+    [(); N + 1]: Sized,
+{
+    fn eval(&self, input: [bool; pow2(N)]) -> [bool; N + 1] {
+        self.encoder.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.encoder.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.encoder.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.encoder.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.encoder.netlist(netlist, input)
+    }
+}
+
+impl<const N: usize> PriorityEncoder<N> where
+    [(); pow2(N)]: Sized,
+    [(); pow2(N) + 0]: Sized,
+    [(); pow2(N) + 1]: Sized,
+    [(); N + 1]: Sized,
+    [(); 1 + pow2(N)]: Sized,
+    [(); (1 + pow2(N)) * pow2(N)]: Sized,
+    [(); (1 + pow2(N)) * pow2(N) + pow2(N)]: Sized,
+    [(); 1 * pow2(N)]: Sized,
+    [(); 1 * pow2(N) + 1]: Sized,
+    [(); 1 * N]: Sized,
+    [(); pow2(N - 1)]: Sized,
+    [(); pow2(N - 1) * N]: Sized,
+{
+    pub fn new() -> Self {
+        // 線ごとに (その線, それより上の線, 残りは0), 最後にどれかが1を見るためにすべての線
+        let zero = pow2(N);
+        let mut table = [zero; (1 + pow2(N)) * pow2(N) + pow2(N)];
+        for (i, chunk) in table.chunks_mut(1 + pow2(N)).take(pow2(N)).enumerate() {
+            chunk[0] = i;
+            for (v, upper) in chunk[1..].iter_mut().zip((i + 1)..pow2(N)) {
+                *v = upper;
+            }
+        }
+        for (i, v) in table.iter_mut().rev().take(pow2(N)).enumerate() {
+            *v = pow2(N) - 1 - i;
+        }
+        let layer1 = Wiring::<{pow2(N) + 1}, {(1 + pow2(N)) * pow2(N) + pow2(N)}>::create(table);
+        let layer2 = ConcatDifferentShapeBlocks::<{(1 + pow2(N)) * pow2(N)}, {pow2(N)}, {1 * pow2(N)}, 1>::create(
+            Box::new(ConcatBlocks::<{1 + pow2(N)}, 1, {pow2(N)}>::create_from_fn(highest_cell::<{pow2(N)}>)),
+            Box::new(Or::<{pow2(N)}>::new()),
+        );
+        let wrapper = Wiring::<{1 * pow2(N) + 1}, {pow2(N) + 1}>::wrapper();
+        let layer3 = ConcatDifferentShapeBlocks::<{pow2(N)}, 1, N, 1>::create(
+            Box::new(Encoder::<N>::new()),
+            Box::new(Buffer::new()),
+        );
+        let encoder = MergeLayers::create(Box::new(with_zero::<{pow2(N)}>()), Box::new(layer1))
+            .connect_to(Box::new(layer2))
+            .connect_to(Box::new(wrapper))
+            .connect_to(Box::new(layer3));
+        Self { encoder }
+    }
+}
+
+#[test]
+fn encoder_test() {
+    use crate::decoder::BitDecoder;
+    use crate::num_bit_converter::*;
+
+    // デコードしてエンコードすると元に戻る
+    let decoder = BitDecoder::<4>::new();
+    let encoder = Encoder::<4>::new();
+    for i in 0..16 {
+        let one_hot = decoder.eval(num_to_bit(i));
+        assert_eq!(bit_to_num(encoder.eval(one_hot)), i);
+        assert_eq!(decoder.eval(encoder.eval(one_hot)), one_hot);
+    }
+    assert_eq!(Encoder::<1>::new().eval([false, true]), [true]);
+}
+
+#[test]
+fn priority_encoder_test() {
+    use crate::decoder::BitDecoder;
+    use crate::num_bit_converter::*;
+
+    // 8本のすべての組み合わせで一番上の1の番号
+    let encoder = PriorityEncoder::<3>::new();
+    for i in 0..256_usize {
+        let output = bit_to_num(encoder.eval(num_to_bit(i)));
+        let expected = match i {
+            0 => 0,
+            _ => (i.ilog2() as usize) + (1 << 3),
+        };
+        assert_eq!(output, expected, "{:08b}", i);
+    }
+
+    // 1本だけならEncoderと同じで, デコードすると元に戻る
+    let decoder = BitDecoder::<4>::new();
+    let encoder = PriorityEncoder::<4>::new();
+    for i in 0..16 {
+        let output = encoder.eval(decoder.eval(num_to_bit(i)));
+        assert_eq!(bit_to_num(output), i + (1 << 4));
+        // 下の線に何が立っていても変わらない
+        let mut input = decoder.eval(num_to_bit(i));
+        input[..i].fill(true);
+        assert_eq!(encoder.eval(input), output);
+        // 上から続く0の数
+        let index = bit_to_num::<4>(output[..4].try_into().unwrap());
+        assert_eq!(15 - index, (1_u16 << i).leading_zeros() as usize);
+    }
+}
@@ -0,0 +1,262 @@
+use crate::core::*;
+
+// ゲートを使わずにVecで動きだけ真似たメモリ, 入出力はmemory::Memoryと同じ
+// read, write, address, value の順で, readのときだけ値を出す
+pub struct FastMemory<const Address: usize, const Bit: usize> {
+    // Bitずつ番地順に並べたもの
+    data: Vec<bool>,
+}
+
+impl<const Address: usize, const Bit: usize>
+    Component<{Address + Bit + 2}, Bit> for FastMemory<Address, Bit>
+where
+    [(); Address + Bit + 2]: Sized,
+{
+    fn eval(&self, input: [bool; Address + Bit + 2]) -> [bool; Bit] {
+        let mut output = [false; Bit];
+        if !input[0] {
+            return output;
+        }
+        // 読み書きを同時にすると書いた値が出る
+        if input[1] {
+            output.copy_from_slice(&input[(2 + Address)..]);
+        } else {
+            output.copy_from_slice(self.word(address::<Address>(&input)));
+        }
+        output
+    }
+    fn eval_mut(&mut self, input: [bool; Address + Bit + 2]) -> [bool; Bit] {
+        if input[1] {
+            let start = address::<Address>(&input) * Bit;
+            self.data[start..(start + Bit)].copy_from_slice(&input[(2 + Address)..]);
+        }
+        self.eval(input)
+    }
+    // 番地順に並べた中身, Simulatorの落ち着いたかの判定やhierarchy::set_stateで使う
+    fn state(&self) -> Vec<bool> {
+        self.data.clone()
+    }
+    fn set_state(&mut self, state: &[bool]) {
+        for (v, &s) in self.data.iter_mut().zip(state) {
+            *v = s;
+        }
+    }
+}
+
+impl<const Address: usize, const Bit: usize> FastMemory<Address, Bit> {
+    pub fn new() -> Self {
+        Self { data: vec![false; pow2(Address) * Bit] }
+    }
+    pub fn word(&self, address: usize) -> &[bool] {
+        &self.data[(address * Bit)..((address + 1) * Bit)]
+    }
+}
+
+// 入力のaddressの部分を番地にする
+fn address<const Address: usize>(input: &[bool]) -> usize {
+    let mut address = 0;
+    for i in 0..Address {
+        if input[2 + i] {
+            address |= 1 << i;
+        }
+    }
+    address
+}
+
+// Lockstepで最初に食い違ったところ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    // 何回目の読み書きか, 0から
+    pub access: usize,
+    pub address: usize,
+    pub input: Vec<bool>,
+    pub fast: Vec<bool>,
+    pub gate: Vec<bool>,
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bits = |v: &[bool]| v.iter().rev().map(|&b| if b { '1' } else { '0' }).collect::<String>();
+        write!(
+            f,
+            "access {} at address {:#x}: fast model gave {}, gate model gave {}",
+            self.access,
+            self.address,
+            bits(&self.fast),
+            bits(&self.gate)
+        )
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+// FastMemoryとゲートのメモリに同じ入力を流して出力を比べる, 出力はFastMemoryのもの
+// everyがNのときはN回に1回だけ比べる
+// 比べない読み出しはゲートのメモリを評価しないので速いが, 書き込みは状態を合わせるために毎回流す
+pub struct Lockstep<const Address: usize, const Bit: usize>
+where
+    [(); Address + Bit + 2]: Sized,
+{
+    fast: FastMemory<Address, Bit>,
+    gate: Box<dyn Component<{Address + Bit + 2}, Bit>>,
+    every: usize,
+    accesses: usize,
+    mismatch: Option<Mismatch>,
+}
+
+impl<const Address: usize, const Bit: usize>
+    Component<{Address + Bit + 2}, Bit> for Lockstep<Address, Bit>
+where
+    [(); Address + Bit + 2]: Sized,
+{
+    fn eval(&self, input: [bool; Address + Bit + 2]) -> [bool; Bit] {
+        self.fast.eval(input)
+    }
+    fn eval_mut(&mut self, input: [bool; Address + Bit + 2]) -> [bool; Bit] {
+        let output = self.fast.eval_mut(input);
+        let (read, write) = (input[0], input[1]);
+        if !read && !write {
+            return output;
+        }
+        let access = self.accesses;
+        self.accesses += 1;
+        let check = access.is_multiple_of(self.every);
+        if !check && !write {
+            return output;
+        }
+        let gate = self.gate.eval_mut(input);
+        if check && gate != output && self.mismatch.is_none() {
+            self.mismatch = Some(Mismatch {
+                access,
+                address: address::<Address>(&input),
+                input: input.to_vec(),
+                fast: output.to_vec(),
+                gate: gate.to_vec(),
+            });
+        }
+        output
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        vec![(None, &self.gate as &dyn Node)]
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        vec![(None, &mut self.gate as &mut dyn Node)]
+    }
+}
+
+impl<const Address: usize, const Bit: usize> Lockstep<Address, Bit>
+where
+    [(); Address + Bit + 2]: Sized,
+{
+    pub fn create(gate: Box<dyn Component<{Address + Bit + 2}, Bit>>) -> Self {
+        Self {
+            fast: FastMemory::new(),
+            gate,
+            every: 1,
+            accesses: 0,
+            mismatch: None,
+        }
+    }
+    // n回に1回だけ比べる
+    pub fn every(mut self, n: usize) -> Self {
+        assert!(n > 0, "cannot check every 0th access");
+        self.every = n;
+        self
+    }
+    pub fn accesses(&self) -> usize {
+        self.accesses
+    }
+    pub fn mismatch(&self) -> Option<&Mismatch> {
+        self.mismatch.as_ref()
+    }
+    pub fn check(&self) -> Result<(), Mismatch> {
+        match &self.mismatch {
+            Some(m) => Err(m.clone()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn fast_memory_test() {
+    use crate::num_bit_converter::*;
+
+    let read = 1;
+    let write = 2;
+    let mut memory = FastMemory::<16, 8>::new();
+    for i in 0..256 {
+        let addr = i * 251;
+        let num = 255 - i;
+        let input = num_to_bit::<26>((num << 18) + (addr << 2) + write);
+        assert_eq!(memory.eval_mut(input), [false; 8]);
+    }
+    for i in 0..256 {
+        let addr = i * 251;
+        let input = num_to_bit::<26>((addr << 2) + read);
+        assert_eq!(memory.eval_mut(input), num_to_bit(255 - i));
+    }
+    let input = num_to_bit::<26>((0x5a << 18) + (3 << 2) + write + read);
+    assert_eq!(memory.eval_mut(input), num_to_bit(0x5a));
+    assert_eq!(memory.word(3), &num_to_bit::<8>(0x5a));
+}
+
+#[test]
+fn fast_memory_state_test() {
+    use crate::hierarchy::collect_state;
+    use crate::num_bit_converter::*;
+
+    // 書き込むと内部状態として見え, set_stateで書く前に戻せる
+    let read = 1;
+    let write = 2;
+    let mut memory: Box<dyn Component<10, 4>> = Box::new(FastMemory::<4, 4>::new());
+    let before = collect_state(&memory);
+    memory.eval_mut(num_to_bit::<10>((0b0110 << 6) + (9 << 2) + write));
+    let after = collect_state(&memory);
+    assert_ne!(before, after);
+    assert_eq!(after[36..40], num_to_bit::<4>(0b0110));
+    memory.set_state(&before);
+    assert_eq!(memory.eval_mut(num_to_bit::<10>((9 << 2) + read)), [false; 4]);
+}
+
+#[test]
+fn lockstep_test() {
+    use crate::basic_comp::Constant;
+    use crate::memory::*;
+    use crate::num_bit_converter::*;
+
+    let inputs = {
+        let mut s: usize = 2024;
+        (0..1000)
+            .map(|_| {
+                s = s.wrapping_mul(1103515245).wrapping_add(12345) % (1 << 31);
+                num_to_bit::<10>(s >> 8)
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut lockstep = Lockstep::<4, 4>::create(Box::new(Memory::<4, 4>::new()));
+    let mut sparse = Lockstep::<4, 4>::create(Box::new(SparseMemory::<4, 4>::new())).every(7);
+    for &input in inputs.iter() {
+        lockstep.eval_mut(input);
+        sparse.eval_mut(input);
+    }
+    assert!(lockstep.check().is_ok());
+    assert!(sparse.check().is_ok());
+    assert!(lockstep.accesses() > 500);
+
+    // 常に0を返す壊れたメモリは1を書いて読んだところで見つかる
+    let mut broken = Lockstep::<4, 4>::create(Box::new(Constant::<10, 4, false>::new()));
+    let write = 2;
+    let read = 1;
+    broken.eval_mut(num_to_bit::<10>((0b0110 << 6) + (9 << 2) + write));
+    broken.eval_mut(num_to_bit::<10>((5 << 2) + read));
+    broken.eval_mut(num_to_bit::<10>((9 << 2) + read));
+    broken.eval_mut(num_to_bit::<10>((9 << 2) + read));
+    let mismatch = broken.mismatch().unwrap();
+    assert_eq!(mismatch.access, 2);
+    assert_eq!(mismatch.address, 9);
+    assert_eq!(mismatch.fast, num_to_bit::<4>(0b0110).to_vec());
+    assert_eq!(
+        mismatch.to_string(),
+        "access 2 at address 0x9: fast model gave 0110, gate model gave 0000"
+    );
+}
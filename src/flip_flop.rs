@@ -0,0 +1,328 @@
+use crate::core::*;
+use crate::basic_comp::*;
+use crate::memory::RSFlipFlop;
+
+// ゲート付きDラッチの入り口, en, d, set, reset -> RSFlipFlopへの Reset, Set
+// set, resetはenに関係なく効き, 両方1のときはresetを優先する
+fn latch_gate() -> MergeLayers<4, 5, 2> {
+    let layer1 = Wiring::<4, 6>::create([0, 1, 0, 1, 2, 3]);
+    let layer2 = ConcatBlocks::create([
+        Box::new(Buffer::new()) as Box<dyn Component<1, 1>>,
+        Box::new(Buffer::new()),
+        Box::new(Buffer::new()),
+        Box::new(Not::new()),
+        Box::new(Buffer::new()),
+        Box::new(Buffer::new()),
+    ]);
+    // en & d, en & !d, set, reset
+    let layer3 = ConcatDifferentShapeBlocks::<4, 2, 2, 2>::create(
+        Box::new(ConcatBlocks::<2, 1, 2>::create_from_fn(And::<2>::new)),
+        Box::new(Wiring::<2, 2>::buffer()),
+    );
+    // en & !d, reset, en & d, set, reset
+    let layer4 = Wiring::<4, 5>::create([1, 3, 0, 2, 3]);
+    let set_unless_reset = MergeLayers::create(
+        Box::new(ConcatDifferentShapeBlocks::<2, 1, 1, 1>::create(
+            Box::new(Or::<2>::new()),
+            Box::new(Not::new()),
+        )),
+        Box::new(And::<2>::new()),
+    );
+    let layer5 = ConcatDifferentShapeBlocks::<2, 3, 1, 1>::create(
+        Box::new(Or::<2>::new()),
+        Box::new(set_unless_reset),
+    );
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(layer3))
+        .connect_to(Box::new(layer4))
+        .connect_to(Box::new(layer5))
+}
+
+// sel, a, b -> selが1ならa, 0ならb
+pub fn select() -> MergeLayers<3, 2, 1> {
+    let layer1 = Wiring::<3, 4>::create([0, 1, 0, 2]);
+    let layer2 = ConcatBlocks::create([
+        Box::new(Buffer::new()) as Box<dyn Component<1, 1>>,
+        Box::new(Buffer::new()),
+        Box::new(Not::new()),
+        Box::new(Buffer::new()),
+    ]);
+    let layer3 = ConcatBlocks::<2, 1, 2>::create_from_fn(And::<2>::new);
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(layer3))
+        .connect_to(Box::new(Or::<2>::new()))
+}
+
+// マスタースレーブ型のDフリップフロップ, クロックの立ち上がりでdを取り込む
+// 入力は clock, d, enable, set, reset の順, 出力は Q, !Q
+// クロックが0の間はマスターがdを追いかけ, 1になった時点の値をスレーブが出す
+// enableが0のときは今のQを取り込み直すので変わらない, set, resetはクロックに関係なくすぐ効く
+// enable, master_gate, master, slave_gate, slave の名前でたどれる
+pub struct DFlipFlop {
+    enable: Box<dyn Component<3, 1>>,
+    // マスターはクロックを反転して使う
+    master_gate: Box<dyn Component<4, 2>>,
+    slave_gate: Box<dyn Component<4, 2>>,
+    master: Box<dyn Component<2, 2>>,
+    slave: Box<dyn Component<2, 2>>,
+}
+
+impl Component<5, 2> for DFlipFlop {
+    fn eval(&self, input: [bool; 5]) -> [bool; 2] {
+        let [clock, d, enable, set, reset] = input;
+        let d = self.enable.eval([enable, d, self.q()])[0];
+        let master = self.master.eval(self.master_gate.eval([clock, d, set, reset]));
+        self.slave.eval(self.slave_gate.eval([clock, master[0], set, reset]))
+    }
+    fn eval_mut(&mut self, input: [bool; 5]) -> [bool; 2] {
+        let [clock, d, enable, set, reset] = input;
+        let d = self.enable.eval([enable, d, self.q()])[0];
+        let master = self.master.eval_mut(self.master_gate.eval([clock, d, set, reset]));
+        self.slave.eval_mut(self.slave_gate.eval([clock, master[0], set, reset]))
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        vec![
+            (None, &self.enable as &dyn Node),
+            (None, &self.master_gate as &dyn Node),
+            (None, &self.master as &dyn Node),
+            (None, &self.slave_gate as &dyn Node),
+            (None, &self.slave as &dyn Node),
+        ]
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        vec![
+            (None, &mut self.enable as &mut dyn Node),
+            (None, &mut self.master_gate as &mut dyn Node),
+            (None, &mut self.master as &mut dyn Node),
+            (None, &mut self.slave_gate as &mut dyn Node),
+            (None, &mut self.slave as &mut dyn Node),
+        ]
+    }
+}
+
+impl DFlipFlop {
+    pub fn new() -> Self {
+        let invert_clock = ConcatBlocks::create([
+            Box::new(Not::new()) as Box<dyn Component<1, 1>>,
+            Box::new(Buffer::new()),
+            Box::new(Buffer::new()),
+            Box::new(Buffer::new()),
+        ]);
+        let master_gate = MergeLayers::create(Box::new(invert_clock), Box::new(latch_gate()));
+        let mut ff = Self {
+            enable: Box::new(Named::create("enable", Box::new(select()))),
+            master_gate: Box::new(Named::create("master_gate", Box::new(master_gate))),
+            slave_gate: Box::new(Named::create("slave_gate", Box::new(latch_gate()))),
+            master: Box::new(Named::create("master", Box::new(RSFlipFlop::new()))),
+            slave: Box::new(Named::create("slave", Box::new(RSFlipFlop::new()))),
+        };
+        // 0にしておく, DetectClockWakeと同じくクロックが1の状態から始める
+        ff.eval_mut([true, false, false, false, true]);
+        ff
+    }
+    pub fn q(&self) -> bool {
+        // 何も入れなければ今の値を出す
+        self.slave.eval([false, false])[0]
+    }
+}
+
+#[test]
+fn d_flip_flop_test() {
+    let mut ff = DFlipFlop::new();
+    let d = |clock, d| [clock, d, true, false, false];
+    assert_eq!(ff.eval_mut(d(false, true)), [false, true]);
+    // 立ち上がりで取り込む
+    assert_eq!(ff.eval_mut(d(true, true)), [true, false]);
+    // クロックが1の間は変わらない
+    assert_eq!(ff.eval_mut(d(true, false)), [true, false]);
+    assert_eq!(ff.eval_mut(d(false, false)), [true, false]);
+    assert_eq!(ff.eval_mut(d(true, false)), [false, true]);
+    // 立ち上がりと同時に変えたdは取り込まない
+    assert_eq!(ff.eval_mut(d(false, false)), [false, true]);
+    assert_eq!(ff.eval_mut(d(true, true)), [false, true]);
+
+    // enableが0なら保つ
+    assert_eq!(ff.eval_mut([false, true, false, false, false]), [false, true]);
+    assert_eq!(ff.eval_mut([true, true, false, false, false]), [false, true]);
+
+    // set, resetはクロックを待たない, 両方ならreset
+    assert_eq!(ff.eval_mut([true, false, true, true, false]), [true, false]);
+    assert_eq!(ff.eval_mut([true, false, true, false, false]), [true, false]);
+    assert_eq!(ff.eval_mut([false, true, true, false, true]), [false, true]);
+    assert_eq!(ff.eval_mut([true, true, true, false, false]), [false, true]);
+    assert_eq!(ff.eval_mut([false, true, true, true, true]), [false, true]);
+
+    // 取り込む値を決めるゲートもたどれる
+    for path in ["enable", "master_gate", "master", "slave_gate", "slave"] {
+        assert!(crate::hierarchy::find(&ff, path).is_some(), "{}", path);
+    }
+    // スレーブの入り口をsetに固定すると, クロックを待たずに1になる
+    assert!(crate::hierarchy::force(&mut ff, "slave_gate", Some(&[false, true])));
+    assert_eq!(ff.eval_mut([false, false, true, false, false]), [true, false]);
+    assert!(crate::hierarchy::force(&mut ff, "slave_gate", None));
+    assert_eq!(ff.eval_mut([false, false, true, false, false]), [true, false]);
+}
+
+#[test]
+fn d_flip_flop_with_clock_test() {
+    use crate::clock::*;
+
+    // 立ち上がりを検出したときだけQが変わり, 取り込むのはその直前のd
+    let mut clock = Clock::new();
+    let mut wake = DetectClockWake::new();
+    let mut ff = DFlipFlop::new();
+    let mut expected = false;
+    let mut last_d = false;
+    for i in 0..40 {
+        let c = clock.eval_mut([])[0];
+        let rising = wake.eval_mut([c])[0];
+        let d = (i * 7) % 5 < 2;
+        if rising {
+            expected = last_d;
+        }
+        assert_eq!(ff.eval_mut([c, d, true, false, false])[0], expected);
+        last_d = d;
+    }
+}
+
+// J, Kが 0, 0 なら保持, 1, 0 なら1, 0, 1 なら0, 1, 1 なら反転
+// 入力は clock, j, k, set, reset の順
+// next と DFlipFlopの中の名前でたどれる
+pub struct JKFlipFlop {
+    // j, k, q -> 次のq
+    next: Box<dyn Component<3, 1>>,
+    ff: DFlipFlop,
+}
+
+impl Component<5, 2> for JKFlipFlop {
+    fn eval(&self, input: [bool; 5]) -> [bool; 2] {
+        let [clock, j, k, set, reset] = input;
+        let d = self.next.eval([j, k, self.ff.q()])[0];
+        self.ff.eval([clock, d, true, set, reset])
+    }
+    fn eval_mut(&mut self, input: [bool; 5]) -> [bool; 2] {
+        let [clock, j, k, set, reset] = input;
+        let d = self.next.eval([j, k, self.ff.q()])[0];
+        self.ff.eval_mut([clock, d, true, set, reset])
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        let mut children = vec![(None, &self.next as &dyn Node)];
+        children.extend(self.ff.children());
+        children
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        let mut children = vec![(None, &mut self.next as &mut dyn Node)];
+        children.extend(self.ff.children_mut());
+        children
+    }
+    fn fuse_children(&mut self) {
+        self.ff.fuse_children()
+    }
+}
+
+impl JKFlipFlop {
+    pub fn new() -> Self {
+        // j & !q | !k & q
+        let layer1 = Wiring::<3, 4>::create([0, 2, 1, 2]);
+        let layer2 = ConcatBlocks::create([
+            Box::new(Buffer::new()) as Box<dyn Component<1, 1>>,
+            Box::new(Not::new()),
+            Box::new(Not::new()),
+            Box::new(Buffer::new()),
+        ]);
+        let layer3 = ConcatBlocks::<2, 1, 2>::create_from_fn(And::<2>::new);
+        let next = MergeLayers::create(Box::new(layer1), Box::new(layer2))
+            .connect_to(Box::new(layer3))
+            .connect_to(Box::new(Or::<2>::new()));
+        Self {
+            next: Box::new(Named::create("next", Box::new(next))),
+            ff: DFlipFlop::new(),
+        }
+    }
+    pub fn q(&self) -> bool {
+        self.ff.q()
+    }
+}
+
+#[test]
+fn jk_flip_flop_test() {
+    let mut ff = JKFlipFlop::new();
+    let mut q = false;
+    for i in 0..16 {
+        let (j, k) = (i & 1 != 0, i & 2 != 0);
+        ff.eval_mut([false, j, k, false, false]);
+        q = match (j, k) {
+            (false, false) => q,
+            (true, false) => true,
+            (false, true) => false,
+            (true, true) => !q,
+        };
+        assert_eq!(ff.eval_mut([true, j, k, false, false]), [q, !q]);
+        // クロックが1のまま入力を変えても変わらない
+        assert_eq!(ff.eval_mut([true, !j, !k, false, false]), [q, !q]);
+    }
+    ff.eval_mut([false, false, false, true, false]);
+    assert!(ff.q());
+    ff.eval_mut([false, false, false, false, true]);
+    assert!(!ff.q());
+}
+
+// tが1ならクロックの立ち上がりで反転する
+// 入力は clock, t, set, reset の順
+pub struct TFlipFlop {
+    ff: JKFlipFlop,
+}
+
+impl Component<4, 2> for TFlipFlop {
+    fn eval(&self, input: [bool; 4]) -> [bool; 2] {
+        let [clock, t, set, reset] = input;
+        self.ff.eval([clock, t, t, set, reset])
+    }
+    fn eval_mut(&mut self, input: [bool; 4]) -> [bool; 2] {
+        let [clock, t, set, reset] = input;
+        self.ff.eval_mut([clock, t, t, set, reset])
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.ff.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.ff.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.ff.fuse_children()
+    }
+}
+
+impl TFlipFlop {
+    pub fn new() -> Self {
+        Self { ff: JKFlipFlop::new() }
+    }
+    pub fn q(&self) -> bool {
+        self.ff.q()
+    }
+}
+
+#[test]
+fn t_flip_flop_test() {
+    use crate::clock::Clock;
+
+    // クロック2周期で1回反転するので, クロックを2分周したものになる
+    // 最初の1は立ち上がりとみなさない
+    let mut clock = Clock::new();
+    let mut ff = TFlipFlop::new();
+    let mut outputs = Vec::new();
+    for _ in 0..8 {
+        let c = clock.eval_mut([])[0];
+        outputs.push(ff.eval_mut([c, true, false, false])[0]);
+    }
+    assert_eq!(outputs, [false, false, true, true, false, false, true, true]);
+
+    // tが0なら変わらない
+    for _ in 0..4 {
+        let c = clock.eval_mut([])[0];
+        ff.eval_mut([c, false, false, false]);
+        assert!(!ff.q());
+    }
+    assert!(crate::hierarchy::find(&ff, "master").is_some());
+    assert!(crate::hierarchy::find(&ff, "next").is_some());
+}
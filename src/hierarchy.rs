@@ -0,0 +1,246 @@
+use crate::core::*;
+
+// パスは memory.bytes[37].cells[5].ff のように名前と添字をつなげたもの
+// rootからの相対で, root自身の名前は含めない
+fn join(path: &str, name: Option<&str>) -> String {
+    match name {
+        Some(name) if path.is_empty() => name.to_string(),
+        Some(name) => format!("{}.{}", path, name),
+        None => path.to_string(),
+    }
+}
+
+fn child_path(path: &str, index: Option<usize>) -> String {
+    match index {
+        Some(i) => format!("{}[{}]", path, i),
+        None => path.to_string(),
+    }
+}
+
+// 途中までのパスが一致していなければその下は探さない
+fn may_contain(path: &str, target: &str) -> bool {
+    target.starts_with(path)
+}
+
+// 名前か添字で新しいパスができた部品だけfに渡す
+fn walk<'a>(
+    node: &'a dyn Node,
+    path: String,
+    indexed: bool,
+    f: &mut dyn FnMut(&str, &'a dyn Node),
+) {
+    let path = join(&path, node.name());
+    if indexed || node.name().is_some() {
+        f(&path, node);
+    }
+    for (index, child) in node.children() {
+        walk(child, child_path(&path, index), index.is_some(), f);
+    }
+}
+
+fn find_node<'a>(node: &'a dyn Node, path: String, target: &str) -> Option<&'a dyn Node> {
+    let path = join(&path, node.name());
+    if path == target {
+        return Some(node);
+    }
+    if !may_contain(&path, target) {
+        return None;
+    }
+    node.children()
+        .into_iter()
+        .find_map(|(index, child)| find_node(child, child_path(&path, index), target))
+}
+
+fn find_node_mut<'a>(
+    node: &'a mut dyn Node,
+    path: String,
+    target: &str,
+) -> Option<&'a mut dyn Node> {
+    let path = join(&path, node.name());
+    if path == target {
+        return Some(node);
+    }
+    if !may_contain(&path, target) {
+        return None;
+    }
+    for (index, child) in node.children_mut() {
+        if let Some(found) = find_node_mut(child, child_path(&path, index), target) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+// 同じパスに複数の部品があるとき(名前のないMergeLayersなど)は一番外側のものを返す
+pub fn find<'a, const I: usize, const O: usize>(
+    root: &'a dyn Component<I, O>,
+    path: &str,
+) -> Option<&'a dyn Node> {
+    root.children()
+        .into_iter()
+        .find_map(|(index, child)| find_node(child, child_path("", index), path))
+}
+
+pub fn find_mut<'a, const I: usize, const O: usize>(
+    root: &'a mut dyn Component<I, O>,
+    path: &str,
+) -> Option<&'a mut dyn Node> {
+    for (index, child) in root.children_mut() {
+        if let Some(found) = find_node_mut(child, child_path("", index), path) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+// 名前か添字のついている部品とそのパスの一覧
+pub fn instances<const I: usize, const O: usize>(
+    root: &dyn Component<I, O>,
+) -> Vec<(String, &dyn Node)> {
+    let mut result = Vec::new();
+    for (index, child) in root.children() {
+        walk(child, child_path("", index), index.is_some(), &mut |path, node| {
+            result.push((path.to_string(), node))
+        });
+    }
+    result
+}
+
+pub fn paths<const I: usize, const O: usize>(root: &dyn Component<I, O>) -> Vec<String> {
+    instances(root).into_iter().map(|(path, _)| path).collect()
+}
+
+// Namedなら出力線の値, そうでなければ内部状態を読む
+pub fn probe<const I: usize, const O: usize>(
+    root: &dyn Component<I, O>,
+    path: &str,
+) -> Option<Vec<bool>> {
+    find(root, path).map(|node| node.probe().unwrap_or_else(|| node.state()))
+}
+
+pub fn force<const I: usize, const O: usize>(
+    root: &mut dyn Component<I, O>,
+    path: &str,
+    value: Option<&[bool]>,
+) -> bool {
+    find_mut(root, path).is_some_and(|node| node.force(value))
+}
+
+pub fn set_state<const I: usize, const O: usize>(
+    root: &mut dyn Component<I, O>,
+    path: &str,
+    state: &[bool],
+) -> bool {
+    match find_mut(root, path) {
+        Some(node) => {
+            node.set_state(state);
+            true
+        }
+        None => false,
+    }
+}
+
+// 部品の中にあるすべての内部状態を上から順に並べたもの
+pub fn collect_state(node: &dyn Node) -> Vec<bool> {
+    let mut state = node.state();
+    for (_, child) in node.children() {
+        state.extend(collect_state(child));
+    }
+    state
+}
+
+// collect_stateで並べた順に書き戻す, 使った長さを返す
+pub fn restore_state(node: &mut dyn Node, state: &[bool]) -> usize {
+    let mut used = node.state().len();
+    node.set_state(&state[..used]);
+    for (_, child) in node.children_mut() {
+        used += restore_state(child, &state[used..]);
+    }
+    used
+}
+
+// 部品の中の内部状態をcollect_stateと同じ順ですべてstatesへ移す
+// 状態を持たないと宣言しておらず, 移すこともできない部品があればfalse
+pub fn attach_all_state(node: &mut dyn Node, states: &SharedState) -> bool {
+    if !node.stateless() && !node.attach_state(states) {
+        return false;
+    }
+    node.children_mut().into_iter().all(|(_, child)| attach_all_state(child, states))
+}
+
+// 指定したパスの値を評価のたびに記録する
+pub struct Tracer {
+    paths: Vec<String>,
+    history: Vec<Vec<Option<Vec<bool>>>>,
+}
+
+impl Tracer {
+    pub fn new(paths: &[&str]) -> Self {
+        Self {
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+            history: Vec::new(),
+        }
+    }
+    pub fn sample<const I: usize, const O: usize>(&mut self, root: &dyn Component<I, O>) {
+        let values = self.paths.iter().map(|p| probe(root, p)).collect();
+        self.history.push(values);
+    }
+    // i番目のパスの記録
+    pub fn trace(&self, i: usize) -> Vec<Option<Vec<bool>>> {
+        self.history.iter().map(|values| values[i].clone()).collect()
+    }
+    pub fn history(&self) -> &[Vec<Option<Vec<bool>>>] {
+        &self.history
+    }
+}
+
+#[test]
+fn find_path_test() {
+    use crate::memory::MemoryByte;
+    use crate::num_bit_converter::*;
+
+    let mut byte = MemoryByte::<8>::new();
+    let write_flag = 2;
+    byte.eval_mut(num_to_bit::<10>((0b0010_0000 << 2) + write_flag));
+
+    let paths = paths(&byte);
+    assert!(paths.contains(&"cells".to_string()));
+    assert!(paths.contains(&"cells[7].ff".to_string()));
+    assert!(find(&byte, "cells[8].ff").is_none());
+
+    // 5番目だけ1が書き込まれている
+    for i in 0..8 {
+        let state = find(&byte, &format!("cells[{}].ff", i)).unwrap().state();
+        assert_eq!(state[0], i == 5);
+    }
+}
+
+#[test]
+fn probe_and_force_test() {
+    use crate::memory::MemoryByte;
+    use crate::num_bit_converter::*;
+
+    let mut byte = MemoryByte::<8>::new();
+    let read_flag = 1;
+    let write_flag = 2;
+    byte.eval_mut(num_to_bit::<10>((0b1010_1010 << 2) + write_flag));
+    assert_eq!(probe(&byte, "cells[1].ff"), Some(vec![true, false]));
+
+    // 内部状態を書き換える
+    assert!(set_state(&mut byte, "cells[0].ff", &[true, false]));
+    assert_eq!(byte.eval_mut(num_to_bit::<10>(read_flag)), num_to_bit(0b1010_1011));
+
+    // 出力線を固定する
+    assert!(force(&mut byte, "cells[7].ff", Some(&[false, true])));
+    assert_eq!(byte.eval_mut(num_to_bit::<10>(read_flag)), num_to_bit(0b0010_1011));
+    assert!(force(&mut byte, "cells[7].ff", None));
+    assert_eq!(byte.eval_mut(num_to_bit::<10>(read_flag)), num_to_bit(0b1010_1011));
+
+    let mut tracer = Tracer::new(&["cells[2].ff"]);
+    for v in [0b100, 0b000, 0b100] {
+        byte.eval_mut(num_to_bit::<10>((v << 2) + write_flag));
+        tracer.sample(&byte);
+    }
+    let q = tracer.trace(0).into_iter().map(|v| v.unwrap()[0]).collect::<Vec<_>>();
+    assert_eq!(q, vec![true, false, true]);
+}
@@ -0,0 +1,527 @@
+use std::path::Path;
+use crate::core::*;
+use crate::num_bit_converter::*;
+
+// メモリの中身をファイルから読み込んだりファイルに書き出したりする
+// 入力が read, write, address, value(8bit) の並びならMemoryでもFastMemoryでも使える
+// 入力の幅Iから番地の本数 I - 10 を決める
+
+#[derive(Debug)]
+pub enum LoadError {
+    // 行番号は1から
+    InvalidRecord { line: usize, reason: String },
+    Checksum { line: usize, expected: u8, found: u8 },
+    OutOfRange { address: usize, size: usize },
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::InvalidRecord { line, reason } => write!(f, "line {}: {}", line, reason),
+            LoadError::Checksum { line, expected, found } => write!(
+                f,
+                "line {}: checksum mismatch, record says {:#04x} but its bytes give {:#04x}",
+                line, found, expected
+            ),
+            LoadError::OutOfRange { address, size } => write!(
+                f,
+                "address {:#x} is outside the memory ({:#x} bytes)",
+                address, size
+            ),
+            LoadError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<std::io::Error> for LoadError {
+    fn from(e: std::io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+// 読み込みではファイル上の番地(生のバイナリなら先頭からの位置)にbaseを足した番地に書く
+// lengthがあればファイル上の番地がlength未満のものだけ書く
+// 書き出しではbaseからlengthバイト, lengthがなければメモリの最後まで
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Region {
+    pub base: usize,
+    pub length: Option<usize>,
+}
+
+impl Region {
+    pub fn all() -> Self {
+        Self::default()
+    }
+    pub fn at(base: usize) -> Self {
+        Self { base, length: None }
+    }
+    pub fn length(mut self, length: usize) -> Self {
+        self.length = Some(length);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Binary,
+    IntelHex,
+    SRecord,
+}
+
+impl Format {
+    // 拡張子から決める, わからなければNone
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "bin" | "img" | "rom" => Some(Format::Binary),
+            "hex" | "ihex" | "ihx" => Some(Format::IntelHex),
+            "srec" | "s19" | "s28" | "s37" | "mot" => Some(Format::SRecord),
+            _ => None,
+        }
+    }
+}
+
+pub fn memory_size<const I: usize>() -> usize {
+    1 << (I - 10)
+}
+
+fn access<const I: usize>(read: bool, write: bool, address: usize, value: u8) -> [bool; I] {
+    let mut input = [false; I];
+    input[0] = read;
+    input[1] = write;
+    for (i, v) in input[2..(I - 8)].iter_mut().enumerate() {
+        *v = address & (1 << i) != 0;
+    }
+    input[(I - 8)..].copy_from_slice(&num_to_bit::<8>(value as usize));
+    input
+}
+
+pub fn write_byte<const I: usize>(
+    memory: &mut dyn Component<I, 8>,
+    address: usize,
+    value: u8,
+) -> Result<(), LoadError> {
+    let size = memory_size::<I>();
+    if address >= size {
+        return Err(LoadError::OutOfRange { address, size });
+    }
+    memory.eval_mut(access(false, true, address, value));
+    Ok(())
+}
+
+pub fn read_byte<const I: usize>(memory: &dyn Component<I, 8>, address: usize) -> u8 {
+    bit_to_num(memory.eval(access(true, false, address, 0))) as u8
+}
+
+// ファイル上の番地offsetの値をregionに従って書く, 書いたらtrue
+fn store<const I: usize>(
+    memory: &mut dyn Component<I, 8>,
+    region: Region,
+    offset: usize,
+    value: u8,
+) -> Result<bool, LoadError> {
+    if region.length.is_some_and(|length| offset >= length) {
+        return Ok(false);
+    }
+    write_byte(memory, region.base + offset, value)?;
+    Ok(true)
+}
+
+fn dump_range<const I: usize>(region: Region) -> Result<std::ops::Range<usize>, LoadError> {
+    let size = memory_size::<I>();
+    let end = region.length.map_or(size, |length| region.base + length);
+    if region.base > size || end > size {
+        return Err(LoadError::OutOfRange { address: end.max(region.base), size });
+    }
+    Ok(region.base..end)
+}
+
+// 書いたバイト数を返す
+pub fn load_binary<const I: usize>(
+    memory: &mut dyn Component<I, 8>,
+    data: &[u8],
+    region: Region,
+) -> Result<usize, LoadError> {
+    let mut written = 0;
+    for (offset, &value) in data.iter().enumerate() {
+        if store(memory, region, offset, value)? {
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+pub fn dump_binary<const I: usize>(
+    memory: &dyn Component<I, 8>,
+    region: Region,
+) -> Result<Vec<u8>, LoadError> {
+    Ok(dump_range::<I>(region)?.map(|address| read_byte(memory, address)).collect())
+}
+
+// 16進の文字列をバイト列にする
+fn decode_hex(text: &str, line: usize) -> Result<Vec<u8>, LoadError> {
+    let invalid = |reason: &str| LoadError::InvalidRecord { line, reason: reason.to_string() };
+    if !text.len().is_multiple_of(2) {
+        return Err(invalid("odd number of hex digits"));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..(i + 2))
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| invalid("invalid hex digit"))
+        })
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn byte_sum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+// Intel HEXのチェックサムは和の2の補数
+fn ihex_checksum(bytes: &[u8]) -> u8 {
+    byte_sum(bytes).wrapping_neg()
+}
+
+// S-recordのチェックサムは和の1の補数
+fn srec_checksum(bytes: &[u8]) -> u8 {
+    !byte_sum(bytes)
+}
+
+// 最後の1バイトをチェックサムとして確かめて, それより前を返す
+fn split_checksum(
+    bytes: &[u8],
+    line: usize,
+    checksum: fn(&[u8]) -> u8,
+) -> Result<&[u8], LoadError> {
+    let (&found, body) = bytes.split_last().ok_or_else(|| LoadError::InvalidRecord {
+        line,
+        reason: "empty record".to_string(),
+    })?;
+    let expected = checksum(body);
+    if expected != found {
+        return Err(LoadError::Checksum { line, expected, found });
+    }
+    Ok(body)
+}
+
+// 終わりのレコード(01)までに書いたバイト数を返す
+pub fn load_ihex<const I: usize>(
+    memory: &mut dyn Component<I, 8>,
+    text: &str,
+    region: Region,
+) -> Result<usize, LoadError> {
+    let mut upper = 0;
+    let mut written = 0;
+    for (n, record) in text.lines().enumerate() {
+        let line = n + 1;
+        let record = record.trim();
+        if record.is_empty() {
+            continue;
+        }
+        let invalid = |reason: &str| LoadError::InvalidRecord { line, reason: reason.to_string() };
+        let hex = record.strip_prefix(':').ok_or_else(|| invalid("record does not start with ':'"))?;
+        let bytes = decode_hex(hex, line)?;
+        let body = split_checksum(&bytes, line, ihex_checksum)?;
+        if body.len() < 4 || body.len() != 4 + body[0] as usize {
+            return Err(invalid("length field does not match the record"));
+        }
+        let address = (body[1] as usize) << 8 | body[2] as usize;
+        let data = &body[4..];
+        match body[3] {
+            0x00 => {
+                for (i, &value) in data.iter().enumerate() {
+                    if store(memory, region, upper + address + i, value)? {
+                        written += 1;
+                    }
+                }
+            }
+            0x01 => return Ok(written),
+            // 拡張セグメントアドレス, 16倍して足す
+            0x02 if data.len() == 2 => upper = ((data[0] as usize) << 8 | data[1] as usize) << 4,
+            // 拡張リニアアドレス, 上位16bit
+            0x04 if data.len() == 2 => upper = ((data[0] as usize) << 8 | data[1] as usize) << 16,
+            // 開始アドレスはメモリには関係ない
+            0x03 | 0x05 => {}
+            0x02 | 0x04 => return Err(invalid("address record must have 2 data bytes")),
+            t => return Err(invalid(&format!("unknown record type {:02X}", t))),
+        }
+    }
+    Err(LoadError::InvalidRecord {
+        line: text.lines().count(),
+        reason: "missing end of file record".to_string(),
+    })
+}
+
+fn ihex_record(address: usize, kind: u8, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8, (address >> 8) as u8, address as u8, kind];
+    bytes.extend_from_slice(data);
+    bytes.push(ihex_checksum(&bytes));
+    format!(":{}\n", encode_hex(&bytes))
+}
+
+// 1行16バイト, 64KBを超えるところは拡張リニアアドレスを使う
+pub fn dump_ihex<const I: usize>(
+    memory: &dyn Component<I, 8>,
+    region: Region,
+) -> Result<String, LoadError> {
+    let range = dump_range::<I>(region)?;
+    let mut text = String::new();
+    let mut upper = 0;
+    let mut address = range.start;
+    while address < range.end {
+        if address >> 16 != upper {
+            upper = address >> 16;
+            text += &ihex_record(0, 0x04, &[(upper >> 8) as u8, upper as u8]);
+        }
+        // 64KBの境目をまたがないように切る
+        let end = range.end.min(address + 16).min((upper + 1) << 16);
+        let data = (address..end).map(|a| read_byte(memory, a)).collect::<Vec<_>>();
+        text += &ihex_record(address & 0xffff, 0x00, &data);
+        address = end;
+    }
+    text += &ihex_record(0, 0x01, &[]);
+    Ok(text)
+}
+
+// S7, S8, S9のいずれかまでに書いたバイト数を返す, 終わりのレコードがなくてもよい
+pub fn load_srec<const I: usize>(
+    memory: &mut dyn Component<I, 8>,
+    text: &str,
+    region: Region,
+) -> Result<usize, LoadError> {
+    let mut written = 0;
+    for (n, record) in text.lines().enumerate() {
+        let line = n + 1;
+        let record = record.trim();
+        if record.is_empty() {
+            continue;
+        }
+        let invalid = |reason: &str| LoadError::InvalidRecord { line, reason: reason.to_string() };
+        let hex = record.strip_prefix('S').ok_or_else(|| invalid("record does not start with 'S'"))?;
+        let kind = hex.chars().next().ok_or_else(|| invalid("missing record type"))?;
+        let bytes = decode_hex(&hex[kind.len_utf8()..], line)?;
+        let body = split_checksum(&bytes, line, srec_checksum)?;
+        if body.is_empty() || body[0] as usize != body.len() {
+            return Err(invalid("byte count does not match the record"));
+        }
+        let address_width = match kind {
+            '0' | '1' | '5' | '9' => 2,
+            '2' | '6' | '8' => 3,
+            '3' | '7' => 4,
+            _ => return Err(invalid(&format!("unknown record type S{}", kind))),
+        };
+        if body.len() < 1 + address_width {
+            return Err(invalid("record is too short for its address"));
+        }
+        let address = body[1..(1 + address_width)]
+            .iter()
+            .fold(0, |a, &b| a << 8 | b as usize);
+        let data = &body[(1 + address_width)..];
+        match kind {
+            '1' | '2' | '3' => {
+                for (i, &value) in data.iter().enumerate() {
+                    if store(memory, region, address + i, value)? {
+                        written += 1;
+                    }
+                }
+            }
+            '7' | '8' | '9' => return Ok(written),
+            // ヘッダとレコード数は読み飛ばす
+            _ => {}
+        }
+    }
+    Ok(written)
+}
+
+fn srec_record(kind: u8, address: usize, address_width: usize, data: &[u8]) -> String {
+    let mut bytes = vec![(address_width + data.len() + 1) as u8];
+    bytes.extend((0..address_width).rev().map(|i| (address >> (8 * i)) as u8));
+    bytes.extend_from_slice(data);
+    bytes.push(srec_checksum(&bytes));
+    format!("S{}{}\n", kind, encode_hex(&bytes))
+}
+
+// 番地の幅は範囲の最後の番地に合わせてS1/S2/S3から選ぶ
+pub fn dump_srec<const I: usize>(
+    memory: &dyn Component<I, 8>,
+    region: Region,
+) -> Result<String, LoadError> {
+    let range = dump_range::<I>(region)?;
+    let last = range.end.saturating_sub(1);
+    let (data_kind, end_kind, width) = match last {
+        0..=0xffff => (1, 9, 2),
+        0x1_0000..=0xff_ffff => (2, 8, 3),
+        _ => (3, 7, 4),
+    };
+    let mut text = srec_record(0, 0, 2, &[]);
+    let mut address = range.start;
+    while address < range.end {
+        let end = range.end.min(address + 16);
+        let data = (address..end).map(|a| read_byte(memory, a)).collect::<Vec<_>>();
+        text += &srec_record(data_kind, address, width, &data);
+        address = end;
+    }
+    text += &srec_record(end_kind, 0, width, &[]);
+    Ok(text)
+}
+
+pub fn load_file<const I: usize>(
+    memory: &mut dyn Component<I, 8>,
+    path: impl AsRef<Path>,
+    format: Format,
+    region: Region,
+) -> Result<usize, LoadError> {
+    match format {
+        Format::Binary => load_binary(memory, &std::fs::read(path)?, region),
+        Format::IntelHex => load_ihex(memory, &std::fs::read_to_string(path)?, region),
+        Format::SRecord => load_srec(memory, &std::fs::read_to_string(path)?, region),
+    }
+}
+
+pub fn dump_file<const I: usize>(
+    memory: &dyn Component<I, 8>,
+    path: impl AsRef<Path>,
+    format: Format,
+    region: Region,
+) -> Result<(), LoadError> {
+    match format {
+        Format::Binary => std::fs::write(path, dump_binary(memory, region)?)?,
+        Format::IntelHex => std::fs::write(path, dump_ihex(memory, region)?)?,
+        Format::SRecord => std::fs::write(path, dump_srec(memory, region)?)?,
+    }
+    Ok(())
+}
+
+#[test]
+fn binary_image_test() {
+    use crate::fast_memory::FastMemory;
+    use crate::memory::Memory;
+
+    let data = [0xde, 0xad, 0xbe, 0xef, 0x01];
+    let mut gate = Memory::<4, 8>::new();
+    assert_eq!(load_binary(&mut gate, &data, Region::at(3)).unwrap(), 5);
+    assert_eq!(dump_binary(&gate, Region::at(2).length(7)).unwrap(), [0, 0xde, 0xad, 0xbe, 0xef, 0x01, 0]);
+
+    let mut fast = FastMemory::<4, 8>::new();
+    assert_eq!(load_binary(&mut fast, &data, Region::at(14).length(2)).unwrap(), 2);
+    assert_eq!(dump_binary(&fast, Region::at(14)).unwrap(), [0xde, 0xad]);
+    assert!(matches!(
+        load_binary(&mut fast, &data, Region::at(14)),
+        Err(LoadError::OutOfRange { address: 16, size: 16 })
+    ));
+    assert!(dump_binary(&fast, Region::at(10).length(7)).is_err());
+}
+
+#[test]
+fn ihex_image_test() {
+    use crate::fast_memory::FastMemory;
+    use crate::memory::SparseMemory;
+
+    let text = "\
+:10010000214601360121470136007EFE09D2190140
+:100110002146017E17C20001FF5F16002148011928
+:10012000194E79234623965778239EDA3F01B2CAA7
+:100130003F0156702B5E712B722B732146013421C7
+:00000001FF
+";
+    let mut sparse = SparseMemory::<16, 8>::new();
+    assert_eq!(load_ihex(&mut sparse, text, Region::all()).unwrap(), 64);
+    assert_eq!(sparse.materialized(), 64);
+    assert_eq!(read_byte(&sparse, 0x100), 0x21);
+    assert_eq!(read_byte(&sparse, 0x13f), 0x21);
+    assert_eq!(dump_ihex(&sparse, Region::at(0x100).length(64)).unwrap(), text);
+
+    // 64KBを超える番地は拡張リニアアドレスで書く
+    let mut fast = FastMemory::<17, 8>::new();
+    load_binary(&mut fast, &[1, 2, 3, 4], Region::at(0xfffe)).unwrap();
+    let dumped = dump_ihex(&fast, Region::at(0xfffe).length(4)).unwrap();
+    assert_eq!(
+        dumped,
+        ":02FFFE000102FE\n:020000040001F9\n:020000000304F7\n:00000001FF\n"
+    );
+    let mut reloaded = FastMemory::<17, 8>::new();
+    assert_eq!(load_ihex(&mut reloaded, &dumped, Region::all()).unwrap(), 4);
+    assert_eq!(dump_binary(&reloaded, Region::at(0xfffe).length(4)).unwrap(), [1, 2, 3, 4]);
+}
+
+#[test]
+fn ihex_error_test() {
+    use crate::fast_memory::FastMemory;
+
+    let mut memory = FastMemory::<16, 8>::new();
+    let broken = ":0300300002337A1F\n:00000001FF\n";
+    let error = load_ihex(&mut memory, broken, Region::all()).unwrap_err();
+    assert!(matches!(error, LoadError::Checksum { line: 1, expected: 0x1e, found: 0x1f }));
+    assert_eq!(
+        error.to_string(),
+        "line 1: checksum mismatch, record says 0x1f but its bytes give 0x1e"
+    );
+    assert!(matches!(
+        load_ihex(&mut memory, ":0300300002337A1E\n", Region::all()),
+        Err(LoadError::InvalidRecord { line: 1, .. })
+    ));
+    assert!(matches!(
+        load_ihex(&mut memory, "0300300002337A1E\n", Region::all()),
+        Err(LoadError::InvalidRecord { line: 1, .. })
+    ));
+}
+
+#[test]
+fn srec_image_test() {
+    use crate::fast_memory::FastMemory;
+    use crate::memory::SparseMemory;
+
+    let text = "\
+S00F000068656C6C6F202020202000003C
+S11F00007C0802A6900100049421FFF07C6C1B787C8C23783C6000003863000026
+S11F001C4BFFFFE5398000007D83637880010014382100107C0803A64E800020E9
+S111003848656C6C6F20776F726C642E0A0042
+S5030003F9
+S9030000FC
+";
+    let mut sparse = SparseMemory::<16, 8>::new();
+    assert_eq!(load_srec(&mut sparse, text, Region::all()).unwrap(), 70);
+    let hello = dump_binary(&sparse, Region::at(0x38).length(14)).unwrap();
+    assert_eq!(hello, b"Hello world.\n\0");
+
+    let mut fast = FastMemory::<8, 8>::new();
+    load_srec(&mut fast, text, Region::at(0x10)).unwrap();
+    let dumped = dump_srec(&fast, Region::at(0x10).length(70)).unwrap();
+    let mut reloaded = FastMemory::<8, 8>::new();
+    assert_eq!(load_srec(&mut reloaded, &dumped, Region::all()).unwrap(), 70);
+    assert_eq!(dump_binary(&reloaded, Region::all()).unwrap(), dump_binary(&fast, Region::all()).unwrap());
+
+    let broken = text.replace("S5030003F9", "S5030003F8");
+    let error = load_srec(&mut fast, &broken, Region::all()).unwrap_err();
+    assert!(matches!(error, LoadError::Checksum { line: 5, expected: 0xf9, found: 0xf8 }));
+}
+
+#[test]
+fn image_file_test() {
+    use crate::fast_memory::FastMemory;
+
+    let mut memory = FastMemory::<8, 8>::new();
+    load_binary(&mut memory, b"computer", Region::at(0x40)).unwrap();
+    let dir = std::env::temp_dir();
+    for name in ["image_file_test.bin", "image_file_test.hex", "image_file_test.s19"] {
+        let path = dir.join(name);
+        let format = Format::from_path(&path).unwrap();
+        dump_file(&memory, &path, format, Region::at(0x40).length(8)).unwrap();
+        let mut loaded = FastMemory::<8, 8>::new();
+        let region = if format == Format::Binary { Region::at(0x40) } else { Region::all() };
+        assert_eq!(load_file(&mut loaded, &path, format, region).unwrap(), 8);
+        assert_eq!(dump_binary(&loaded, Region::at(0x40).length(8)).unwrap(), b"computer");
+        std::fs::remove_file(&path).unwrap();
+    }
+    assert!(matches!(
+        load_file(&mut memory, dir.join("image_file_test_missing.hex"), Format::IntelHex, Region::all()),
+        Err(LoadError::Io(_))
+    ));
+}
@@ -0,0 +1,257 @@
+use crate::core::*;
+use crate::hierarchy::instances;
+use crate::netlist::{flatten, flatten_node, Gate, Netlist};
+use crate::optimize::{propagate_constants, structural_hash};
+
+// ゲートに展開できない部品は入力を試して調べる
+// 入力がこの本数以下なら全パターンを試し, それより多ければ乱数で選んだ入力で調べる
+const EXHAUSTIVE_LIMIT: usize = 12;
+const SAMPLES: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LintKind {
+    // どの出力にもつながっていない入力
+    UnusedInput(usize),
+    ConstantOutput(usize, bool),
+    // 後の出力が前の出力と同じ線
+    DuplicateOutput(usize, usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub path: String,
+    pub kind: LintKind,
+    // ゲートのつながりから確かめたものはtrue
+    // 内部状態があって展開できず, 入力を試して見つけただけのものはfalse
+    pub confirmed: bool,
+}
+
+impl Lint {
+    // 確かめられたものだけを誤りとして扱う, 試しただけのものは見逃しも誤検出もありうる
+    pub fn is_error(&self) -> bool {
+        self.confirmed
+    }
+}
+
+// 定数を畳んで同じゲートをまとめてから出力の側からつながりをたどる
+// x & !x のような恒等式までは見ないので見逃しはあるが, 見つけたものは必ず正しい
+fn lint_netlist(netlist: &Netlist) -> Vec<LintKind> {
+    let (folded, _) = propagate_constants(netlist);
+    let (netlist, _) = structural_hash(&folded);
+    let gates = netlist.gates();
+    let outputs = netlist.outputs();
+    let mut live = vec![false; gates.len()];
+    for &n in outputs {
+        live[n] = true;
+    }
+    for n in (0..gates.len()).rev() {
+        if live[n] {
+            for &m in gates[n].inputs() {
+                live[m] = true;
+            }
+        }
+    }
+
+    let unused = gates.iter()
+        .zip(live.iter())
+        .filter_map(|(gate, &l)| match gate {
+            Gate::Input(i) if !l => Some(LintKind::UnusedInput(*i)),
+            _ => None,
+        });
+    let constant = outputs.iter()
+        .enumerate()
+        .filter_map(|(o, &n)| match gates[n] {
+            Gate::Const(v) => Some(LintKind::ConstantOutput(o, v)),
+            _ => None,
+        });
+    let duplicate = outputs.iter()
+        .enumerate()
+        .filter_map(|(o, n)| {
+            outputs[..o].iter().position(|m| m == n).map(|p| LintKind::DuplicateOutput(p, o))
+        });
+    unused.chain(constant).chain(duplicate).collect()
+}
+
+// xorshift, 再現性のために種は固定
+struct Samples {
+    s: u64,
+}
+
+impl Samples {
+    fn new() -> Self {
+        Self { s: 0x2545_f491_4f6c_dd1d }
+    }
+    fn next(&mut self, width: usize) -> Vec<bool> {
+        (0..width)
+            .map(|_| {
+                self.s ^= self.s << 13;
+                self.s ^= self.s >> 7;
+                self.s ^= self.s << 17;
+                self.s & 1 == 1
+            })
+            .collect()
+    }
+}
+
+fn inputs_to_check(width: usize) -> Vec<Vec<bool>> {
+    if width <= EXHAUSTIVE_LIMIT {
+        return (0..1_usize << width)
+            .map(|n| (0..width).map(|i| n & (1 << i) != 0).collect())
+            .collect();
+    }
+    let mut samples = Samples::new();
+    let mut inputs = vec![vec![false; width], vec![true; width]];
+    inputs.extend((0..SAMPLES).map(|_| samples.next(width)));
+    inputs
+}
+
+// evalだけを使うので内部状態は変わらず, いまの状態での振る舞いしか見ない
+// 全パターンを試しても状態が違えば結果が変わりうるので確かめたことにはならない
+fn lint_by_eval(
+    inputs: usize,
+    outputs: usize,
+    eval: &dyn Fn(&[bool]) -> Vec<bool>,
+) -> Vec<LintKind> {
+    let mut used = vec![false; inputs];
+    let mut seen = vec![[false; 2]; outputs];
+    let mut same_as = (0..outputs)
+        .map(|o| (0..o).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    for input in inputs_to_check(inputs) {
+        let output = eval(&input);
+        for (o, &v) in output.iter().enumerate() {
+            seen[o][v as usize] = true;
+            same_as[o].retain(|&p| output[p] == v);
+        }
+        for i in 0..inputs {
+            if used[i] {
+                continue;
+            }
+            let mut flipped = input.clone();
+            flipped[i] = !flipped[i];
+            used[i] = eval(&flipped) != output;
+        }
+    }
+
+    let unused = used.iter()
+        .enumerate()
+        .filter(|(_, &u)| !u)
+        .map(|(i, _)| LintKind::UnusedInput(i));
+    let constant = seen.iter()
+        .enumerate()
+        .filter(|(_, s)| s[0] != s[1])
+        .map(|(o, s)| LintKind::ConstantOutput(o, s[1]));
+    let duplicate = same_as.iter()
+        .enumerate()
+        .filter_map(|(o, p)| p.first().map(|&p| LintKind::DuplicateOutput(p, o)));
+    unused.chain(constant).chain(duplicate).collect()
+}
+
+// ゲートに展開できればそのつながりを調べ, できなければ入力を試す
+fn lint_block(
+    path: &str,
+    netlist: Option<Netlist>,
+    inputs: usize,
+    outputs: usize,
+    eval: &dyn Fn(&[bool]) -> Vec<bool>,
+) -> Vec<Lint> {
+    let (kinds, confirmed) = match netlist {
+        Some(netlist) => (lint_netlist(&netlist), true),
+        None => (lint_by_eval(inputs, outputs, eval), false),
+    };
+    kinds.into_iter()
+        .map(|kind| Lint { path: path.to_string(), kind, confirmed })
+        .collect()
+}
+
+// rootと名前か添字のついた部品をすべて調べる
+// Wiringなど名前のない中間の部品は分岐や切り捨てが本来の役目なので対象外
+pub fn lint<const I: usize, const O: usize>(root: &dyn Component<I, O>) -> Vec<Lint> {
+    let mut result = lint_block("", flatten(root), I, O, &|input| {
+        let mut fixed = [false; I];
+        fixed.copy_from_slice(input);
+        root.eval(fixed).to_vec()
+    });
+    for (path, node) in instances(root) {
+        result.extend(lint_block(&path, flatten_node(node), node.inputs(), node.outputs(), &|input| {
+            node.eval_dyn(input)
+        }));
+    }
+    result
+}
+
+#[test]
+fn lint_test() {
+    use crate::basic_comp::*;
+
+    let bad = MergeLayers::create(
+        Box::new(Wiring::<3, 3>::create([0, 0, 2])),
+        Box::new(ConcatDifferentShapeBlocks::create(
+            Box::new(Wiring::<2, 2>::buffer()),
+            Box::new(Constant::<1, 1, true>::new()),
+        )),
+    );
+    let lints = lint(&bad);
+    let root = |kind| Lint { path: "".to_string(), kind, confirmed: true };
+    assert_eq!(
+        lints,
+        vec![
+            root(LintKind::UnusedInput(1)),
+            root(LintKind::UnusedInput(2)),
+            root(LintKind::ConstantOutput(2, true)),
+            root(LintKind::DuplicateOutput(0, 1)),
+        ]
+    );
+
+    let adder = ConcatBlocks::<3, 2, 2>::create_from_fn(crate::arithmetic_comp::FullAdder::new)
+        .named("adders");
+    assert!(lint(&adder).is_empty());
+}
+
+#[test]
+fn lint_finds_named_block_test() {
+    use crate::basic_comp::*;
+
+    let block = ConcatBlocks::create([
+        Box::new(And::<2>::new()) as Box<dyn Component<2, 1>>,
+        Box::new(Named::create("stuck", Box::new(Constant::<2, 1, false>::new()))),
+    ]);
+    let lints = lint(&block);
+    assert!(lints.contains(&Lint {
+        path: "[1].stuck".to_string(),
+        kind: LintKind::ConstantOutput(0, false),
+        confirmed: true,
+    }));
+    assert!(lints.contains(&Lint {
+        path: "[1].stuck".to_string(),
+        kind: LintKind::UnusedInput(1),
+        confirmed: true,
+    }));
+    assert!(lints.contains(&Lint {
+        path: "".to_string(),
+        kind: LintKind::UnusedInput(3),
+        confirmed: true,
+    }));
+    assert!(!lints.iter().any(|l| l.path == "[0]"));
+}
+
+#[test]
+fn lint_wide_block_test() {
+    use crate::arithmetic_comp::FullAdder;
+    use crate::memory::MemoryCell;
+
+    // 乱数で試すしかない幅でもつながりを見るので何も出ない
+    let adder = RecurrentBlock::<1, 2, 1, 8>::create_from_fn(FullAdder::new).named("adder");
+    assert!(lint(&adder).is_empty());
+
+    // 内部状態を持つものは入力を試すだけなので, 見つけても誤りとはしない
+    // フリップフロップはいまの状態のまま1段だけ評価するとQの反転が1に見える
+    let lints = lint(&MemoryCell::new());
+    assert!(lints.contains(&Lint {
+        path: "ff".to_string(),
+        kind: LintKind::ConstantOutput(1, true),
+        confirmed: false,
+    }));
+    assert!(lints.iter().all(|l| !l.is_error()));
+}
@@ -8,6 +8,26 @@ mod arithmetic_comp;
 mod num_bit_converter;
 mod clock;
 mod computer;
+mod hierarchy;
+mod lint;
+mod optimize;
+mod netlist;
+mod techmap;
+mod fast_memory;
+mod image;
+mod rom;
+mod register_file;
+mod flip_flop;
+mod counter;
+mod shift_register;
+mod simulator;
+mod adder;
+mod multiplier;
+mod divider;
+mod alu;
+mod barrel_shifter;
+mod mux;
+mod encoder;
 
 fn main() {
 }
@@ -1,4 +1,7 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
 use crate::core::*;
+use crate::netlist::{Net, Netlist};
 use crate::basic_comp::*;
 use crate::decoder::*;
 
@@ -7,6 +10,8 @@ pub struct RSFlipFlop {
     ff: MergeLayers<4, 4, 2>,
     nand1_to_nand2_line_state: bool,
     nand2_to_rand1_line_state: bool,
+    // Flyweightで共有したときは線の状態をここに置く
+    shared: Option<(SharedState, usize)>,
 }
 
 impl Component<2, 2> for RSFlipFlop {
@@ -14,13 +19,39 @@ impl Component<2, 2> for RSFlipFlop {
         self.ff.eval(self.input_with_cache(input))
     }
     fn eval_mut(&mut self, input: [bool; 2]) -> [bool; 2] {
+        // 線の状態が変わらなくなれば, 残りの回も同じ値になるので打ち切る
         for _ in 0..8 {
             let result = self.ff.eval_mut(self.input_with_cache(input));
-            self.nand1_to_nand2_line_state = result[0];
-            self.nand2_to_rand1_line_state = result[1];
+            if result == self.lines() {
+                return result;
+            }
+            self.set_lines(result);
         }
         self.eval(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.ff.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.ff.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.ff.fuse_children()
+    }
+    // 二つのNANDの出力
+    fn state(&self) -> Vec<bool> {
+        self.lines().to_vec()
+    }
+    fn set_state(&mut self, state: &[bool]) {
+        self.set_lines([state[0], state[1]]);
+    }
+    fn attach_state(&mut self, states: &SharedState) -> bool {
+        let lines = self.lines();
+        let offset = states.borrow().len();
+        states.borrow_mut().extend(lines);
+        self.shared = Some((Rc::clone(states), offset));
+        true
+    }
 }
 
 impl RSFlipFlop {
@@ -40,15 +71,29 @@ impl RSFlipFlop {
             ff,
             nand2_to_rand1_line_state: false,
             nand1_to_nand2_line_state: false,
+            shared: None,
+        }
+    }
+    fn lines(&self) -> [bool; 2] {
+        match &self.shared {
+            Some((states, offset)) => {
+                let states = states.borrow();
+                [states[*offset], states[offset + 1]]
+            }
+            None => [self.nand1_to_nand2_line_state, self.nand2_to_rand1_line_state],
+        }
+    }
+    fn set_lines(&mut self, lines: [bool; 2]) {
+        match &self.shared {
+            Some((states, offset)) => {
+                states.borrow_mut()[*offset..offset + 2].copy_from_slice(&lines);
+            }
+            None => [self.nand1_to_nand2_line_state, self.nand2_to_rand1_line_state] = lines,
         }
     }
     fn input_with_cache(&self, input: [bool; 2]) -> [bool; 4] {
-        [
-            input[0],
-            self.nand2_to_rand1_line_state,
-            self.nand1_to_nand2_line_state,
-            input[1],
-        ]
+        let [nand1_to_nand2, nand2_to_rand1] = self.lines();
+        [input[0], nand2_to_rand1, nand1_to_nand2, input[1]]
     }
 }
 
@@ -68,6 +113,34 @@ fn rsflipflop_test() {
     assert_eq!(ff.eval_mut([true, false]), [false, true]);
 }
 
+#[test]
+fn rsflipflop_settle_test() {
+    use crate::num_bit_converter::num_to_bit;
+
+    // 打ち切らずに8回まわしたもの
+    fn settle_fully(ff: &mut RSFlipFlop, input: [bool; 2]) -> [bool; 2] {
+        for _ in 0..8 {
+            let result = ff.ff.eval_mut(ff.input_with_cache(input));
+            ff.set_lines(result);
+        }
+        ff.eval(input)
+    }
+
+    // 4通りの状態から4通りの入力を3回続けるすべての列で, 出力と状態が同じになる
+    for start in 0..4 {
+        for inputs in 0..64 {
+            let (mut ff, mut reference) = (RSFlipFlop::new(), RSFlipFlop::new());
+            ff.set_state(&num_to_bit::<2>(start));
+            reference.set_state(&num_to_bit::<2>(start));
+            for step in 0..3 {
+                let input = num_to_bit::<2>(inputs >> (2 * step));
+                assert_eq!(ff.eval_mut(input), settle_fully(&mut reference, input));
+                assert_eq!(ff.state(), reference.state());
+            }
+        }
+    }
+}
+
 
 pub struct MemoryCell {
     // read, write, value
@@ -81,6 +154,21 @@ impl Component<3, 1> for MemoryCell {
     fn eval(&self, input: [bool; 3]) -> [bool; 1] {
         self.cell.eval(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.cell.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.cell.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.cell.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.cell.netlist(netlist, input)
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
 }
 
 impl MemoryCell {
@@ -100,7 +188,7 @@ impl MemoryCell {
             let layer1 = Box::new(layer1);
             let layer2 = Box::new(layer2);
             let layer3 = Box::new(layer3);
-            let layer4 = Box::new(ff) as Box<dyn Component<2, 2>>;
+            let layer4 = Box::new(Named::create("ff", Box::new(ff))) as Box<dyn Component<2, 2>>;
             let pick_only_q = Box::new(Wiring::create([0]));
             
             MergeLayers::create(layer1, layer2)
@@ -156,6 +244,21 @@ impl<const N: usize> Component<{N + 2}, N> for MemoryByte<N> where
     fn eval(&self, input: [bool; N + 2]) -> [bool; N] {
         self.byte.eval(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.byte.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.byte.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.byte.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.byte.netlist(netlist, input)
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
 }
 
 impl<const N: usize> MemoryByte<N> where
@@ -174,7 +277,7 @@ impl<const N: usize> MemoryByte<N> where
         let layer1: Wiring<{N + 2}, {3 * N}> = Wiring::create(layer1_table);
         let cells = ConcatBlocks::create(
             [0; N].map(|c| Box::new(MemoryCell::new()) as Box<dyn Component<3, 1>>)
-        );
+        ).named("cells");
 
         let out_wrapper = Wiring::<{1 * N}, N>::wrapper();
         let cells = MergeLayers::create(Box::new(cells), Box::new(out_wrapper));
@@ -255,6 +358,18 @@ where
     fn eval(&self, input: [bool; Address + Bit + 2]) -> [bool; Bit] {
         self.memory.eval(input)
     }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.memory.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.memory.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.memory.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.memory.netlist(netlist, input)
+    }
 }
 
 
@@ -335,11 +450,8 @@ impl<const Address: usize, const Bit: usize> Memory<Address, Bit> where
         // read0, write0, read1, write1, ...
         // -> read0, data0, data1, ..., data_{Bit}, write0, data0, ..., data_{Bit}, ...
         let layer2 = Wiring::<{2 * pow2(Address) + 1 * Bit}, {(Bit + 2) * pow2(Address)}>::create(layer2_table);
-        let bytes = ConcatBlocks::<{Bit + 2}, Bit, {pow2(Address)}>::create(
-            [0; pow2(Address)].map(|_| {
-                Box::new(MemoryByte::new()) as Box<dyn Component<{Bit + 2}, Bit>>
-            })
-        );
+        let bytes = ConcatBlocks::<{Bit + 2}, Bit, {pow2(Address)}>::create_shared(MemoryByte::new)
+            .named("bytes");
         let layer3 = Wiring::<{Bit * pow2(Address)}, {Bit * pow2(Address)}>::unzip::<Bit>();
         let layer34_wrapper = Wiring::<{Bit * pow2(Address)}, {pow2(Address) * Bit}>::wrapper();
         let layer4 = ConcatBlocks::create(
@@ -376,3 +488,257 @@ fn memory_test() {
         assert_eq!(memory.eval_mut(input), num_to_bit(num));
     }
 }
+
+#[test]
+fn memory_probe_test() {
+    use crate::hierarchy::*;
+    use crate::num_bit_converter::*;
+
+    // パスでたどるとそのバイトだけの回路ができるので, 中のフリップフロップまでたどれる
+    let read = 1;
+    let write = 2;
+    let mut memory = Memory::<4, 4>::new();
+    memory.eval_mut(num_to_bit::<10>((0b0100 << 6) + (9 << 2) + write));
+    assert_eq!(probe(&memory, "bytes[9].cells[2].ff"), Some(vec![true, false]));
+    assert_eq!(probe(&memory, "bytes[8].cells[2].ff"), Some(vec![false, true]));
+
+    // 固定した線はそのバイトにしか効かない
+    assert!(force(&mut memory, "bytes[9].cells[0].ff", Some(&[true, false])));
+    assert_eq!(memory.eval_mut(num_to_bit::<10>((9 << 2) + read)), num_to_bit(0b0101));
+    assert_eq!(memory.eval_mut(num_to_bit::<10>((8 << 2) + read)), [false; 4]);
+    assert!(force(&mut memory, "bytes[9].cells[0].ff", None));
+    assert_eq!(memory.eval_mut(num_to_bit::<10>((9 << 2) + read)), num_to_bit(0b0100));
+}
+
+// 確保の回数を数えるアロケータ, 数えるのはテストを走らせているスレッドの分だけ
+#[cfg(test)]
+struct CountingAllocator;
+#[cfg(test)]
+thread_local! {
+    static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+#[cfg(test)]
+unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        unsafe { std::alloc::System.alloc(layout) }
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        unsafe { std::alloc::System.dealloc(ptr, layout) }
+    }
+}
+#[cfg(test)]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn memory_shared_construction_test() {
+    use crate::num_bit_converter::*;
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(std::cell::Cell::get)
+    }
+    fn test() {
+        // バイトの回路は一つだけで, 番地ごとに持つのは状態とそれを指すFlyweightだけ
+        let start = allocations();
+        let byte = MemoryByte::<8>::new();
+        let per_byte = allocations() - start;
+        drop(byte);
+        let start = allocations();
+        let mut memory = Memory::<16, 8>::new();
+        let shared = allocations() - start;
+        assert!(shared * 10 < per_byte * pow2(16), "{} allocations, {} per byte", shared, per_byte);
+
+        let read = 1;
+        let write = 2;
+        memory.eval_mut(num_to_bit::<26>((0xa5 << 18) + (0xbeef << 2) + write));
+        assert_eq!(memory.eval_mut(num_to_bit::<26>((0xbeef << 2) + read)), num_to_bit(0xa5));
+        assert_eq!(memory.eval_mut(num_to_bit::<26>((0xbeee << 2) + read)), [false; 8]);
+    }
+    // 65536番地分の配列を置くのでスタックを大きくとる
+    std::thread::Builder::new().stack_size(1 << 28).spawn(test).unwrap().join().unwrap();
+}
+
+#[test]
+fn memory_fuse_wirings_test() {
+    use crate::num_bit_converter::*;
+    use crate::optimize::fuse_wirings;
+
+    // read_write_addr_selectの layer2 -> layer23_wrapper などの配線が入れ子をまたいでまとまる
+    let mut original = Memory::<4, 4>::new();
+    let mut fused = Memory::<4, 4>::new();
+    fuse_wirings(&mut fused);
+    let (before, after) = (original.memory.wiring_layers(), fused.memory.wiring_layers());
+    assert!(after < before, "{} -> {}", before, after);
+    let mut s: usize = 12345;
+    for _ in 0..1000 {
+        s = s.wrapping_mul(1103515245).wrapping_add(12345) % (1 << 31);
+        let input = num_to_bit::<10>(s >> 8);
+        assert_eq!(fused.eval_mut(input), original.eval_mut(input));
+    }
+}
+
+// 書き込まれたバイトだけをMemoryByteとして作るメモリ, 入出力はMemoryと同じ
+// まだ作っていないバイトは電源投入直後のMemoryByte(blank)と同じ値を読む
+// パスもMemoryと同じで, 作ったバイトは番地を添字にしたbytesの子になる (bytes[37].cells[5].ff)
+pub struct SparseMemory<const Address: usize, const Bit: usize> where
+    [(); Address + Bit + 2]: Sized,
+{
+    bytes: Box<dyn Component<{Address + Bit + 2}, Bit>>,
+}
+
+impl<const Address: usize, const Bit: usize>
+    Component<{Address + Bit + 2}, Bit> for SparseMemory<Address, Bit>
+where
+    [(); Address + Bit + 2]: Sized,
+{
+    fn eval(&self, input: [bool; Address + Bit + 2]) -> [bool; Bit] {
+        self.bytes.eval(input)
+    }
+    fn eval_mut(&mut self, input: [bool; Address + Bit + 2]) -> [bool; Bit] {
+        self.bytes.eval_mut(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        vec![(None, &self.bytes)]
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        vec![(None, &mut self.bytes)]
+    }
+}
+
+impl<const Address: usize, const Bit: usize> SparseMemory<Address, Bit> where
+    [(); Bit + 2]: Sized,
+    [(); 1 * Bit]: Sized,
+    [(); 3 * Bit]: Sized,
+    [(); Address + Bit + 2]: Sized,
+{
+    pub fn new() -> Self {
+        Self {
+            bytes: Box::new(SparseBytes::<Address, Bit> {
+                blank: MemoryByte::new(),
+                bytes: BTreeMap::new(),
+            }),
+        }
+    }
+    // 作ったバイトの数
+    pub fn materialized(&self) -> usize {
+        self.bytes.children().len()
+    }
+}
+
+// SparseMemoryの中身, MemoryのConcatBlocksと同じく"bytes"という名前で番地を添字にする
+struct SparseBytes<const Address: usize, const Bit: usize> where
+    [(); Bit + 2]: Sized,
+    [(); 3 * Bit]: Sized,
+{
+    blank: MemoryByte<Bit>,
+    bytes: BTreeMap<usize, Box<dyn Component<{Bit + 2}, Bit>>>,
+}
+
+impl<const Address: usize, const Bit: usize>
+    Component<{Address + Bit + 2}, Bit> for SparseBytes<Address, Bit>
+where
+    [(); Bit + 2]: Sized,
+    [(); 1 * Bit]: Sized,
+    [(); 3 * Bit]: Sized,
+    [(); Address + Bit + 2]: Sized,
+{
+    fn eval(&self, input: [bool; Address + Bit + 2]) -> [bool; Bit] {
+        let (addr, byte_input) = Self::split_input(input);
+        match self.bytes.get(&addr) {
+            Some(byte) => byte.eval(byte_input),
+            None => self.blank.eval(byte_input),
+        }
+    }
+    fn eval_mut(&mut self, input: [bool; Address + Bit + 2]) -> [bool; Bit] {
+        let (addr, byte_input) = Self::split_input(input);
+        let write = input[1];
+        if write && !self.bytes.contains_key(&addr) {
+            self.bytes.insert(addr, Box::new(MemoryByte::new()));
+        }
+        match self.bytes.get_mut(&addr) {
+            Some(byte) => byte.eval_mut(byte_input),
+            None => self.blank.eval(byte_input),
+        }
+    }
+    fn name(&self) -> Option<&str> {
+        Some("bytes")
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.bytes.iter()
+            .map(|(&addr, byte)| (Some(addr), byte as &dyn Node))
+            .collect()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.bytes.iter_mut()
+            .map(|(&addr, byte)| (Some(addr), byte as &mut dyn Node))
+            .collect()
+    }
+}
+
+impl<const Address: usize, const Bit: usize> SparseBytes<Address, Bit> where
+    [(); Bit + 2]: Sized,
+    [(); 3 * Bit]: Sized,
+    [(); Address + Bit + 2]: Sized,
+{
+    // read, write, address, value を番地とMemoryByteへの入力(read, write, value)に分ける
+    fn split_input(input: [bool; Address + Bit + 2]) -> (usize, [bool; Bit + 2]) {
+        let mut addr = 0;
+        for i in 0..Address {
+            if input[2 + i] {
+                addr |= 1 << i;
+            }
+        }
+        let mut byte_input = [false; Bit + 2];
+        byte_input[0] = input[0];
+        byte_input[1] = input[1];
+        byte_input[2..].copy_from_slice(&input[(2 + Address)..]);
+        (addr, byte_input)
+    }
+}
+
+#[test]
+fn sparse_memory_test() {
+    use crate::num_bit_converter::*;
+
+    let read = 1;
+    let write = 2;
+    let mut memory = SparseMemory::<16, 8>::new();
+    for addr in [0, 37, 0xbeef, 0xffff] {
+        let input = num_to_bit::<26>(((addr & 0xff) << 18) + (addr << 2) + write);
+        memory.eval_mut(input);
+    }
+    assert_eq!(memory.materialized(), 4);
+    for addr in [0, 37, 0xbeef, 0xffff] {
+        let input = num_to_bit::<26>((addr << 2) + read);
+        assert_eq!(memory.eval_mut(input), num_to_bit(addr & 0xff));
+    }
+    // 読むだけでは作らない
+    assert_eq!(memory.eval_mut(num_to_bit::<26>((1234 << 2) + read)), [false; 8]);
+    assert_eq!(memory.materialized(), 4);
+    // パスはMemoryと同じ
+    assert!(crate::hierarchy::find(&memory, "bytes[37].cells[0].ff").is_some());
+    assert!(crate::hierarchy::find(&memory, "[37].cells[0].ff").is_none());
+    assert_eq!(crate::hierarchy::probe(&memory, "bytes[37].cells[0].ff"), Some(vec![true, false]));
+}
+
+#[test]
+fn sparse_memory_matches_memory_test() {
+    use crate::num_bit_converter::*;
+
+    // 同じ入力列をMemoryと並べて流す
+    let mut sparse = SparseMemory::<4, 4>::new();
+    let mut memory = Memory::<4, 4>::new();
+    let mut s: usize = 12345;
+    for _ in 0..1000 {
+        s = s.wrapping_mul(1103515245).wrapping_add(12345) % (1 << 31);
+        let input = num_to_bit::<10>(s >> 8);
+        assert_eq!(sparse.eval_mut(input), memory.eval_mut(input));
+    }
+    // 作ったバイトの中はMemoryの同じパスと同じ値
+    let paths = crate::hierarchy::paths(&sparse);
+    assert!(paths.contains(&"bytes".to_string()));
+    for path in paths.iter().filter(|p| p.ends_with(".ff")) {
+        assert_eq!(crate::hierarchy::probe(&sparse, path), crate::hierarchy::probe(&memory, path), "{}", path);
+    }
+}
@@ -0,0 +1,389 @@
+use crate::core::*;
+use crate::netlist::{Net, Netlist};
+use crate::basic_comp::*;
+use crate::arithmetic_comp::FullAdder;
+use crate::adder::kogge_stone_adder;
+
+// 部分積をWbitの行にならべて桁上げ保存加算で2行まで減らし, 最後に速い加算器で足す
+// 行の束は R行 * Wbit で, 使い終わった行は0にしておく
+
+// 桁上げ保存加算, 3行 -> bitごとの (和, 繰り上がり)
+fn carry_save<const W: usize>() -> MergeLayers<{3 * W}, {3 * W}, {2 * W}> where
+    [(); 3 * W]: Sized,
+    [(); 2 * W]: Sized,
+{
+    let layer1 = Wiring::<{3 * W}, {3 * W}>::zip::<W>();
+    let layer2 = ConcatBlocks::<3, 2, W>::create_from_fn(FullAdder::new);
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+}
+
+// 束の後ろに0をつける
+pub(crate) fn with_zero<const N: usize>() -> MergeLayers<N, {N + 0}, {N + 1}> where
+    [(); N + 0]: Sized,
+    [(); N + 1]: Sized,
+{
+    let in_wrapper = Wiring::<N, {N + 0}>::wrapper();
+    let layer1 = ConcatDifferentShapeBlocks::<N, 0, N, 1>::create(
+        Box::new(Wiring::<N, N>::buffer()),
+        Box::new(Constant::<0, 1, false>::new()),
+    );
+    MergeLayers::create(Box::new(in_wrapper), Box::new(layer1))
+}
+
+// Wallace木の1段, 生きているrows行を3行ずつ2行にし, 余った行はそのまま残す
+fn wallace_level<const R: usize, const W: usize>(rows: usize) -> MergeLayers<{R * W}, {(2 * W) * (R / 3) + (R * W + 1)}, {R * W}> where
+    [(); R * W]: Sized,
+    [(); R * W + 0]: Sized,
+    [(); R * W + 1]: Sized,
+    [(); 3 * W]: Sized,
+    [(); 2 * W]: Sized,
+    [(); (3 * W) * (R / 3)]: Sized,
+    [(); (2 * W) * (R / 3)]: Sized,
+    [(); (3 * W) * (R / 3) + (R * W + 1)]: Sized,
+    [(); (2 * W) * (R / 3) + (R * W + 1)]: Sized,
+{
+    let zero = R * W;
+    let adders = rows / 3;
+    // 加算器ごとに3行, 使わない加算器は0につなぐ, 後ろに束をそのまま
+    let mut table = [0; (3 * W) * (R / 3) + (R * W + 1)];
+    for (i, v) in table.iter_mut().enumerate() {
+        *v = match i {
+            _ if i < (3 * W) * (R / 3) && i / (3 * W) < adders => i,
+            _ if i < (3 * W) * (R / 3) => zero,
+            _ => i - (3 * W) * (R / 3),
+        };
+    }
+    let layer2 = Wiring::<{R * W + 1}, {(3 * W) * (R / 3) + (R * W + 1)}>::create(table);
+    let layer3 = ConcatDifferentShapeBlocks::<{(3 * W) * (R / 3)}, {R * W + 1}, {(2 * W) * (R / 3)}, {R * W + 1}>::create(
+        Box::new(ConcatBlocks::<{3 * W}, {2 * W}, {R / 3}>::create_from_fn(carry_save::<W>)),
+        Box::new(Wiring::<{R * W + 1}, {R * W + 1}>::buffer()),
+    );
+    // 和の行, 1bit上げた繰り上がりの行, 残った行, 0の行 と詰める
+    let (copy, zero) = ((2 * W) * (R / 3), (2 * W) * (R / 3) + R * W);
+    let mut table = [zero; R * W];
+    for (row, bits) in table.chunks_mut(W).enumerate() {
+        for (bit, v) in bits.iter_mut().enumerate() {
+            let adder = row / 2;
+            *v = if adder < adders {
+                match (row % 2, bit) {
+                    (0, _) => 2 * W * adder + 2 * bit,
+                    (_, 0) => zero,
+                    _ => 2 * W * adder + 2 * (bit - 1) + 1,
+                }
+            } else if row - adders * 2 + adders * 3 < rows {
+                copy + (row - adders * 2 + adders * 3) * W + bit
+            } else {
+                zero
+            };
+        }
+    }
+    let layer4 = Wiring::<{(2 * W) * (R / 3) + (R * W + 1)}, {R * W}>::create(table);
+    MergeLayers::create(Box::new(with_zero::<{R * W}>()), Box::new(layer2))
+        .connect_to(Box::new(layer3))
+        .connect_to(Box::new(layer4))
+}
+
+// R行を2行になるまで減らしてから足す
+fn sum_rows<const R: usize, const W: usize>() -> MergeLayers<{R * W}, {2 * W}, W> where
+    [(); R * W]: Sized,
+    [(); R * W + 0]: Sized,
+    [(); R * W + 1]: Sized,
+    [(); 3 * W]: Sized,
+    [(); 2 * W]: Sized,
+    [(); 1 * W]: Sized,
+    [(); 3 * W + 0]: Sized,
+    [(); 3 * W + 1]: Sized,
+    [(); 3 * W + 2]: Sized,
+    [(); 5 * W]: Sized,
+    [(); (3 * W) * (R / 3)]: Sized,
+    [(); (2 * W) * (R / 3)]: Sized,
+    [(); (3 * W) * (R / 3) + (R * W + 1)]: Sized,
+    [(); (2 * W) * (R / 3) + (R * W + 1)]: Sized,
+{
+    let mut tree: Box<dyn Component<{R * W}, {R * W}>> = Box::new(Wiring::<{R * W}, {R * W}>::buffer());
+    let mut rows = R;
+    while rows > 2 {
+        tree = Box::new(MergeLayers::create(tree, Box::new(wallace_level::<R, W>(rows))));
+        rows -= rows / 3;
+    }
+    // 1行しかなければもう1行は0
+    let mut table = [R * W; 2 * W];
+    for (i, v) in table.iter_mut().enumerate().take(rows * W) {
+        *v = i;
+    }
+    let last = Wiring::<{R * W + 1}, {2 * W}>::create(table);
+    let adder = kogge_stone_adder::<W>();
+    MergeLayers::create(tree, Box::new(with_zero::<{R * W}>()))
+        .connect_to(Box::new(last))
+        .connect_to(Box::new(adder))
+}
+
+// Nbitの符号なし乗算, 入力は a(N), b(N), 出力は a * b(2N)
+// 部分積 a * b(i) をN行ならべてWallace木で減らす, 段数はlog(N)に比例する
+pub struct Multiplier<const N: usize> where
+    [(); 2 * N]: Sized,
+    [(); N * (2 * N)]: Sized,
+{
+    multiplier: MergeLayers<{2 * N}, {N * (2 * N)}, {2 * N}>,
+}
+
+impl<const N: usize> Component<{2 * N}, {2 * N}> for Multiplier<N> where
+    [(); 2 * N]: Sized,
+    [(); N * (2 * N)]: Sized,
+{
+    fn eval(&self, input: [bool; 2 * N]) -> [bool; 2 * N] {
+        self.multiplier.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.multiplier.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.multiplier.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.multiplier.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.multiplier.netlist(netlist, input)
+    }
+}
+
+impl<const N: usize> Multiplier<N> where
+    [(); 2 * N]: Sized,
+    [(); 2 * (2 * N)]: Sized,
+    [(); 2 * (N * N)]: Sized,
+    [(); 1 * (N * N)]: Sized,
+    [(); 1 * (N * N) + 0]: Sized,
+    [(); 1 * (N * N) + 1]: Sized,
+    [(); N * (2 * N)]: Sized,
+    [(); N * (2 * N) + 0]: Sized,
+    [(); N * (2 * N) + 1]: Sized,
+    [(); 3 * (2 * N)]: Sized,
+    [(); 1 * (2 * N)]: Sized,
+    [(); 3 * (2 * N) + 0]: Sized,
+    [(); 3 * (2 * N) + 1]: Sized,
+    [(); 3 * (2 * N) + 2]: Sized,
+    [(); 5 * (2 * N)]: Sized,
+    [(); (3 * (2 * N)) * (N / 3)]: Sized,
+    [(); (2 * (2 * N)) * (N / 3)]: Sized,
+    [(); (3 * (2 * N)) * (N / 3) + (N * (2 * N) + 1)]: Sized,
+    [(); (2 * (2 * N)) * (N / 3) + (N * (2 * N) + 1)]: Sized,
+{
+    pub fn new() -> Self {
+        // 部分積 a(j) & b(i) を i * N + j に
+        let mut table = [0; 2 * (N * N)];
+        for (k, pair) in table.chunks_mut(2).enumerate() {
+            pair.copy_from_slice(&[k % N, N + k / N]);
+        }
+        let layer1 = Wiring::<{2 * N}, {2 * (N * N)}>::create(table);
+        let layer2 = ConcatBlocks::<2, 1, {N * N}>::create_from_fn(And::<2>::new);
+        // i行目はiだけずらす
+        let mut table = [N * N; N * (2 * N)];
+        for (k, v) in table.iter_mut().enumerate() {
+            let (row, bit) = (k / (2 * N), k % (2 * N));
+            if (row..row + N).contains(&bit) {
+                *v = row * N + bit - row;
+            }
+        }
+        let layer3 = Wiring::<{1 * (N * N) + 1}, {N * (2 * N)}>::create(table);
+        let multiplier = MergeLayers::create(Box::new(layer1), Box::new(layer2))
+            .connect_to(Box::new(with_zero::<{1 * (N * N)}>()))
+            .connect_to(Box::new(layer3))
+            .connect_to(Box::new(sum_rows::<N, {2 * N}>()));
+        Self { multiplier }
+    }
+}
+
+// Booth符号化, b(2i + 1), b(2i), b(2i - 1) -> 1倍, 2倍, 負
+// 値は -2 * b(2i + 1) + b(2i) + b(2i - 1)
+fn booth_encoder() -> MergeLayers<3, 6, 3> {
+    // 2倍は 100 か 011
+    let two = {
+        let layer1 = ConcatBlocks::<1, 2, 3>::create_from_fn(Branch::<2>::new);
+        let layer2 = ConcatBlocks::create([
+            Box::new(Buffer::new()) as Box<dyn Component<1, 1>>,
+            Box::new(Not::new()),
+            Box::new(Not::new()),
+            Box::new(Buffer::new()),
+            Box::new(Not::new()),
+            Box::new(Buffer::new()),
+        ]);
+        let layer3 = Wiring::<6, 6>::create([0, 2, 4, 1, 3, 5]);
+        MergeLayers::create(Box::new(layer1), Box::new(layer2))
+            .connect_to(Box::new(layer3))
+            .connect_to(Box::new(ConcatBlocks::<3, 1, 2>::create_from_fn(And::<3>::new)))
+            .connect_to(Box::new(Or::<2>::new()))
+    };
+    let layer1 = Wiring::<3, 6>::create([1, 2, 0, 1, 2, 0]);
+    let layer2 = ConcatDifferentShapeBlocks::<2, 4, 1, 2>::create(
+        Box::new(XOR::<2>::new()),
+        Box::new(ConcatDifferentShapeBlocks::<3, 1, 1, 1>::create(Box::new(two), Box::new(Buffer::new()))),
+    );
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+}
+
+// 部分積の1bit, 1倍, 2倍, 負, a(t), a(t - 1) -> (1倍 & a(t) | 2倍 & a(t - 1)) ^ 負
+fn booth_cell() -> MergeLayers<5, 2, 1> {
+    let layer1 = Wiring::<5, 5>::create([0, 3, 1, 4, 2]);
+    let layer2 = ConcatDifferentShapeBlocks::<4, 1, 1, 1>::create(
+        Box::new(MergeLayers::create(
+            Box::new(ConcatBlocks::<2, 1, 2>::create_from_fn(And::<2>::new)),
+            Box::new(Or::<2>::new()),
+        )),
+        Box::new(Buffer::new()),
+    );
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(XOR::<2>::new()))
+}
+
+// Nbitの符号付き(2の補数)乗算, 入力は a(N), b(N), 出力は a * b(2N)
+// 2bitずつBooth符号化して部分積を (N + 1) / 2 行にし, 負の行の +1 をまとめた1行を足して木で減らす
+pub struct BoothMultiplier<const N: usize> where
+    [(); 2 * N]: Sized,
+    [(); (N.div_ceil(2) + 1) * (2 * N)]: Sized,
+{
+    multiplier: MergeLayers<{2 * N}, {(N.div_ceil(2) + 1) * (2 * N)}, {2 * N}>,
+}
+
+impl<const N: usize> Component<{2 * N}, {2 * N}> for BoothMultiplier<N> where
+    [(); 2 * N]: Sized,
+    [(); (N.div_ceil(2) + 1) * (2 * N)]: Sized,
+{
+    fn eval(&self, input: [bool; 2 * N]) -> [bool; 2 * N] {
+        self.multiplier.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.multiplier.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.multiplier.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.multiplier.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.multiplier.netlist(netlist, input)
+    }
+}
+
+impl<const N: usize> BoothMultiplier<N> where
+    [(); 2 * N]: Sized,
+    [(); 2 * (2 * N)]: Sized,
+    [(); 2 * N + 0]: Sized,
+    [(); 2 * N + 1]: Sized,
+    [(); 3 * (N.div_ceil(2))]: Sized,
+    [(); 3 * (N.div_ceil(2)) + (N + 1)]: Sized,
+    [(); (N.div_ceil(2)) * (2 * N)]: Sized,
+    [(); 5 * ((N.div_ceil(2)) * (2 * N)) + 2 * N]: Sized,
+    [(); 1 * ((N.div_ceil(2)) * (2 * N)) + 2 * N]: Sized,
+    [(); 5 * ((N.div_ceil(2)) * (2 * N))]: Sized,
+    [(); 1 * ((N.div_ceil(2)) * (2 * N))]: Sized,
+    [(); (N.div_ceil(2) + 1) * (2 * N)]: Sized,
+    [(); (N.div_ceil(2) + 1) * (2 * N) + 0]: Sized,
+    [(); (N.div_ceil(2) + 1) * (2 * N) + 1]: Sized,
+    [(); 3 * (2 * N)]: Sized,
+    [(); 1 * (2 * N)]: Sized,
+    [(); 3 * (2 * N) + 0]: Sized,
+    [(); 3 * (2 * N) + 1]: Sized,
+    [(); 3 * (2 * N) + 2]: Sized,
+    [(); 5 * (2 * N)]: Sized,
+    [(); (3 * (2 * N)) * ((N.div_ceil(2) + 1) / 3)]: Sized,
+    [(); (2 * (2 * N)) * ((N.div_ceil(2) + 1) / 3)]: Sized,
+    [(); (3 * (2 * N)) * ((N.div_ceil(2) + 1) / 3) + ((N.div_ceil(2) + 1) * (2 * N) + 1)]: Sized,
+    [(); (2 * (2 * N)) * ((N.div_ceil(2) + 1) / 3) + ((N.div_ceil(2) + 1) * (2 * N) + 1)]: Sized,
+{
+    pub fn new() -> Self {
+        let rows = N.div_ceil(2);
+        let zero = 2 * N;
+        // bは符号拡張し, b(-1)は0
+        let b = |i: isize| if i < 0 { zero } else { N + (i as usize).min(N - 1) };
+        // 行ごとの (b(2i + 1), b(2i), b(2i - 1)), 後ろに a と 0
+        let mut table = [0; 3 * (N.div_ceil(2)) + (N + 1)];
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = match i {
+                _ if i < 3 * rows => b(2 * (i / 3) as isize + 1 - (i % 3) as isize),
+                _ if i < 3 * rows + N => i - 3 * rows,
+                _ => zero,
+            };
+        }
+        let layer1 = Wiring::<{2 * N + 1}, {3 * (N.div_ceil(2)) + (N + 1)}>::create(table);
+        let layer2 = ConcatDifferentShapeBlocks::<{3 * (N.div_ceil(2))}, {N + 1}, {3 * (N.div_ceil(2))}, {N + 1}>::create(
+            Box::new(ConcatBlocks::<3, 3, {N.div_ceil(2)}>::create_from_fn(booth_encoder)),
+            Box::new(Wiring::<{N + 1}, {N + 1}>::buffer()),
+        );
+        // i行目のkbit目は (1倍, 2倍, 負, a(k - 2i), a(k - 2i - 1)), aは符号拡張する
+        // 2iより下は0, 最後の行は i行目の負を2i bit目に置いたもの
+        let (one, two, neg) = (|i| 3 * i, |i| 3 * i + 1, |i| 3 * i + 2);
+        let (a, zero) = (3 * rows, 3 * rows + N);
+        let a_ext = |t: isize| if t < 0 { zero } else { a + (t as usize).min(N - 1) };
+        let mut table = [zero; 5 * ((N.div_ceil(2)) * (2 * N)) + 2 * N];
+        for (k, cell) in table[..5 * rows * 2 * N].chunks_mut(5).enumerate() {
+            let (row, bit) = (k / (2 * N), k % (2 * N));
+            if bit >= 2 * row {
+                let t = (bit - 2 * row) as isize;
+                cell.copy_from_slice(&[one(row), two(row), neg(row), a_ext(t), a_ext(t - 1)]);
+            }
+        }
+        for row in 0..rows {
+            table[5 * rows * 2 * N + 2 * row] = neg(row);
+        }
+        let layer3 = Wiring::<{3 * (N.div_ceil(2)) + (N + 1)}, {5 * ((N.div_ceil(2)) * (2 * N)) + 2 * N}>::create(table);
+        let layer4 = ConcatDifferentShapeBlocks::<{5 * ((N.div_ceil(2)) * (2 * N))}, {2 * N}, {1 * ((N.div_ceil(2)) * (2 * N))}, {2 * N}>::create(
+            Box::new(ConcatBlocks::<5, 1, {(N.div_ceil(2)) * (2 * N)}>::create_from_fn(booth_cell)),
+            Box::new(Wiring::<{2 * N}, {2 * N}>::buffer()),
+        );
+        let rows_wrapper = Wiring::<{1 * ((N.div_ceil(2)) * (2 * N)) + 2 * N}, {(N.div_ceil(2) + 1) * (2 * N)}>::wrapper();
+        let multiplier = MergeLayers::create(Box::new(with_zero::<{2 * N}>()), Box::new(layer1))
+            .connect_to(Box::new(layer2))
+            .connect_to(Box::new(layer3))
+            .connect_to(Box::new(layer4))
+            .connect_to(Box::new(rows_wrapper))
+            .connect_to(Box::new(sum_rows::<{N.div_ceil(2) + 1}, {2 * N}>()));
+        Self { multiplier }
+    }
+}
+
+#[test]
+fn multiplier_test() {
+    use crate::num_bit_converter::*;
+
+    // 8bitはすべての組み合わせ
+    let unsigned = Multiplier::<8>::new();
+    let signed = BoothMultiplier::<8>::new();
+    for a in 0..256 {
+        for b in 0..256 {
+            let input = num_to_bit::<16>((b << 8) + a);
+            assert_eq!(bit_to_num(unsigned.eval(input)), a * b, "{} * {}", a, b);
+            let product = (a as u8 as i8 as i64) * (b as u8 as i8 as i64);
+            assert_eq!(bit_to_num(signed.eval(input)), product as u16 as usize, "{} * {}", a as u8 as i8, b as u8 as i8);
+        }
+    }
+
+    // 奇数の幅と行が少ないとき
+    let unsigned = Multiplier::<5>::new();
+    let signed = BoothMultiplier::<5>::new();
+    for a in 0..32 {
+        for b in 0..32 {
+            let input = num_to_bit::<10>((b << 5) + a);
+            assert_eq!(bit_to_num(unsigned.eval(input)), a * b);
+            let (sa, sb) = (a as i64 - ((a as i64 & 16) << 1), b as i64 - ((b as i64 & 16) << 1));
+            assert_eq!(bit_to_num(signed.eval(input)), (sa * sb).rem_euclid(1 << 10) as usize);
+        }
+    }
+    assert_eq!(Multiplier::<1>::new().eval([true, true]), [true, false]);
+    assert_eq!(Multiplier::<2>::new().eval([true, true, true, true]), [true, false, false, true]);
+    assert_eq!(BoothMultiplier::<1>::new().eval([true, true]), [true, false]);
+}
+
+#[test]
+fn multiplier_depth_test() {
+    use crate::optimize::simplify;
+
+    // 行の数がlogで減るので, 幅を倍にしても段数は倍にならない
+    let narrow = simplify(&Multiplier::<4>::new()).unwrap().0.netlist().metrics();
+    let wide = simplify(&Multiplier::<8>::new()).unwrap().0.netlist().metrics();
+    assert!(wide.depth < 2 * narrow.depth, "{:?} {:?}", narrow, wide);
+    // Booth符号化で行が半分になる
+    let booth = simplify(&BoothMultiplier::<8>::new()).unwrap().0.netlist().metrics();
+    assert!(booth.depth <= wide.depth, "{:?} {:?}", booth, wide);
+}
@@ -0,0 +1,474 @@
+use crate::core::*;
+use crate::netlist::{Net, Netlist};
+use crate::basic_comp::*;
+use crate::arithmetic_comp::pass_not;
+use crate::decoder::BitDecoder;
+use crate::flip_flop::select;
+
+// (選ぶ線, 候補)をK組並べて, 選ばれた候補のOR
+pub(crate) fn and_or<const K: usize>() -> MergeLayers<{2 * K}, {1 * K}, 1> where
+    [(); 2 * K]: Sized,
+    [(); 1 * K]: Sized,
+{
+    MergeLayers::create(
+        Box::new(ConcatBlocks::<2, 1, K>::create_from_fn(And::<2>::new)),
+        Box::new(Or::<{1 * K}>::new()),
+    )
+}
+
+// 2^N本のWbitの線から1本を選ぶ
+// 入力は 選ぶ番号(N), 0番の線(W), 1番の線(W), ... の順, 出力は選んだ線(W)
+// 番号をデコードしてそれぞれの線とANDをとり, bitごとに2^N入力のORでまとめる
+pub struct Mux<const N: usize, const W: usize> where
+    [(); N + pow2(N) * W]: Sized,
+    [(); 1 * W]: Sized,
+{
+    mux: MergeLayers<{N + pow2(N) * W}, {1 * W}, W>,
+}
+
+impl<const N: usize, const W: usize> Component<{N + pow2(N) * W}, W> for Mux<N, W> where
+    [(); N + pow2(N) * W]: Sized,
+    [(); 1 * W]: Sized,
+{
+    fn eval(&self, input: [bool; N + pow2(N) * W]) -> [bool; W] {
+        self.mux.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.mux.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.mux.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.mux.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.mux.netlist(netlist, input)
+    }
+}
+
+impl<const N: usize, const W: usize> Mux<N, W> where
+    [(); N + pow2(N) * W]: Sized,
+    [(); 1 * W]: Sized,
+    [(); pow2(N) * W]: Sized,
+    [(); pow2(N) + pow2(N) * W]: Sized,
+    [(); 2 * pow2(N)]: Sized,
+    [(); 1 * pow2(N)]: Sized,
+    [(); 2 * pow2(N) * W]: Sized,
+    MergeLayers<N, { 2_usize * N }, { pow2(N) }>: Sized,
+    MergeLayers<N, { N * pow2(N) }, { pow2(N) }>: Sized,
+    [(); 1 * N]: Sized,
+    [(); 2 * N]: Sized,
+    [(); 1 * N * 2]: Sized,
+    [(); pow2(N - 1)]: Sized,
+    [(); N * pow2(N)]: Sized,
+    Box<dyn Component<{ 2 * 1 * N }, { 2 * 1 * N }>>: Sized,
+{
+    pub fn new() -> Self {
+        let layer1 = ConcatDifferentShapeBlocks::<N, {pow2(N) * W}, {pow2(N)}, {pow2(N) * W}>::create(
+            Box::new(BitDecoder::<N>::new()),
+            Box::new(Wiring::<{pow2(N) * W}, {pow2(N) * W}>::buffer()),
+        );
+        // 出力のbitごとに (k番目の選ぶ線, k番目の線のそのbit) を2^N組
+        let mut table = [0; 2 * pow2(N) * W];
+        for (i, pair) in table.chunks_mut(2).enumerate() {
+            let (bit, k) = (i / pow2(N), i % pow2(N));
+            pair.copy_from_slice(&[k, pow2(N) + k * W + bit]);
+        }
+        let layer2 = Wiring::<{pow2(N) + pow2(N) * W}, {2 * pow2(N) * W}>::create(table);
+        let layer3 = ConcatBlocks::<{2 * pow2(N)}, 1, W>::create_from_fn(and_or::<{pow2(N)}>);
+        let out_wrapper = Wiring::<{1 * W}, W>::wrapper();
+        let mux = MergeLayers::create(Box::new(layer1), Box::new(layer2))
+            .connect_to(Box::new(layer3))
+            .connect_to(Box::new(out_wrapper));
+        Self { mux }
+    }
+}
+
+// Wbitの線を2^N本のうちの1本に出し, 残りは0にする
+// 入力は 出す番号(N), 値(W) の順, 出力は 0番の線(W), 1番の線(W), ... の順
+pub struct Demux<const N: usize, const W: usize> where
+    [(); N + W]: Sized,
+    [(); 1 * (pow2(N) * W)]: Sized,
+    [(); pow2(N) * W]: Sized,
+{
+    demux: MergeLayers<{N + W}, {1 * (pow2(N) * W)}, {pow2(N) * W}>,
+}
+
+impl<const N: usize, const W: usize> Component<{N + W}, {pow2(N) * W}> for Demux<N, W> where
+    [(); N + W]: Sized,
+    [(); 1 * (pow2(N) * W)]: Sized,
+    [(); pow2(N) * W]: Sized,
+{
+    fn eval(&self, input: [bool; N + W]) -> [bool; pow2(N) * W] {
+        self.demux.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.demux.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.demux.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.demux.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.demux.netlist(netlist, input)
+    }
+}
+
+impl<const N: usize, const W: usize> Demux<N, W> where
+    [(); N + W]: Sized,
+    [(); 1 * (pow2(N) * W)]: Sized,
+    [(); pow2(N) * W]: Sized,
+    [(); pow2(N) + W]: Sized,
+    [(); 2 * (pow2(N) * W)]: Sized,
+    MergeLayers<N, { 2_usize * N }, { pow2(N) }>: Sized,
+    MergeLayers<N, { N * pow2(N) }, { pow2(N) }>: Sized,
+    [(); 1 * N]: Sized,
+    [(); 2 * N]: Sized,
+    [(); 1 * N * 2]: Sized,
+    [(); pow2(N - 1)]: Sized,
+    [(); 1 * pow2(N)]: Sized,
+    [(); N * pow2(N)]: Sized,
+    Box<dyn Component<{ 2 * 1 * N }, { 2 * 1 * N }>>: Sized,
+{
+    pub fn new() -> Self {
+        let layer1 = ConcatDifferentShapeBlocks::<N, W, {pow2(N)}, W>::create(
+            Box::new(BitDecoder::<N>::new()),
+            Box::new(Wiring::<W, W>::buffer()),
+        );
+        // 出力のbitごとに (k番目の選ぶ線, 値のそのbit)
+        let mut table = [0; 2 * (pow2(N) * W)];
+        for (i, pair) in table.chunks_mut(2).enumerate() {
+            let (k, bit) = (i / W, i % W);
+            pair.copy_from_slice(&[k, pow2(N) + bit]);
+        }
+        let layer2 = Wiring::<{pow2(N) + W}, {2 * (pow2(N) * W)}>::create(table);
+        let layer3 = ConcatBlocks::<2, 1, {pow2(N) * W}>::create_from_fn(And::<2>::new);
+        let out_wrapper = Wiring::<{1 * (pow2(N) * W)}, {pow2(N) * W}>::wrapper();
+        let demux = MergeLayers::create(Box::new(layer1), Box::new(layer2))
+            .connect_to(Box::new(layer3))
+            .connect_to(Box::new(out_wrapper));
+        Self { demux }
+    }
+}
+
+// 選ぶ, a(W), b(W) -> 選ぶが1ならb, 0ならa
+fn mux_cell<const W: usize>() -> MergeLayers<{1 + 2 * W}, {1 * W}, W> where
+    [(); 1 + 2 * W]: Sized,
+    [(); 3 * W]: Sized,
+    [(); 1 * W]: Sized,
+{
+    let mut table = [0; 3 * W];
+    for (i, chunk) in table.chunks_mut(3).enumerate() {
+        chunk.copy_from_slice(&[0, 1 + W + i, 1 + i]);
+    }
+    let layer1 = Wiring::<{1 + 2 * W}, {3 * W}>::create(table);
+    let layer2 = ConcatBlocks::<3, 1, W>::create_from_fn(select);
+    let out_wrapper = Wiring::<{1 * W}, W>::wrapper();
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(out_wrapper))
+}
+
+// 選ぶ, 値(W) -> 選ぶが0なら 値, 0 , 1なら 0, 値
+fn demux_cell<const W: usize>() -> MergeLayers<{1 + W}, {1 * (2 * W)}, {2 * W}> where
+    [(); 1 + W]: Sized,
+    [(); 2 + W]: Sized,
+    [(); 2 * W]: Sized,
+    [(); 2 * (2 * W)]: Sized,
+    [(); 1 * (2 * W)]: Sized,
+{
+    let mut table = [0; 2 + W];
+    for (i, v) in table.iter_mut().enumerate().skip(2) {
+        *v = i - 1;
+    }
+    let layer1 = Wiring::<{1 + W}, {2 + W}>::create(table);
+    let layer2 = ConcatDifferentShapeBlocks::<2, W, 2, W>::create(
+        Box::new(pass_not()),
+        Box::new(Wiring::<W, W>::buffer()),
+    );
+    // 前半は !選ぶ, 後半は 選ぶ とのAND
+    let mut table = [0; 2 * (2 * W)];
+    for (i, pair) in table.chunks_mut(2).enumerate() {
+        pair.copy_from_slice(&[if i < W { 1 } else { 0 }, 2 + i % W]);
+    }
+    let layer3 = Wiring::<{2 + W}, {2 * (2 * W)}>::create(table);
+    let layer4 = ConcatBlocks::<2, 1, {2 * W}>::create_from_fn(And::<2>::new);
+    let out_wrapper = Wiring::<{1 * (2 * W)}, {2 * W}>::wrapper();
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(layer3))
+        .connect_to(Box::new(layer4))
+        .connect_to(Box::new(out_wrapper))
+}
+
+// 木の段の間の線は 番号(N), 線(W)をpow2(N - 1)本 で, どの段も同じ幅にしておく
+// 1段はpow2(N - 1)個の場所を持ち, その段で使わない場所は0を出す定数で埋める
+
+// 番号のk bit目で 2c番と2c+1番の線から選び, c番の線にする段
+// 使う部品は2^(N - 1 - k)個, 最初の段だけ入力は 番号(N), 線(W)を2^N本
+fn mux_level<const N: usize, const W: usize, const I: usize>(
+    k: usize,
+) -> MergeLayers<I, {N + (1 + 2 * W) * pow2(N - 1)}, {N + W * pow2(N - 1)}> where
+    [(); 1 + 2 * W]: Sized,
+    [(); 3 * W]: Sized,
+    [(); 1 * W]: Sized,
+    [(); (1 + 2 * W) * pow2(N - 1)]: Sized,
+    [(); W * pow2(N - 1)]: Sized,
+    [(); N + (1 + 2 * W) * pow2(N - 1)]: Sized,
+    [(); N + W * pow2(N - 1)]: Sized,
+{
+    let cells = pow2(N - 1 - k);
+    let mut table = [0; N + (1 + 2 * W) * pow2(N - 1)];
+    for (i, v) in table.iter_mut().enumerate().take(N) {
+        *v = i;
+    }
+    for (c, chunk) in table[N..].chunks_mut(1 + 2 * W).enumerate().take(cells) {
+        chunk[0] = k;
+        for (i, v) in chunk.iter_mut().enumerate().skip(1) {
+            *v = N + 2 * c * W + i - 1;
+        }
+    }
+    let layer1 = Wiring::<I, {N + (1 + 2 * W) * pow2(N - 1)}>::create(table);
+    let layer2 = ConcatDifferentShapeBlocks::<N, {(1 + 2 * W) * pow2(N - 1)}, N, {W * pow2(N - 1)}>::create(
+        Box::new(Wiring::<N, N>::buffer()),
+        Box::new(ConcatBlocks::<{1 + 2 * W}, W, {pow2(N - 1)}>::create(std::array::from_fn(|c| {
+            if c < cells {
+                Box::new(mux_cell::<W>()) as Box<dyn Component<{1 + 2 * W}, W>>
+            } else {
+                Box::new(Constant::<{1 + 2 * W}, W, false>::new())
+            }
+        }))),
+    );
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+}
+
+// 番号の上からk番目のbitで c番の線を 2c番と2c+1番に分ける段
+// 使う部品は2^k個, 最初の段だけ入力は 番号(N), 値(W)
+fn demux_level<const N: usize, const W: usize, const I: usize>(
+    k: usize,
+) -> MergeLayers<I, {N + (1 + W) * pow2(N - 1)}, {N + (2 * W) * pow2(N - 1)}> where
+    [(); 1 + W]: Sized,
+    [(); 2 + W]: Sized,
+    [(); 2 * W]: Sized,
+    [(); 2 * (2 * W)]: Sized,
+    [(); 1 * (2 * W)]: Sized,
+    [(); (1 + W) * pow2(N - 1)]: Sized,
+    [(); (2 * W) * pow2(N - 1)]: Sized,
+    [(); N + (1 + W) * pow2(N - 1)]: Sized,
+    [(); N + (2 * W) * pow2(N - 1)]: Sized,
+{
+    let cells = pow2(k);
+    let mut table = [0; N + (1 + W) * pow2(N - 1)];
+    for (i, v) in table.iter_mut().enumerate().take(N) {
+        *v = i;
+    }
+    for (c, chunk) in table[N..].chunks_mut(1 + W).enumerate().take(cells) {
+        chunk[0] = N - 1 - k;
+        for (i, v) in chunk.iter_mut().enumerate().skip(1) {
+            *v = N + c * W + i - 1;
+        }
+    }
+    let layer1 = Wiring::<I, {N + (1 + W) * pow2(N - 1)}>::create(table);
+    let layer2 = ConcatDifferentShapeBlocks::<N, {(1 + W) * pow2(N - 1)}, N, {(2 * W) * pow2(N - 1)}>::create(
+        Box::new(Wiring::<N, N>::buffer()),
+        Box::new(ConcatBlocks::<{1 + W}, {2 * W}, {pow2(N - 1)}>::create(std::array::from_fn(|c| {
+            if c < cells {
+                Box::new(demux_cell::<W>()) as Box<dyn Component<{1 + W}, {2 * W}>>
+            } else {
+                Box::new(Constant::<{1 + W}, {2 * W}, false>::new())
+            }
+        }))),
+    );
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+}
+
+// Muxと同じ入出力で, 2本から1本を選ぶ部品を木にしたもの
+// 番号の下のbitから順に隣り合う2本を1本にしていき, 2^N - 1個の部品をN段通る
+// 2入力のゲートだけでできるので, 広いAND, ORが使えないときに使う
+pub struct MuxTree<const N: usize, const W: usize> where
+    [(); N + pow2(N) * W]: Sized,
+    [(); N + W * pow2(N - 1)]: Sized,
+{
+    tree: MergeLayers<{N + pow2(N) * W}, {N + W * pow2(N - 1)}, W>,
+}
+
+impl<const N: usize, const W: usize> Component<{N + pow2(N) * W}, W> for MuxTree<N, W> where
+    [(); N + pow2(N) * W]: Sized,
+    [(); N + W * pow2(N - 1)]: Sized,
+{
+    fn eval(&self, input: [bool; N + pow2(N) * W]) -> [bool; W] {
+        self.tree.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.tree.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.tree.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.tree.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.tree.netlist(netlist, input)
+    }
+}
+
+impl<const N: usize, const W: usize> MuxTree<N, W> where
+    [(); N + pow2(N) * W]: Sized,
+    [(); 1 + 2 * W]: Sized,
+    [(); 3 * W]: Sized,
+    [(); 1 * W]: Sized,
+    [(); (1 + 2 * W) * pow2(N - 1)]: Sized,
+    [(); W * pow2(N - 1)]: Sized,
+    [(); N + (1 + 2 * W) * pow2(N - 1)]: Sized,
+    [(); N + W * pow2(N - 1)]: Sized,
+{
+    pub fn new() -> Self {
+        let mut levels: Box<dyn Component<{N + W * pow2(N - 1)}, {N + W * pow2(N - 1)}>> =
+            Box::new(Wiring::<{N + W * pow2(N - 1)}, {N + W * pow2(N - 1)}>::buffer());
+        for k in 1..N {
+            levels = Box::new(MergeLayers::create(levels, Box::new(mux_level::<N, W, {N + W * pow2(N - 1)}>(k))));
+        }
+        // 最後に残った0番の線
+        let mut table = [0; W];
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = N + i;
+        }
+        let tree = MergeLayers::create(Box::new(mux_level::<N, W, {N + pow2(N) * W}>(0)), levels)
+            .connect_to(Box::new(Wiring::<{N + W * pow2(N - 1)}, W>::create(table)));
+        Self { tree }
+    }
+}
+
+// Demuxと同じ入出力で, 1本を2本に分ける部品を木にしたもの
+// 番号の上のbitから順に分けていき, 2^N - 1個の部品をN段通る
+pub struct DemuxTree<const N: usize, const W: usize> where
+    [(); N + W]: Sized,
+    [(); N + (2 * W) * pow2(N - 1)]: Sized,
+    [(); pow2(N) * W]: Sized,
+{
+    tree: MergeLayers<{N + W}, {N + (2 * W) * pow2(N - 1)}, {pow2(N) * W}>,
+}
+
+impl<const N: usize, const W: usize> Component<{N + W}, {pow2(N) * W}> for DemuxTree<N, W> where
+    [(); N + W]: Sized,
+    [(); N + (2 * W) * pow2(N - 1)]: Sized,
+    [(); pow2(N) * W]: Sized,
+{
+    fn eval(&self, input: [bool; N + W]) -> [bool; pow2(N) * W] {
+        self.tree.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.tree.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.tree.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.tree.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.tree.netlist(netlist, input)
+    }
+}
+
+impl<const N: usize, const W: usize> DemuxTree<N, W> where
+    [(); N + W]: Sized,
+    [(); pow2(N) * W]: Sized,
+    [(); 1 + W]: Sized,
+    [(); 2 + W]: Sized,
+    [(); 2 * W]: Sized,
+    [(); 2 * (2 * W)]: Sized,
+    [(); 1 * (2 * W)]: Sized,
+    [(); (1 + W) * pow2(N - 1)]: Sized,
+    [(); (2 * W) * pow2(N - 1)]: Sized,
+    [(); N + (1 + W) * pow2(N - 1)]: Sized,
+    [(); N + (2 * W) * pow2(N - 1)]: Sized,
+{
+    pub fn new() -> Self {
+        let mut levels: Box<dyn Component<{N + (2 * W) * pow2(N - 1)}, {N + (2 * W) * pow2(N - 1)}>> =
+            Box::new(Wiring::<{N + (2 * W) * pow2(N - 1)}, {N + (2 * W) * pow2(N - 1)}>::buffer());
+        for k in 1..N {
+            levels = Box::new(MergeLayers::create(levels, Box::new(demux_level::<N, W, {N + (2 * W) * pow2(N - 1)}>(k))));
+        }
+        // 番号を除いた線がそのまま0番の線から並ぶ
+        let mut table = [0; pow2(N) * W];
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = N + i;
+        }
+        let tree = MergeLayers::create(Box::new(demux_level::<N, W, {N + W}>(0)), levels)
+            .connect_to(Box::new(Wiring::<{N + (2 * W) * pow2(N - 1)}, {pow2(N) * W}>::create(table)));
+        Self { tree }
+    }
+}
+
+// 入力のすべての組み合わせで, 選んだ線が出てくるか, ゲートに展開しても同じか
+#[cfg(test)]
+fn check_mux<const N: usize, const W: usize>(mux: &dyn Component<{N + pow2(N) * W}, W>) where
+    [(); N + pow2(N) * W]: Sized,
+{
+    use crate::num_bit_converter::*;
+
+    let netlist = crate::netlist::flatten(mux).unwrap();
+    for i in 0..pow2(N + pow2(N) * W) {
+        let input = num_to_bit::<{N + pow2(N) * W}>(i);
+        let expected = (i >> (N + (i % pow2(N)) * W)) % pow2(W);
+        assert_eq!(bit_to_num(mux.eval(input)), expected, "{:b}", i);
+        assert_eq!(netlist.eval(&input), mux.eval(input).to_vec());
+    }
+}
+
+#[cfg(test)]
+fn check_demux<const N: usize, const W: usize>(demux: &dyn Component<{N + W}, {pow2(N) * W}>) where
+    [(); N + W]: Sized,
+    [(); pow2(N) * W]: Sized,
+{
+    use crate::num_bit_converter::*;
+
+    let netlist = crate::netlist::flatten(demux).unwrap();
+    for i in 0..pow2(N + W) {
+        let input = num_to_bit::<{N + W}>(i);
+        let expected = (i >> N) << ((i % pow2(N)) * W);
+        assert_eq!(bit_to_num(demux.eval(input)), expected, "{:b}", i);
+        assert_eq!(netlist.eval(&input), demux.eval(input).to_vec());
+    }
+}
+
+#[test]
+fn mux_test() {
+    check_mux::<2, 2>(&Mux::<2, 2>::new());
+    check_mux::<2, 2>(&MuxTree::<2, 2>::new());
+    check_mux::<3, 1>(&Mux::<3, 1>::new());
+    check_mux::<3, 1>(&MuxTree::<3, 1>::new());
+    check_mux::<1, 4>(&Mux::<1, 4>::new());
+    check_mux::<1, 4>(&MuxTree::<1, 4>::new());
+    // 部品の数, 空いた場所の定数はゲートにならない
+    let cell = crate::netlist::flatten(&mux_cell::<1>()).unwrap().count_gates().total();
+    let tree = crate::netlist::flatten(&MuxTree::<3, 1>::new()).unwrap().count_gates().total();
+    assert_eq!(tree, 7 * cell);
+}
+
+#[test]
+fn demux_test() {
+    check_demux::<3, 2>(&Demux::<3, 2>::new());
+    check_demux::<3, 2>(&DemuxTree::<3, 2>::new());
+    check_demux::<2, 3>(&Demux::<2, 3>::new());
+    check_demux::<2, 3>(&DemuxTree::<2, 3>::new());
+    check_demux::<1, 1>(&Demux::<1, 1>::new());
+    check_demux::<1, 1>(&DemuxTree::<1, 1>::new());
+    let cell = crate::netlist::flatten(&demux_cell::<1>()).unwrap().count_gates().total();
+    let tree = crate::netlist::flatten(&DemuxTree::<3, 1>::new()).unwrap().count_gates().total();
+    assert_eq!(tree, 7 * cell);
+
+    // Demuxで出した線をMuxで選び直すと元に戻る
+    use crate::num_bit_converter::*;
+    let (demux, mux) = (Demux::<2, 4>::new(), MuxTree::<2, 4>::new());
+    for i in 0..pow2(6) {
+        let spread = demux.eval(num_to_bit(i));
+        let mut input = [false; 2 + 16];
+        input[..2].copy_from_slice(&num_to_bit::<2>(i));
+        input[2..].copy_from_slice(&spread);
+        assert_eq!(bit_to_num(mux.eval(input)), i >> 2);
+    }
+}
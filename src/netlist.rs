@@ -0,0 +1,244 @@
+use crate::core::*;
+
+// 線の番号, gates[net]がその線を駆動する
+pub type Net = usize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Gate {
+    Input(usize),
+    Const(bool),
+    Not(Net),
+    And(Vec<Net>),
+    Or(Vec<Net>),
+    Nand(Vec<Net>),
+    Nor(Vec<Net>),
+}
+
+impl Gate {
+    pub fn inputs(&self) -> &[Net] {
+        match self {
+            Gate::Input(_) | Gate::Const(_) => &[],
+            Gate::Not(a) => std::slice::from_ref(a),
+            Gate::And(ins) | Gate::Or(ins) | Gate::Nand(ins) | Gate::Nor(ins) => ins,
+        }
+    }
+    pub fn is_logic(&self) -> bool {
+        !matches!(self, Gate::Input(_) | Gate::Const(_))
+    }
+    // 入力線の番号を付け替えたゲート
+    pub fn map_inputs(&self, f: impl Fn(Net) -> Net) -> Gate {
+        let map = |ins: &Vec<Net>| ins.iter().map(|&n| f(n)).collect();
+        match self {
+            Gate::Input(i) => Gate::Input(*i),
+            Gate::Const(v) => Gate::Const(*v),
+            Gate::Not(a) => Gate::Not(f(*a)),
+            Gate::And(ins) => Gate::And(map(ins)),
+            Gate::Or(ins) => Gate::Or(map(ins)),
+            Gate::Nand(ins) => Gate::Nand(map(ins)),
+            Gate::Nor(ins) => Gate::Nor(map(ins)),
+        }
+    }
+}
+
+// 種類ごとのゲートの数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GateCount {
+    pub not: usize,
+    pub and: usize,
+    pub or: usize,
+    pub nand: usize,
+    pub nor: usize,
+}
+
+impl GateCount {
+    pub fn total(&self) -> usize {
+        self.not + self.and + self.or + self.nand + self.nor
+    }
+}
+
+// 回路の大きさと速さの目安
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub gates: GateCount,
+    pub depth: usize,
+}
+
+// 部品をゲートまで平らにしたもの
+// gatesは入力から順に並んでいるので前から評価すればよい
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Netlist {
+    gates: Vec<Gate>,
+    outputs: Vec<Net>,
+}
+
+impl Netlist {
+    pub fn new(inputs: usize) -> Self {
+        Self {
+            gates: (0..inputs).map(Gate::Input).collect(),
+            outputs: Vec::new(),
+        }
+    }
+    pub fn add(&mut self, gate: Gate) -> Net {
+        self.gates.push(gate);
+        self.gates.len() - 1
+    }
+    pub fn input_nets(&self) -> Vec<Net> {
+        (0..self.inputs()).collect()
+    }
+    pub fn inputs(&self) -> usize {
+        self.gates.iter().filter(|g| matches!(g, Gate::Input(_))).count()
+    }
+    pub fn gates(&self) -> &[Gate] {
+        &self.gates
+    }
+    pub fn outputs(&self) -> &[Net] {
+        &self.outputs
+    }
+    pub fn set_outputs(&mut self, outputs: Vec<Net>) {
+        self.outputs = outputs;
+    }
+    // InputとConstを除いたゲートの数
+    pub fn gate_count(&self) -> usize {
+        self.gates.iter().filter(|g| g.is_logic()).count()
+    }
+    pub fn count_gates(&self) -> GateCount {
+        let mut count = GateCount::default();
+        for gate in self.gates.iter() {
+            match gate {
+                Gate::Input(_) | Gate::Const(_) => {}
+                Gate::Not(_) => count.not += 1,
+                Gate::And(_) => count.and += 1,
+                Gate::Or(_) => count.or += 1,
+                Gate::Nand(_) => count.nand += 1,
+                Gate::Nor(_) => count.nor += 1,
+            }
+        }
+        count
+    }
+    // 入力から出力までに通るゲートの段数の一番多いもの, Notも1段と数える
+    pub fn depth(&self) -> usize {
+        let mut levels: Vec<usize> = Vec::with_capacity(self.gates.len());
+        for gate in self.gates.iter() {
+            let level = match gate {
+                Gate::Input(_) | Gate::Const(_) => 0,
+                _ => 1 + gate.inputs().iter().map(|&n| levels[n]).max().unwrap_or(0),
+            };
+            levels.push(level);
+        }
+        self.outputs.iter().map(|&n| levels[n]).max().unwrap_or(0)
+    }
+    pub fn metrics(&self) -> Metrics {
+        Metrics { gates: self.count_gates(), depth: self.depth() }
+    }
+    pub fn eval(&self, input: &[bool]) -> Vec<bool> {
+        let mut values: Vec<bool> = Vec::with_capacity(self.gates.len());
+        for gate in self.gates.iter() {
+            let v = match gate {
+                Gate::Input(i) => input[*i],
+                Gate::Const(v) => *v,
+                Gate::Not(a) => !values[*a],
+                Gate::And(ins) => ins.iter().all(|&n| values[n]),
+                Gate::Or(ins) => ins.iter().any(|&n| values[n]),
+                Gate::Nand(ins) => !ins.iter().all(|&n| values[n]),
+                Gate::Nor(ins) => !ins.iter().any(|&n| values[n]),
+            };
+            values.push(v);
+        }
+        self.outputs.iter().map(|&n| values[n]).collect()
+    }
+}
+
+// Netlistを部品として使うためのもの
+pub struct FlatCircuit<const I: usize, const O: usize> {
+    netlist: Netlist,
+}
+
+impl<const I: usize, const O: usize> Component<I, O> for FlatCircuit<I, O> {
+    fn eval(&self, input: [bool; I]) -> [bool; O] {
+        let mut output = [false; O];
+        output.copy_from_slice(&self.netlist.eval(&input));
+        output
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        // 入力線を付け替えながら写す
+        let mut map = Vec::with_capacity(self.netlist.gates.len());
+        for gate in self.netlist.gates.iter() {
+            let net = match gate {
+                Gate::Input(i) => input[*i],
+                _ => netlist.add(gate.map_inputs(|n| map[n])),
+            };
+            map.push(net);
+        }
+        Some(self.netlist.outputs.iter().map(|&n| map[n]).collect())
+    }
+    fn stateless(&self) -> bool {
+        true
+    }
+}
+
+impl<const I: usize, const O: usize> FlatCircuit<I, O> {
+    // 幅が合わないときはNone
+    pub fn create(netlist: Netlist) -> Option<Self> {
+        if netlist.inputs() != I || netlist.outputs.len() != O {
+            return None;
+        }
+        Some(Self { netlist })
+    }
+    pub fn netlist(&self) -> &Netlist {
+        &self.netlist
+    }
+}
+
+// 内部状態を持つ部品などゲートに展開できないものが含まれるとNone
+pub fn flatten<const I: usize, const O: usize>(component: &dyn Component<I, O>) -> Option<Netlist> {
+    let mut netlist = Netlist::new(I);
+    let input = netlist.input_nets();
+    let outputs = component.netlist(&mut netlist, &input)?;
+    netlist.set_outputs(outputs);
+    Some(netlist)
+}
+
+// 幅の分からない部品を平らにする, 階層の途中の部品を調べるのに使う
+pub fn flatten_node(node: &dyn Node) -> Option<Netlist> {
+    let mut netlist = Netlist::new(node.inputs());
+    let input = netlist.input_nets();
+    let outputs = node.netlist(&mut netlist, &input)?;
+    netlist.set_outputs(outputs);
+    Some(netlist)
+}
+
+#[test]
+fn flatten_test() {
+    use crate::arithmetic_comp::FullAdder;
+    use crate::num_bit_converter::*;
+
+    let adder = FullAdder::new();
+    let netlist = flatten(&adder).unwrap();
+    assert_eq!(netlist.inputs(), 3);
+    for i in 0..8 {
+        let input = num_to_bit::<3>(i);
+        assert_eq!(netlist.eval(&input), adder.eval(input).to_vec());
+    }
+
+    let flat = FlatCircuit::<3, 2>::create(netlist.clone()).unwrap();
+    assert_eq!(flatten(&flat).unwrap().eval(&[true, true, false]), vec![false, true]);
+    assert!(FlatCircuit::<2, 2>::create(netlist).is_none());
+
+    assert!(flatten(&crate::memory::MemoryCell::new()).is_none());
+}
+
+#[test]
+fn depth_test() {
+    use crate::arithmetic_comp::{FullAdder, HalfAdder};
+
+    // 半加算器はXorの中の Or と Nand, And の2段
+    let half = flatten(&HalfAdder::new()).unwrap();
+    assert_eq!(half.depth(), 2);
+    let full = flatten(&FullAdder::new()).unwrap().metrics();
+    assert_eq!(full.depth, 4);
+    assert_eq!(full.gates.total(), flatten(&FullAdder::new()).unwrap().gate_count());
+
+    let mut wire = Netlist::new(2);
+    wire.set_outputs(vec![1, 0]);
+    assert_eq!(wire.depth(), 0);
+}
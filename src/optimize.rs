@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+use crate::core::*;
+use crate::netlist::*;
+
+fn fuse(node: &mut dyn Node) {
+    for (_, child) in node.children_mut() {
+        fuse(child);
+    }
+    node.fuse_children();
+}
+
+// 配線だけでできた部分を下から順に一つの表にまとめる
+// connect_toでつないだ鎖のように入れ子になったMergeLayersでも, 隣り合う配線は一つの表になる
+pub fn fuse_wirings<const I: usize, const O: usize>(root: &mut dyn Component<I, O>) {
+    for (_, child) in root.children_mut() {
+        fuse(child);
+    }
+    root.fuse_children();
+}
+
+#[test]
+fn fuse_wirings_test() {
+    use crate::basic_comp::*;
+    use crate::num_bit_converter::*;
+
+    // 配線, 配線, Not, 配線
+    let build = || {
+        let wires = MergeLayers::create(
+            Box::new(Wiring::<4, 4>::reverse()),
+            Box::new(ConcatDifferentShapeBlocks::create(
+                Box::new(Wiring::<2, 2>::create([1, 0])),
+                Box::new(Wiring::<2, 3>::create([0, 1, 1])),
+            )),
+        );
+        MergeLayers::create(Box::new(wires), Box::new(ConcatBlocks::<1, 1, 5>::create_from_fn(Not::new)))
+            .connect_to(Box::new(Wiring::<5, 5>::rotate_right::<1>()))
+    };
+    let original = build();
+    let mut fused = build();
+    fuse_wirings(&mut fused);
+
+    let first = fused.children()[0].1.children()[0].1;
+    assert_eq!(first.wiring_table(), Some(vec![2, 3, 1, 0, 0]));
+    for i in 0..16 {
+        let input = num_to_bit::<4>(i);
+        assert_eq!(original.eval(input), fused.eval(input));
+    }
+}
+
+#[test]
+fn fuse_nested_wirings_test() {
+    use crate::basic_comp::*;
+    use crate::num_bit_converter::*;
+
+    // MergeLayers(MergeLayers(MergeLayers(Not, 配線), 配線), Not) の配線2つが1つになる
+    let build = || {
+        MergeLayers::create(
+            Box::new(ConcatBlocks::<1, 1, 4>::create_from_fn(Not::new)),
+            Box::new(Wiring::<4, 4>::reverse()),
+        )
+        .connect_to(Box::new(Wiring::<4, 4>::rotate_right::<1>()))
+        .connect_to(Box::new(ConcatBlocks::<1, 1, 4>::create_from_fn(Not::new)))
+    };
+    let original = build();
+    let mut fused = build();
+    fuse_wirings(&mut fused);
+    assert_eq!(original.wiring_layers(), 2);
+    assert_eq!(fused.wiring_layers(), 1);
+    assert_eq!(fused.children()[0].1.trailing_wiring(), Some(vec![0, 3, 2, 1]));
+    for i in 0..16 {
+        let input = num_to_bit::<4>(i);
+        assert_eq!(original.eval(input), fused.eval(input));
+    }
+    assert_eq!(flatten(&fused), flatten(&original));
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptimizeReport {
+    pub gates_before: usize,
+    pub gates_after: usize,
+    // 定数になったか, 入力をそのまま通すだけになって消えたゲート
+    pub folded: usize,
+    // どの出力にも届かないので消したゲート
+    pub dead: usize,
+    // 同じ種類で同じ入力の別のゲートにまとめたゲート
+    pub shared: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    Const(bool),
+    Net(Net),
+}
+
+// And, Nand, Or, Norを畳む, orならOr系, negate_outputならNがつく方
+fn fold_and(folded: &mut Netlist, inputs: &[Value], negate_output: bool, or: bool) -> Value {
+    // Andならfalse, Orならtrueが一つでもあれば決まる
+    let dominant = or;
+    if inputs.iter().any(|v| matches!(v, Value::Const(c) if *c == dominant)) {
+        return Value::Const(dominant != negate_output);
+    }
+    let mut nets = inputs.iter()
+        .filter_map(|v| match v {
+            Value::Net(n) => Some(*n),
+            Value::Const(_) => None,
+        })
+        .collect::<Vec<_>>();
+    nets.sort();
+    nets.dedup();
+    match (nets.len(), negate_output) {
+        (0, _) => Value::Const(!dominant != negate_output),
+        (1, false) => Value::Net(nets[0]),
+        (1, true) => fold_not(folded, Value::Net(nets[0])),
+        (_, false) if or => Value::Net(folded.add(Gate::Or(nets))),
+        (_, false) => Value::Net(folded.add(Gate::And(nets))),
+        (_, true) if or => Value::Net(folded.add(Gate::Nor(nets))),
+        (_, true) => Value::Net(folded.add(Gate::Nand(nets))),
+    }
+}
+
+fn fold_not(folded: &mut Netlist, input: Value) -> Value {
+    match input {
+        Value::Const(v) => Value::Const(!v),
+        Value::Net(n) => match folded.gates()[n] {
+            Gate::Not(m) => Value::Net(m),
+            _ => Value::Net(folded.add(Gate::Not(n))),
+        },
+    }
+}
+
+// 出力から逆にたどって使われているゲートだけ残す, 入力線は幅を変えないためにすべて残す
+pub fn remove_dead(netlist: &Netlist) -> Netlist {
+    let gates = netlist.gates();
+    let mut live = vec![false; gates.len()];
+    for &n in netlist.outputs() {
+        live[n] = true;
+    }
+    for n in (0..gates.len()).rev() {
+        if live[n] {
+            for &m in gates[n].inputs() {
+                live[m] = true;
+            }
+        }
+    }
+
+    let mut result = Netlist::new(netlist.inputs());
+    let mut map = vec![0; gates.len()];
+    for (n, gate) in gates.iter().enumerate() {
+        map[n] = match gate {
+            Gate::Input(i) => *i,
+            _ if !live[n] => continue,
+            _ => result.add(gate.map_inputs(|m| map[m])),
+        };
+    }
+    result.set_outputs(netlist.outputs().iter().map(|&n| map[n]).collect());
+    result
+}
+
+// 定数をゲートの先へ伝えて畳み込み, 出力に届かないゲートを消す
+pub fn propagate_constants(netlist: &Netlist) -> (Netlist, OptimizeReport) {
+    let mut folded = Netlist::new(netlist.inputs());
+    let mut values: Vec<Value> = Vec::with_capacity(netlist.gates().len());
+    for gate in netlist.gates() {
+        let ins = gate.inputs().iter().map(|&n| values[n]).collect::<Vec<_>>();
+        let value = match gate {
+            Gate::Input(i) => Value::Net(*i),
+            Gate::Const(v) => Value::Const(*v),
+            Gate::Not(_) => fold_not(&mut folded, ins[0]),
+            Gate::And(_) => fold_and(&mut folded, &ins, false, false),
+            Gate::Nand(_) => fold_and(&mut folded, &ins, true, false),
+            Gate::Or(_) => fold_and(&mut folded, &ins, false, true),
+            Gate::Nor(_) => fold_and(&mut folded, &ins, true, true),
+        };
+        values.push(value);
+    }
+    let mut constants = [None, None];
+    let outputs = netlist.outputs()
+        .iter()
+        .map(|&n| match values[n] {
+            Value::Net(m) => m,
+            Value::Const(v) => *constants[v as usize].get_or_insert_with(|| folded.add(Gate::Const(v))),
+        })
+        .collect();
+    folded.set_outputs(outputs);
+
+    let result = remove_dead(&folded);
+    let report = OptimizeReport {
+        gates_before: netlist.gate_count(),
+        gates_after: result.gate_count(),
+        folded: netlist.gate_count() - folded.gate_count(),
+        dead: folded.gate_count() - result.gate_count(),
+        shared: 0,
+    };
+    (result, report)
+}
+
+// 同じ種類で同じ入力のゲートを一つにまとめる, And系とOr系は入力の順番と重複を問わない
+// ConcatBlocksで同じ入力に並べた部品などがまとまる
+pub fn structural_hash(netlist: &Netlist) -> (Netlist, usize) {
+    let mut result = Netlist::new(netlist.inputs());
+    let mut seen: HashMap<Gate, Net> = HashMap::new();
+    let mut map = Vec::with_capacity(netlist.gates().len());
+    for gate in netlist.gates() {
+        let net = match gate.map_inputs(|n| map[n]) {
+            Gate::Input(i) => i,
+            gate => {
+                let gate = match gate {
+                    Gate::And(ins) => Gate::And(normalize(ins)),
+                    Gate::Or(ins) => Gate::Or(normalize(ins)),
+                    Gate::Nand(ins) => Gate::Nand(normalize(ins)),
+                    Gate::Nor(ins) => Gate::Nor(normalize(ins)),
+                    gate => gate,
+                };
+                match seen.get(&gate) {
+                    Some(&n) => n,
+                    None => {
+                        let n = result.add(gate.clone());
+                        seen.insert(gate, n);
+                        n
+                    }
+                }
+            }
+        };
+        map.push(net);
+    }
+    result.set_outputs(netlist.outputs().iter().map(|&n| map[n]).collect());
+    let shared = netlist.gate_count() - result.gate_count();
+    (result, shared)
+}
+
+fn normalize(mut ins: Vec<Net>) -> Vec<Net> {
+    ins.sort();
+    ins.dedup();
+    ins
+}
+
+fn optimize_netlist(netlist: &Netlist) -> (Netlist, OptimizeReport) {
+    let (netlist, mut report) = propagate_constants(netlist);
+    let (netlist, shared) = structural_hash(&netlist);
+    report.shared = shared;
+    report.gates_after = netlist.gate_count();
+    (netlist, report)
+}
+
+// 部品を平らにしてから最適化したもの, ゲートに展開できなければNone
+// 内部状態を持つ部品を含むものはsimplify_blocksを使う
+pub fn simplify<const I: usize, const O: usize>(
+    component: &dyn Component<I, O>,
+) -> Option<(FlatCircuit<I, O>, OptimizeReport)> {
+    let (netlist, report) = optimize_netlist(&flatten(component)?);
+    Some((FlatCircuit::create(netlist)?, report))
+}
+
+// ゲートに展開できる一番大きな部分ごとに最適化し, 小さくなったものだけ置き換える
+fn simplify_node(node: &mut dyn Node, report: &mut OptimizeReport) {
+    let Some(netlist) = flatten_node(node) else {
+        for (_, child) in node.children_mut() {
+            simplify_node(child, report);
+        }
+        return;
+    };
+    let (netlist, block) = optimize_netlist(&netlist);
+    if block.gates_after < block.gates_before && node.replace_with(netlist) {
+        report.gates_before += block.gates_before;
+        report.gates_after += block.gates_after;
+        report.folded += block.folded;
+        report.dead += block.dead;
+        report.shared += block.shared;
+    }
+}
+
+// 内部状態を持つ部品の中にある, 状態を持たない部分をそれぞれ最適化したものに置き換える
+// 定数は内部状態を持つ部品の境界を越えては伝わらないので, simplifyほどは小さくならない
+// MicroProgramCounterのようにConstantを直接MemoryCellのreadにつないだものは畳めない
+// 置き換えた部分の中の名前はパスでたどれなくなる. 報告は置き換えた部分だけの合計
+pub fn simplify_blocks<const I: usize, const O: usize>(
+    root: &mut dyn Component<I, O>,
+) -> OptimizeReport {
+    let mut report = OptimizeReport {
+        gates_before: 0,
+        gates_after: 0,
+        folded: 0,
+        dead: 0,
+        shared: 0,
+    };
+    for (_, child) in root.children_mut() {
+        simplify_node(child, &mut report);
+    }
+    report
+}
+
+#[test]
+fn propagate_constants_test() {
+    use crate::arithmetic_comp::FullAdder;
+    use crate::basic_comp::*;
+    use crate::num_bit_converter::*;
+
+    // 繰り上がりの入力を0に固定した全加算器は半加算器と同じ
+    let adder = MergeLayers::create(
+        Box::new(ConcatDifferentShapeBlocks::create(
+            Box::new(Constant::<0, 1, false>::new()),
+            Box::new(Wiring::<2, 2>::buffer()),
+        )),
+        Box::new(FullAdder::new()),
+    );
+    let (simplified, report) = simplify(&adder).unwrap();
+    for i in 0..4 {
+        let input = num_to_bit::<2>(i);
+        assert_eq!(simplified.eval(input), adder.eval(input));
+    }
+    assert!(report.folded > 0);
+    assert!(report.gates_after < report.gates_before);
+    assert_eq!(report.gates_after, simplified.netlist().gate_count());
+
+    // 使わない出力につながるゲートは消える
+    let sum_only = MergeLayers::create(
+        Box::new(crate::arithmetic_comp::HalfAdder::new()),
+        Box::new(Wiring::<2, 1>::create([0])),
+    );
+    let (simplified, report) = simplify(&sum_only).unwrap();
+    assert_eq!(report.dead, 1);
+    for i in 0..4 {
+        let input = num_to_bit::<2>(i);
+        assert_eq!(simplified.eval(input), sum_only.eval(input));
+    }
+}
+
+#[test]
+fn simplify_blocks_test() {
+    use crate::arithmetic_comp::FullAdder;
+    use crate::basic_comp::*;
+    use crate::hierarchy::paths;
+    use crate::memory::MemoryCell;
+    use crate::num_bit_converter::*;
+
+    // a, b, write: 繰り上がりの入力を0に固定した全加算器の和をMemoryCellに書き込んで読む
+    let build = || {
+        let adder = MergeLayers::create(
+            Box::new(ConcatDifferentShapeBlocks::create(
+                Box::new(Constant::<0, 1, false>::new()),
+                Box::new(Wiring::<2, 2>::buffer()),
+            )),
+            Box::new(FullAdder::new()),
+        );
+        MergeLayers::create(
+            Box::new(ConcatDifferentShapeBlocks::<2, 1, 2, 1>::create(Box::new(adder), Box::new(Buffer::new()))),
+            Box::new(Wiring::<3, 3>::create([2, 2, 0])),
+        )
+        .connect_to(Box::new(MemoryCell::new()))
+    };
+    let mut original = build();
+    let mut simplified = build();
+    assert!(simplify(&simplified).is_none());
+
+    let report = simplify_blocks(&mut simplified);
+    assert!(report.folded > 0);
+    assert!(report.gates_after < report.gates_before);
+    // 内部状態を持つ部品はそのまま残る
+    assert!(paths(&simplified).contains(&"ff".to_string()));
+    for _ in 0..2 {
+        for i in 0..8 {
+            let input = num_to_bit::<3>(i);
+            assert_eq!(simplified.eval_mut(input), original.eval_mut(input));
+        }
+    }
+}
+
+#[test]
+fn propagate_constants_output_test() {
+    use crate::basic_comp::*;
+
+    // x & !x のような恒等式までは見ないが, 定数の出力は一つの線にまとまる
+    let constant = ConcatBlocks::<1, 1, 2>::create([
+        Box::new(MergeLayers::create(Box::new(Constant::<1, 1, true>::new()), Box::new(Not::new()))),
+        Box::new(Constant::<1, 1, false>::new()),
+    ]);
+    let (simplified, report) = simplify(&constant).unwrap();
+    assert_eq!(report.gates_after, 0);
+    assert_eq!(simplified.netlist().gates().len(), 3);
+    assert_eq!(simplified.eval([true, true]), [false, false]);
+}
+
+#[test]
+fn structural_hash_test() {
+    use crate::basic_comp::*;
+    use crate::num_bit_converter::*;
+
+    // 同じ2本を入れ替えてつないだAndが4つ, まとめると1つ
+    let ands = MergeLayers::create(
+        Box::new(Wiring::<2, 8>::create([0, 1, 1, 0, 0, 1, 1, 0])),
+        Box::new(ConcatBlocks::<2, 1, 4>::create_from_fn(And::<2>::new)),
+    );
+    let netlist = flatten(&ands).unwrap();
+    let (hashed, shared) = structural_hash(&netlist);
+    assert_eq!(shared, 3);
+    assert_eq!(hashed.gate_count(), 1);
+    assert_eq!(hashed.outputs(), &[2, 2, 2, 2]);
+    for i in 0..4 {
+        let input = num_to_bit::<2>(i);
+        assert_eq!(hashed.eval(&input), ands.eval(input).to_vec());
+    }
+
+    // 同じ入力に並べた全加算器
+    let adders = MergeLayers::create(
+        Box::new(Wiring::<3, 6>::create([0, 1, 2, 0, 1, 2])),
+        Box::new(ConcatBlocks::<3, 2, 2>::create_shared(crate::arithmetic_comp::FullAdder::new)),
+    );
+    let (simplified, report) = simplify(&adders).unwrap();
+    assert_eq!(report.gates_after * 2, report.gates_before);
+    assert_eq!(report.shared, report.gates_after);
+    for i in 0..8 {
+        let input = num_to_bit::<3>(i);
+        assert_eq!(simplified.eval(input), adders.eval(input));
+    }
+}
@@ -0,0 +1,273 @@
+use crate::core::*;
+use crate::basic_comp::*;
+use crate::decoder::*;
+use crate::memory::MemoryByte;
+
+// 2^R個のWbitレジスタ, 読み出し2つと書き込み1つを同時に行う
+// 入力は write enable, 書き込み番地(R), 読み出し番地A(R), 読み出し番地B(R), 書き込む値(W) の順
+// 出力はA, Bの順で, 同じ評価で書き込んだ値はそのまま読める
+// write_select, regs, read_a, read_b の名前でたどれる
+pub struct RegisterFile<const R: usize, const W: usize> where
+    [(); 1 + 3 * R + W]: Sized,
+    [(); W + W]: Sized,
+    [(); 2 * W]: Sized,
+{
+    file: MergeLayers<{1 + 3 * R + W}, {W + W}, {2 * W}>,
+}
+
+impl<const R: usize, const W: usize> Component<{1 + 3 * R + W}, {2 * W}> for RegisterFile<R, W> where
+    [(); 1 + 3 * R + W]: Sized,
+    [(); W + W]: Sized,
+    [(); 2 * W]: Sized,
+{
+    fn eval(&self, input: [bool; 1 + 3 * R + W]) -> [bool; 2 * W] {
+        self.file.eval(input)
+    }
+    fn eval_mut(&mut self, input: [bool; 1 + 3 * R + W]) -> [bool; 2 * W] {
+        self.file.eval_mut(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.file.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.file.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.file.fuse_children()
+    }
+}
+
+impl<const R: usize, const W: usize> RegisterFile<R, W> where
+    [(); 1 + 3 * R + W]: Sized,
+    [(); W + W]: Sized,
+    [(); 2 * W]: Sized,
+    [(); 1 + R]: Sized,
+    [(); 2 + R]: Sized,
+    [(); 1 + R + 1]: Sized,
+    [(); 2 * R]: Sized,
+    [(); 2 * R + W]: Sized,
+    [(); 2 + R + (2 * R + W)]: Sized,
+    [(); pow2(R) + 1]: Sized,
+    [(); pow2(R) + 1 + (2 * R + W)]: Sized,
+    [(); 2 * R + (W + 2) * pow2(R)]: Sized,
+    [(); 2 * R + W * pow2(R)]: Sized,
+    [(); R + pow2(R) * W]: Sized,
+    [(); R + pow2(R) * W + (R + pow2(R) * W)]: Sized,
+    [(); 1 * pow2(R)]: Sized,
+    [(); 2 * pow2(R)]: Sized,
+    [(); pow2(R) - 1]: Sized,
+    [(); 2 * (pow2(R) - 1)]: Sized,
+    [(); 1 * (pow2(R) - 1)]: Sized,
+    [(); 2 + 2 * (pow2(R) - 1)]: Sized,
+    [(); 1 + 1 * (pow2(R) - 1)]: Sized,
+    [(); pow2(R) + pow2(R)]: Sized,
+    [(); 1 * W]: Sized,
+    [(); W + 2]: Sized,
+    [(); 3 * W]: Sized,
+    [(); (W + 2) * pow2(R)]: Sized,
+    [(); W * pow2(R)]: Sized,
+    [(); pow2(R) + pow2(R) * W]: Sized,
+    [(); 2 * (pow2(R) * W)]: Sized,
+    [(); 1 * (pow2(R) * W)]: Sized,
+    [(); pow2(R) * W]: Sized,
+
+    BitDecoder<R>: Sized,
+    MergeLayers<R, { 2_usize * R }, { pow2(R) }>: Sized,
+    [(); 1 * R]: Sized,
+    [(); 1 * R * 2]: Sized,
+    [(); pow2(R - 1)]: Sized,
+    [(); R * pow2(R)]: Sized,
+    Box<dyn Component<{ 2 * 1 * R }, { 2 * 1 * R }>>: Sized,
+{
+    pub fn new() -> Self {
+        Self::create(false)
+    }
+    // 0番のレジスタを常に0にしたもの
+    pub fn with_zero_register() -> Self {
+        Self::create(true)
+    }
+    fn create(zero_register: bool) -> Self {
+        // write enable, 書き込み番地, 読み出しを有効にする1を作るためのwrite enable, 残り
+        let mut table = [0; 2 + R + (2 * R + W)];
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = match i {
+                i if i < 1 + R => i,
+                i if i == 1 + R => 0,
+                i => i - 1,
+            };
+        }
+        let layer1 = Wiring::<{1 + 3 * R + W}, {2 + R + (2 * R + W)}>::create(table);
+        let write_control = MergeLayers::create(
+            Box::new(Wiring::<{2 + R}, {1 + R + 1}>::wrapper()),
+            Box::new(ConcatDifferentShapeBlocks::<{1 + R}, 1, {pow2(R)}, 1>::create(
+                Box::new(Named::create("write_select", Box::new(Self::write_select(zero_register)))),
+                Box::new(Constant::<1, 1, true>::new()),
+            )),
+        );
+        let layer2 = ConcatDifferentShapeBlocks::<{2 + R}, {2 * R + W}, {pow2(R) + 1}, {2 * R + W}>::create(
+            Box::new(write_control),
+            Box::new(Wiring::<{2 * R + W}, {2 * R + W}>::buffer()),
+        );
+
+        // 読み出し番地A, B, レジスタごとに (read = 1, write, 値)
+        let one = pow2(R);
+        let mut table = [0; 2 * R + (W + 2) * pow2(R)];
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = if i < 2 * R {
+                one + 1 + i
+            } else {
+                let (reg, k) = ((i - 2 * R) / (W + 2), (i - 2 * R) % (W + 2));
+                match k {
+                    0 => one,
+                    1 => reg,
+                    _ => one + 1 + 2 * R + k - 2,
+                }
+            };
+        }
+        let layer3 = Wiring::<{pow2(R) + 1 + (2 * R + W)}, {2 * R + (W + 2) * pow2(R)}>::create(table);
+        let regs = ConcatBlocks::<{W + 2}, W, {pow2(R)}>::create_shared(MemoryByte::<W>::new)
+            .named("regs");
+        let layer4 = ConcatDifferentShapeBlocks::<{2 * R}, {(W + 2) * pow2(R)}, {2 * R}, {W * pow2(R)}>::create(
+            Box::new(Wiring::<{2 * R}, {2 * R}>::buffer()),
+            Box::new(regs),
+        );
+
+        // 読み出し番地とすべてのレジスタの値をそれぞれの読み出し口へ
+        let values = W * pow2(R);
+        let mut table = [0; R + pow2(R) * W + (R + pow2(R) * W)];
+        for (i, v) in table.iter_mut().enumerate() {
+            let (port, j) = (i / (R + values), i % (R + values));
+            *v = if j < R { port * R + j } else { 2 * R + j - R };
+        }
+        let layer5 = Wiring::<{2 * R + W * pow2(R)}, {R + pow2(R) * W + (R + pow2(R) * W)}>::create(table);
+        let layer6 = ConcatDifferentShapeBlocks::<{R + pow2(R) * W}, {R + pow2(R) * W}, W, W>::create(
+            Box::new(Named::create("read_a", Box::new(Self::read_port()))),
+            Box::new(Named::create("read_b", Box::new(Self::read_port()))),
+        );
+        let out_wrapper = Wiring::<{W + W}, {2 * W}>::wrapper();
+
+        let file = MergeLayers::create(Box::new(layer1), Box::new(layer2))
+            .connect_to(Box::new(layer3))
+            .connect_to(Box::new(layer4))
+            .connect_to(Box::new(layer5))
+            .connect_to(Box::new(layer6))
+            .connect_to(Box::new(out_wrapper));
+        Self { file }
+    }
+    // write enableとデコードした番地のAnd, 0番のレジスタを0に固定するときはその線をConstantにする
+    fn write_select(zero_register: bool) -> MergeLayers<{1 + R}, {1 + 1 * (pow2(R) - 1)}, {pow2(R)}> {
+        let layer1 = ConcatDifferentShapeBlocks::<1, R, {pow2(R)}, {pow2(R)}>::create(
+            Box::new(Branch::<{pow2(R)}>::new()),
+            Box::new(BitDecoder::<R>::new()),
+        );
+        // enable0, select0, enable1, select1, ...
+        let mut table = [0; 2 * pow2(R)];
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = if i % 2 == 0 { i / 2 } else { pow2(R) + i / 2 };
+        }
+        let layer2 = Wiring::<{pow2(R) + pow2(R)}, {2 * pow2(R)}>::create(table);
+        let layer23_wrapper = Wiring::<{2 * pow2(R)}, {2 + 2 * (pow2(R) - 1)}>::wrapper();
+        let first: Box<dyn Component<2, 1>> = if zero_register {
+            Box::new(Constant::<2, 1, false>::new())
+        } else {
+            Box::new(And::<2>::new())
+        };
+        let layer3 = ConcatDifferentShapeBlocks::<2, {2 * (pow2(R) - 1)}, 1, {1 * (pow2(R) - 1)}>::create(
+            first,
+            Box::new(ConcatBlocks::<2, 1, {pow2(R) - 1}>::create_from_fn(And::<2>::new)),
+        );
+        let out_wrapper = Wiring::<{1 + 1 * (pow2(R) - 1)}, {pow2(R)}>::wrapper();
+        MergeLayers::create(Box::new(layer1), Box::new(layer2))
+            .connect_to(Box::new(layer23_wrapper))
+            .connect_to(Box::new(layer3))
+            .connect_to(Box::new(out_wrapper))
+    }
+    // 番地をデコードした線と各レジスタの値をAndして, bitごとにOrをとるマルチプレクサ
+    fn read_port() -> MergeLayers<{R + pow2(R) * W}, {1 * W}, W> {
+        let layer1 = ConcatDifferentShapeBlocks::<R, {pow2(R) * W}, {pow2(R)}, {pow2(R) * W}>::create(
+            Box::new(BitDecoder::<R>::new()),
+            Box::new(Wiring::<{pow2(R) * W}, {pow2(R) * W}>::buffer()),
+        );
+        // bitごとに (番地0の選択線, レジスタ0の値), (番地1の選択線, レジスタ1の値), ... と並べる
+        let mut table = [0; 2 * (pow2(R) * W)];
+        for (i, v) in table.iter_mut().enumerate() {
+            let (bit, reg) = (i / 2 / pow2(R), i / 2 % pow2(R));
+            *v = if i % 2 == 0 { reg } else { pow2(R) + reg * W + bit };
+        }
+        let layer2 = Wiring::<{pow2(R) + pow2(R) * W}, {2 * (pow2(R) * W)}>::create(table);
+        let layer3 = ConcatBlocks::<2, 1, {pow2(R) * W}>::create_from_fn(And::<2>::new);
+        let layer34_wrapper = Wiring::<{1 * (pow2(R) * W)}, {pow2(R) * W}>::wrapper();
+        let layer4 = ConcatBlocks::<{pow2(R)}, 1, W>::create_from_fn(Or::<{pow2(R)}>::new);
+        let out_wrapper = Wiring::<{1 * W}, W>::wrapper();
+        MergeLayers::create(Box::new(layer1), Box::new(layer2))
+            .connect_to(Box::new(layer3))
+            .connect_to(Box::new(layer34_wrapper))
+            .connect_to(Box::new(layer4))
+            .connect_to(Box::new(out_wrapper))
+    }
+}
+
+#[test]
+fn register_file_test() {
+    use crate::num_bit_converter::*;
+
+    // 入力のすべての組み合わせを順に流して, Rustで書いたレジスタと比べる
+    for zero_register in [false, true] {
+        let mut file = if zero_register {
+            RegisterFile::<2, 4>::with_zero_register()
+        } else {
+            RegisterFile::<2, 4>::new()
+        };
+        let mut model = [0; 4];
+        for i in 0..(1 << 11) {
+            // 順番を混ぜる
+            let n = (i * 1237) % (1 << 11);
+            let input = num_to_bit::<11>(n);
+            let (enable, write, a, b, data) = (n & 1, (n >> 1) & 3, (n >> 3) & 3, (n >> 5) & 3, n >> 7);
+            if enable == 1 && !(zero_register && write == 0) {
+                model[write] = data;
+            }
+            let output = file.eval_mut(input);
+            assert_eq!(bit_to_num(output), model[a] + (model[b] << 4), "input {:011b}", n);
+        }
+    }
+}
+
+#[test]
+fn general_register_test() {
+    use crate::num_bit_converter::*;
+
+    let mut file = RegisterFile::<3, 8>::with_zero_register();
+    let write = |reg: usize, value: usize| num_to_bit::<18>(1 + (reg << 1) + (value << 10));
+    let read = |a: usize, b: usize| num_to_bit::<18>((a << 4) + (b << 7));
+    for reg in 0..8 {
+        file.eval_mut(write(reg, 0x11 * reg + 1));
+    }
+    for reg in 1..8 {
+        assert_eq!(bit_to_num(file.eval_mut(read(reg, 0))), 0x11 * reg + 1);
+        assert_eq!(bit_to_num(file.eval_mut(read(0, reg))), (0x11 * reg + 1) << 8);
+    }
+    assert!(crate::hierarchy::find(&file, "regs[7].cells[7].ff").is_some());
+}
+
+#[test]
+fn register_file_probe_test() {
+    use crate::hierarchy::*;
+    use crate::num_bit_converter::*;
+
+    // 書き込み先の選択線と読み出し口の出力をパスで見る
+    let mut file = RegisterFile::<2, 4>::with_zero_register();
+    let input = |enable: usize, write: usize, a: usize, b: usize, data: usize| {
+        num_to_bit::<11>(enable + (write << 1) + (a << 3) + (b << 5) + (data << 7))
+    };
+    file.eval_mut(input(1, 2, 2, 0, 0b1010));
+    assert_eq!(probe(&file, "write_select"), Some(vec![false, false, true, false]));
+    assert_eq!(probe(&file, "read_a"), Some(num_to_bit::<4>(0b1010).to_vec()));
+    assert_eq!(probe(&file, "read_b"), Some(vec![false; 4]));
+
+    // 0番への書き込みは選択線の段階で0になる
+    file.eval_mut(input(1, 0, 0, 2, 0b0110));
+    assert_eq!(probe(&file, "write_select"), Some(vec![false; 4]));
+    assert_eq!(probe(&file, "read_b"), Some(num_to_bit::<4>(0b1010).to_vec()));
+    assert!(find(&file, "regs[3].cells[3].ff").is_some());
+}
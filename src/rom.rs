@@ -0,0 +1,256 @@
+use std::path::Path;
+use crate::core::*;
+use crate::netlist::{Net, Netlist};
+use crate::basic_comp::*;
+use crate::decoder::*;
+use crate::image::LoadError;
+
+// 読み出し専用のメモリ, 入力は read, address の順でreadのときだけ値を出す
+// 中身はバイト列から作り, 1語は下位から(Bit + 7) / 8バイトずつ使う, 足りない分は0
+
+// 1語ずつBit本に並べた中身
+fn image_bits<const Address: usize, const Bit: usize>(image: &[u8]) -> Result<Vec<bool>, LoadError> {
+    let word_bytes = Bit.div_ceil(8);
+    let size = pow2(Address);
+    if image.len() > size * word_bytes {
+        return Err(LoadError::OutOfRange { address: image.len() / word_bytes, size });
+    }
+    let mut bits = vec![false; size * Bit];
+    for (i, v) in bits.iter_mut().enumerate() {
+        let (word, bit) = (i / Bit, i % Bit);
+        let byte = image.get(word * word_bytes + bit / 8).copied().unwrap_or(0);
+        *v = byte & (1 << (bit % 8)) != 0;
+    }
+    Ok(bits)
+}
+
+// 番地をデコードしたAND平面と, 各bitで1になっている番地の線を集めるOR平面でできたROM
+pub struct Rom<const Address: usize, const Bit: usize> where
+    [(); Address + 1]: Sized,
+    [(); 1 * Bit]: Sized,
+{
+    rom: MergeLayers<{Address + 1}, {1 * Bit}, Bit>,
+}
+
+impl<const Address: usize, const Bit: usize> Component<{Address + 1}, Bit> for Rom<Address, Bit> where
+    [(); Address + 1]: Sized,
+    [(); 1 * Bit]: Sized,
+{
+    fn eval(&self, input: [bool; Address + 1]) -> [bool; Bit] {
+        self.rom.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        self.rom.children()
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        self.rom.children_mut()
+    }
+    fn fuse_children(&mut self) {
+        self.rom.fuse_children()
+    }
+    fn netlist(&self, netlist: &mut Netlist, input: &[Net]) -> Option<Vec<Net>> {
+        self.rom.netlist(netlist, input)
+    }
+}
+
+// readとOR平面の1本をAndする, OR平面の入力は2^Address本
+fn rom_bit<const P: usize>() -> MergeLayers<{1 + P}, 2, 1> {
+    MergeLayers::create(
+        Box::new(ConcatDifferentShapeBlocks::<1, P, 1, 1>::create(
+            Box::new(Buffer::new()),
+            Box::new(Or::<P>::new()),
+        )),
+        Box::new(And::<2>::new()),
+    )
+}
+
+impl<const Address: usize, const Bit: usize> Rom<Address, Bit> where
+    [(); Address + 1]: Sized,
+    [(); 1 + Address]: Sized,
+    [(); 1 + pow2(Address)]: Sized,
+    [(); 1 + pow2(Address) + 1]: Sized,
+    [(); (1 + pow2(Address)) * Bit]: Sized,
+    [(); 1 * Bit]: Sized,
+    [(); 1 + Address + 0]: Sized,
+
+    BitDecoder<Address>: Sized,
+    MergeLayers<Address, { 2_usize * Address }, { pow2(Address) }>: Sized,
+    [(); 1 * Address]: Sized,
+    [(); 2 * Address]: Sized,
+    [(); 1 * Address * 2]: Sized,
+    [(); pow2(Address - 1)]: Sized,
+    [(); 1 * pow2(Address)]: Sized,
+    [(); Address * pow2(Address)]: Sized,
+    Box<dyn Component<{ 2 * 1 * Address }, { 2 * 1 * Address }>>: Sized,
+{
+    pub fn from_bytes(image: &[u8]) -> Result<Self, LoadError> {
+        let bits = image_bits::<Address, Bit>(image)?;
+        // read, 番地ごとの選択線, 0に固定した線
+        let select = ConcatDifferentShapeBlocks::<{1 + Address}, 0, {1 + pow2(Address)}, 1>::create(
+            Box::new(ConcatDifferentShapeBlocks::<1, Address, 1, {pow2(Address)}>::create(
+                Box::new(Buffer::new()),
+                Box::new(BitDecoder::<Address>::new()),
+            )),
+            Box::new(Constant::<0, 1, false>::new()),
+        );
+        let in_wrapper = Wiring::<{Address + 1}, {1 + Address + 0}>::wrapper();
+        // bitごとに read, 番地0の線, 番地1の線, ... と並べる, 0のところは固定した線につなぐ
+        let mut plane_table = [0; (1 + pow2(Address)) * Bit];
+        for (i, v) in plane_table.iter_mut().enumerate() {
+            let (bit, line) = (i / (1 + pow2(Address)), i % (1 + pow2(Address)));
+            *v = match line {
+                0 => 0,
+                _ if bits[(line - 1) * Bit + bit] => line,
+                _ => 1 + pow2(Address),
+            };
+        }
+        let plane = Wiring::<{1 + pow2(Address) + 1}, {(1 + pow2(Address)) * Bit}>::create(plane_table);
+        let bits = ConcatBlocks::<{1 + pow2(Address)}, 1, Bit>::create_from_fn(rom_bit::<{pow2(Address)}>);
+        let out_wrapper = Wiring::<{1 * Bit}, Bit>::wrapper();
+
+        let rom = MergeLayers::create(Box::new(in_wrapper), Box::new(select))
+            .connect_to(Box::new(plane))
+            .connect_to(Box::new(bits))
+            .connect_to(Box::new(out_wrapper));
+        Ok(Self { rom })
+    }
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+// 中身を表で持つだけのROM, 入出力はRomと同じ
+pub struct FastRom<const Address: usize, const Bit: usize> {
+    data: Vec<bool>,
+}
+
+impl<const Address: usize, const Bit: usize> Component<{Address + 1}, Bit> for FastRom<Address, Bit> where
+    [(); Address + 1]: Sized,
+{
+    fn eval(&self, input: [bool; Address + 1]) -> [bool; Bit] {
+        let mut output = [false; Bit];
+        if input[0] {
+            let mut address = 0;
+            for i in 0..Address {
+                if input[1 + i] {
+                    address |= 1 << i;
+                }
+            }
+            output.copy_from_slice(&self.data[(address * Bit)..((address + 1) * Bit)]);
+        }
+        output
+    }
+}
+
+impl<const Address: usize, const Bit: usize> FastRom<Address, Bit> {
+    pub fn from_bytes(image: &[u8]) -> Result<Self, LoadError> {
+        Ok(Self { data: image_bits::<Address, Bit>(image)? })
+    }
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+// ROMをMemoryと同じ read, write, address, value の入力で使うためのもの
+// 書き込みは無視して回数だけ数える
+pub struct ReadOnly<const Address: usize, const Bit: usize> where
+    [(); Address + 1]: Sized,
+{
+    rom: Box<dyn Component<{Address + 1}, Bit>>,
+    ignored_writes: usize,
+}
+
+impl<const Address: usize, const Bit: usize> Component<{Address + Bit + 2}, Bit> for ReadOnly<Address, Bit> where
+    [(); Address + 1]: Sized,
+    [(); Address + Bit + 2]: Sized,
+{
+    fn eval(&self, input: [bool; Address + Bit + 2]) -> [bool; Bit] {
+        let mut rom_input = [false; Address + 1];
+        rom_input.copy_from_slice(&input[1..(Address + 2)]);
+        rom_input[0] = input[0];
+        self.rom.eval(rom_input)
+    }
+    fn eval_mut(&mut self, input: [bool; Address + Bit + 2]) -> [bool; Bit] {
+        if input[1] {
+            self.ignored_writes += 1;
+        }
+        self.eval(input)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        vec![(None, &self.rom as &dyn Node)]
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        vec![(None, &mut self.rom as &mut dyn Node)]
+    }
+}
+
+impl<const Address: usize, const Bit: usize> ReadOnly<Address, Bit> where
+    [(); Address + 1]: Sized,
+{
+    pub fn create(rom: Box<dyn Component<{Address + 1}, Bit>>) -> Self {
+        Self { rom, ignored_writes: 0 }
+    }
+    // 無視した書き込みの回数
+    pub fn ignored_writes(&self) -> usize {
+        self.ignored_writes
+    }
+}
+
+#[test]
+fn rom_test() {
+    use crate::num_bit_converter::*;
+
+    let image = [0x3c, 0x00, 0xff, 0x81, 0x42, 0x18, 0x7e, 0xa5, 0x5a, 0x01];
+    let rom = Rom::<4, 8>::from_bytes(&image).unwrap();
+    let fast = FastRom::<4, 8>::from_bytes(&image).unwrap();
+    for address in 0..16 {
+        let expected = image.get(address).copied().unwrap_or(0) as usize;
+        let read = num_to_bit::<5>((address << 1) + 1);
+        assert_eq!(rom.eval(read), num_to_bit(expected));
+        assert_eq!(fast.eval(read), num_to_bit(expected));
+        let idle = num_to_bit::<5>(address << 1);
+        assert_eq!(rom.eval(idle), [false; 8]);
+        assert_eq!(fast.eval(idle), [false; 8]);
+    }
+
+    // 12bitの語は2バイトずつ
+    let wide = FastRom::<2, 12>::from_bytes(&[0x34, 0x12, 0xff, 0xff]).unwrap();
+    let gate = Rom::<2, 12>::from_bytes(&[0x34, 0x12, 0xff, 0xff]).unwrap();
+    assert_eq!(wide.eval([true, false, false]), num_to_bit(0x234));
+    assert_eq!(gate.eval([true, true, false]), num_to_bit(0xfff));
+
+    assert!(matches!(
+        FastRom::<2, 8>::from_bytes(&[0; 5]),
+        Err(LoadError::OutOfRange { size: 4, .. })
+    ));
+}
+
+#[test]
+fn rom_flatten_test() {
+    use crate::num_bit_converter::*;
+    use crate::optimize::simplify;
+
+    // 組み合わせ回路なのでゲートに展開できる, 0につないだ線は畳まれる
+    let rom = Rom::<3, 4>::from_bytes(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+    let (flat, report) = simplify(&rom).unwrap();
+    assert!(report.folded > 0);
+    for i in 0..16 {
+        let input = num_to_bit::<4>(i);
+        assert_eq!(flat.eval(input), rom.eval(input));
+    }
+}
+
+#[test]
+fn read_only_test() {
+    use crate::image::*;
+
+    let path = std::env::temp_dir().join("read_only_test.bin");
+    std::fs::write(&path, b"boot").unwrap();
+    let mut rom = ReadOnly::<4, 8>::create(Box::new(Rom::<4, 8>::from_file(&path).unwrap()));
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(dump_binary(&rom, Region::all().length(5)).unwrap(), b"boot\0");
+    load_binary(&mut rom, b"xyz", Region::all()).unwrap();
+    assert_eq!(rom.ignored_writes(), 3);
+    assert_eq!(dump_binary(&rom, Region::all().length(4)).unwrap(), b"boot");
+}
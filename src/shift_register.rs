@@ -0,0 +1,231 @@
+use crate::core::*;
+use crate::basic_comp::*;
+use crate::decoder::BitDecoder;
+use crate::flip_flop::DFlipFlop;
+use crate::num_bit_converter::num_to_bit;
+
+// ShiftRegisterの動作, 左はbit0からbitN-1に向かう方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftMode {
+    Hold,
+    // 値を取り込み, キャリーにはserial inを入れる
+    Load,
+    // 空いたところにserial inを入れる
+    ShiftLeft,
+    ShiftRight,
+    Rotate,
+    RotateRight,
+    // キャリーを含めたN + 1bitで回す
+    RotateThroughCarry,
+    RotateRightThroughCarry,
+}
+
+impl ShiftMode {
+    pub fn bits(self) -> [bool; 3] {
+        num_to_bit(self as usize)
+    }
+}
+
+// modeをデコードして8本の候補から1本選ぶ, 入力は mode(3), 候補(8)
+fn mode_select() -> MergeLayers<11, 8, 1> {
+    let layer1 = ConcatDifferentShapeBlocks::<3, 8, 8, 8>::create(
+        Box::new(BitDecoder::<3>::new()),
+        Box::new(Wiring::<8, 8>::buffer()),
+    );
+    let layer2 = Wiring::<16, 16>::zip::<8>();
+    let layer3 = ConcatBlocks::<2, 1, 8>::create_from_fn(And::<2>::new);
+    MergeLayers::create(Box::new(layer1), Box::new(layer2))
+        .connect_to(Box::new(layer3))
+        .connect_to(Box::new(Or::<8>::new()))
+}
+
+// Nbitのシフトレジスタとキャリー, クロックの立ち上がりでmodeに従って動く
+// 入力は clock, mode(3), serial in, 値(N) の順, 出力は 今の値(N), キャリー
+// キャリーはシフトで押し出されたbitで, 直列に出すときもここから読む
+// Loadしてから右シフトを続ければ並列入力直列出力, シフトしながら値を読めば直列入力並列出力になる
+pub struct ShiftRegister<const N: usize> where
+    [(); 5 + 2 * N]: Sized,
+    [(); 1 * (N + 1)]: Sized,
+    [(); 5 * (N + 1)]: Sized,
+    [(); 2 * (N + 1)]: Sized,
+{
+    // mode, serial in, 値, 今の値, キャリー -> 次の値, 次のキャリー
+    next: MergeLayers<{5 + 2 * N}, {1 * (N + 1)}, {N + 1}>,
+    bits: Box<dyn Component<{5 * (N + 1)}, {2 * (N + 1)}>>,
+}
+
+impl<const N: usize> Component<{5 + N}, {N + 1}> for ShiftRegister<N> where
+    [(); 5 + 2 * N]: Sized,
+    [(); 1 * (N + 1)]: Sized,
+    [(); 5 * (N + 1)]: Sized,
+    [(); 2 * (N + 1)]: Sized,
+    [(); 5 + N]: Sized,
+    [(); N + 1]: Sized,
+{
+    fn eval(&self, input: [bool; 5 + N]) -> [bool; N + 1] {
+        let bits = self.bits.eval(self.bits_input(&input));
+        Self::output(&bits)
+    }
+    fn eval_mut(&mut self, input: [bool; 5 + N]) -> [bool; N + 1] {
+        let bits_input = self.bits_input(&input);
+        let bits = self.bits.eval_mut(bits_input);
+        Self::output(&bits)
+    }
+    fn children(&self) -> Vec<Child<'_>> {
+        vec![(None, &self.bits as &dyn Node)]
+    }
+    fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        vec![(None, &mut self.bits as &mut dyn Node)]
+    }
+}
+
+impl<const N: usize> ShiftRegister<N> where
+    [(); 5 + 2 * N]: Sized,
+    [(); 1 * (N + 1)]: Sized,
+    [(); 5 * (N + 1)]: Sized,
+    [(); 2 * (N + 1)]: Sized,
+    [(); 11 * (N + 1)]: Sized,
+{
+    pub fn new() -> Self {
+        // 入力の中の位置
+        let (serial_in, value, current, carry) = (3, |i| 4 + i, |i| 4 + N + i, 4 + 2 * N);
+        // bitごとに mode, Holdから順に8つの候補 と並べる, 最後のN番目はキャリー
+        let mut table = [0; 11 * (N + 1)];
+        for (bit, chunk) in table.chunks_mut(11).enumerate() {
+            let candidates = if bit == N {
+                [carry, serial_in, current(N - 1), current(0), current(N - 1), current(0), current(N - 1), current(0)]
+            } else {
+                let (lower, upper) = (bit.checked_sub(1), (bit + 1 < N).then_some(bit + 1));
+                [
+                    current(bit),
+                    value(bit),
+                    lower.map_or(serial_in, current),
+                    upper.map_or(serial_in, current),
+                    current(lower.unwrap_or(N - 1)),
+                    current(upper.unwrap_or(0)),
+                    lower.map_or(carry, current),
+                    upper.map_or(carry, current),
+                ]
+            };
+            chunk[..3].copy_from_slice(&[0, 1, 2]);
+            chunk[3..].copy_from_slice(&candidates);
+        }
+        let layer1 = Wiring::<{5 + 2 * N}, {11 * (N + 1)}>::create(table);
+        let layer2 = ConcatBlocks::<11, 1, {N + 1}>::create_from_fn(mode_select);
+        let out_wrapper = Wiring::<{1 * (N + 1)}, {N + 1}>::wrapper();
+        let next = MergeLayers::create(Box::new(layer1), Box::new(layer2))
+            .connect_to(Box::new(out_wrapper));
+        let bits = ConcatBlocks::<5, 2, {N + 1}>::create_from_fn(DFlipFlop::new).named("bits");
+        Self { next, bits: Box::new(bits) }
+    }
+}
+
+impl<const N: usize> ShiftRegister<N> where
+    [(); 5 + 2 * N]: Sized,
+    [(); 1 * (N + 1)]: Sized,
+    [(); 5 * (N + 1)]: Sized,
+    [(); 2 * (N + 1)]: Sized,
+    [(); N + 1]: Sized,
+{
+    // 今の値とキャリー, クロックを0にして何も取り込まなければスレーブの値がそのまま出る
+    pub fn value(&self) -> [bool; N + 1] {
+        Self::output(&self.bits.eval([false; 5 * (N + 1)]))
+    }
+    // 各bitのフリップフロップへの clock, d, enable, set, reset
+    fn bits_input(&self, input: &[bool]) -> [bool; 5 * (N + 1)] {
+        let mut next_input = [false; 5 + 2 * N];
+        next_input[..(4 + N)].copy_from_slice(&input[1..]);
+        next_input[(4 + N)..].copy_from_slice(&self.value());
+        let next = self.next.eval(next_input);
+        let mut bits_input = [false; 5 * (N + 1)];
+        for (chunk, d) in bits_input.chunks_mut(5).zip(next) {
+            chunk[0] = input[0];
+            chunk[1] = d;
+            chunk[2] = true;
+        }
+        bits_input
+    }
+    fn output(bits: &[bool]) -> [bool; N + 1] {
+        let mut output = [false; N + 1];
+        for (i, v) in output.iter_mut().enumerate() {
+            *v = bits[2 * i];
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+fn shift_input<const N: usize>(clock: bool, mode: ShiftMode, serial_in: bool, value: usize) -> [bool; 5 + N] {
+    use crate::num_bit_converter::*;
+
+    let mode = bit_to_num(mode.bits());
+    num_to_bit(clock as usize + (mode << 1) + ((serial_in as usize) << 4) + (value << 5))
+}
+
+#[test]
+fn shift_register_test() {
+    use crate::num_bit_converter::*;
+
+    // 1クロック進めて値とキャリーを返す
+    fn step(reg: &mut ShiftRegister<8>, mode: ShiftMode, serial_in: bool, value: usize) -> (usize, bool) {
+        reg.eval_mut(shift_input::<8>(false, mode, serial_in, value));
+        let output = reg.eval_mut(shift_input::<8>(true, mode, serial_in, value));
+        (bit_to_num(output) & 0xff, output[8])
+    }
+    use ShiftMode::*;
+    let mut reg = ShiftRegister::<8>::new();
+    assert_eq!(step(&mut reg, Load, true, 0b1001_0110), (0b1001_0110, true));
+    assert_eq!(step(&mut reg, Hold, false, 0), (0b1001_0110, true));
+    assert_eq!(step(&mut reg, ShiftLeft, false, 0), (0b0010_1100, true));
+    assert_eq!(step(&mut reg, ShiftLeft, true, 0), (0b0101_1001, false));
+    assert_eq!(step(&mut reg, ShiftRight, true, 0), (0b1010_1100, true));
+    assert_eq!(step(&mut reg, ShiftRight, false, 0), (0b0101_0110, false));
+    assert_eq!(step(&mut reg, Rotate, false, 0), (0b1010_1100, false));
+    assert_eq!(step(&mut reg, Rotate, false, 0), (0b0101_1001, true));
+    assert_eq!(step(&mut reg, RotateRight, false, 0), (0b1010_1100, true));
+    assert_eq!(step(&mut reg, RotateRight, false, 0), (0b0101_0110, false));
+
+    // キャリーを含めて9回回すと元に戻る
+    let before = step(&mut reg, Load, true, 0b1100_0001);
+    let mut model = 0b1_1100_0001_usize;
+    for _ in 0..9 {
+        model = ((model << 1) | (model >> 8)) & 0x1ff;
+        let (value, carry) = step(&mut reg, RotateThroughCarry, false, 0);
+        assert_eq!(value + ((carry as usize) << 8), model);
+    }
+    assert_eq!(step(&mut reg, Hold, false, 0), before);
+    for _ in 0..9 {
+        model = ((model >> 1) | (model << 8)) & 0x1ff;
+        let (value, carry) = step(&mut reg, RotateRightThroughCarry, false, 0);
+        assert_eq!(value + ((carry as usize) << 8), model);
+    }
+
+    // クロックが1のままなら動かない
+    let output = reg.eval_mut(shift_input::<8>(true, ShiftLeft, false, 0));
+    assert_eq!(bit_to_num(output), 0b1_1100_0001);
+}
+
+#[test]
+fn serial_test() {
+    use crate::clock::Clock;
+    use crate::num_bit_converter::*;
+
+    // 並列に入れて右シフトでbit0から1bitずつ出し, もう1つのレジスタに直列で入れる
+    // 最初の1は立ち上がりとみなさないので, 2回目の立ち上がりでLoadしてその後8回シフトする
+    let mut clock = Clock::new();
+    let mut tx = ShiftRegister::<8>::new();
+    let mut rx = ShiftRegister::<8>::new();
+    let byte = 0b1011_0010;
+    let mut received = 0;
+    for tick in 0..19 {
+        let c = clock.eval_mut([])[0];
+        let mode = if tick <= 2 { ShiftMode::Load } else { ShiftMode::ShiftRight };
+        let serial = tx.value()[0];
+        tx.eval_mut(shift_input::<8>(c, mode, false, byte));
+        received = bit_to_num(rx.eval_mut(shift_input::<8>(c, ShiftMode::ShiftRight, serial, 0))) & 0xff;
+    }
+    assert_eq!(received, byte);
+    // 最後に押し出したbit7がキャリーに残る
+    assert_eq!(bit_to_num(tx.value()), 1 << 8);
+    assert!(crate::hierarchy::find(&rx, "bits[8].master").is_some());
+}
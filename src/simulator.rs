@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+use crate::core::*;
+use crate::clock::Clock;
+use crate::hierarchy::{collect_state, Tracer};
+
+// 1回の半周期が終わったときの様子, モニタや停止条件に渡す
+pub struct Sample<'a, const I: usize, const O: usize> {
+    pub half_cycle: usize,
+    pub input: [bool; I],
+    pub output: [bool; O],
+    pub top: &'a dyn Component<I, O>,
+}
+
+// シミュレーションが止まった理由, half_cycleは止まった半周期
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    Condition { half_cycle: usize },
+    CycleLimit { half_cycle: usize },
+    Monitor { half_cycle: usize, message: String },
+    // settle_limit回評価しても出力と内部状態が落ち着かなかった
+    Unsettled { half_cycle: usize },
+}
+
+type Monitor<const I: usize, const O: usize> = Box<dyn FnMut(&Sample<I, O>) -> Result<(), String>>;
+
+// 一番上の部品とクロック源を持って半周期ずつ進める
+// 半周期ごとに 予定した入力を入れる, クロックを進める, 落ち着くまで評価する, トレーサとモニタを呼ぶ の順
+// クロックは入力のうちの1本につなぐ, Clockは最初に1を出すので0番目の半周期は立ち上がりとみなさない
+pub struct Simulator<const I: usize, const O: usize> {
+    top: Box<dyn Component<I, O>>,
+    // つなぐ入力の位置とクロック源
+    clocks: Vec<(usize, Box<dyn Component<0, 1>>)>,
+    input: [bool; I],
+    output: [bool; O],
+    // 半周期 -> (入力の位置, 値)
+    schedule: BTreeMap<usize, Vec<(usize, Vec<bool>)>>,
+    monitors: Vec<Monitor<I, O>>,
+    tracers: Vec<Tracer>,
+    settle_limit: usize,
+    half_cycle: usize,
+}
+
+impl<const I: usize, const O: usize> Simulator<I, O> {
+    pub fn create(top: Box<dyn Component<I, O>>) -> Self {
+        Self {
+            top,
+            clocks: Vec::new(),
+            input: [false; I],
+            output: [false; O],
+            schedule: BTreeMap::new(),
+            monitors: Vec::new(),
+            tracers: Vec::new(),
+            settle_limit: 8,
+            half_cycle: 0,
+        }
+    }
+    // input番目の入力をsourceで動かす
+    pub fn clock_source(mut self, input: usize, source: Box<dyn Component<0, 1>>) -> Self {
+        assert!(input < I, "clock input {} is out of range", input);
+        self.clocks.push((input, source));
+        self
+    }
+    pub fn clock(self, input: usize) -> Self {
+        self.clock_source(input, Box::new(Clock::new()))
+    }
+    // half_cycle番目の半周期の初めにstart番目からの入力をvalueにする
+    pub fn at(mut self, half_cycle: usize, start: usize, value: &[bool]) -> Self {
+        assert!(start + value.len() <= I, "stimulus at {} is out of range", start);
+        self.schedule.entry(half_cycle).or_default().push((start, value.to_vec()));
+        self
+    }
+    // Errを返すと止まる
+    pub fn monitor(mut self, monitor: impl FnMut(&Sample<I, O>) -> Result<(), String> + 'static) -> Self {
+        self.monitors.push(Box::new(monitor));
+        self
+    }
+    // 半周期ごとにsampleする
+    pub fn trace(mut self, tracer: Tracer) -> Self {
+        self.tracers.push(tracer);
+        self
+    }
+    pub fn settle_limit(mut self, n: usize) -> Self {
+        assert!(n > 0, "cannot settle in 0 evaluations");
+        self.settle_limit = n;
+        self
+    }
+
+    pub fn half_cycle(&self) -> usize {
+        self.half_cycle
+    }
+    pub fn input(&self) -> [bool; I] {
+        self.input
+    }
+    pub fn output(&self) -> [bool; O] {
+        self.output
+    }
+    pub fn top(&self) -> &dyn Component<I, O> {
+        self.top.as_ref()
+    }
+    pub fn tracers(&self) -> &[Tracer] {
+        &self.tracers
+    }
+    // 予定とは別に今すぐ入力を変える, 次の半周期から効く
+    pub fn set_input(&mut self, start: usize, value: &[bool]) {
+        self.input[start..(start + value.len())].copy_from_slice(value);
+    }
+
+    // 半周期を1つ進める, モニタが失敗したか落ち着かなかったときはErr
+    pub fn half_step(&mut self) -> Result<[bool; O], Stop> {
+        let half_cycle = self.half_cycle;
+        self.half_cycle += 1;
+        if let Some(stimuli) = self.schedule.remove(&half_cycle) {
+            for (start, value) in stimuli {
+                self.set_input(start, &value);
+            }
+        }
+        for (input, source) in self.clocks.iter_mut() {
+            self.input[*input] = source.eval_mut([])[0];
+        }
+        self.settle(half_cycle)?;
+
+        for tracer in self.tracers.iter_mut() {
+            tracer.sample(self.top.as_ref());
+        }
+        let sample = Sample {
+            half_cycle,
+            input: self.input,
+            output: self.output,
+            top: self.top.as_ref(),
+        };
+        for monitor in self.monitors.iter_mut() {
+            if let Err(message) = monitor(&sample) {
+                return Err(Stop::Monitor { half_cycle, message });
+            }
+        }
+        Ok(self.output)
+    }
+    // 1周期, 半周期2つ分進める
+    pub fn step(&mut self) -> Result<[bool; O], Stop> {
+        self.half_step()?;
+        self.half_step()
+    }
+    // conditionがtrueを返すかcycles周期進むまで動かす
+    pub fn run_until(&mut self, mut condition: impl FnMut(&Sample<I, O>) -> bool, cycles: usize) -> Stop {
+        for _ in 0..(2 * cycles) {
+            if let Err(stop) = self.half_step() {
+                return stop;
+            }
+            let sample = Sample {
+                half_cycle: self.half_cycle - 1,
+                input: self.input,
+                output: self.output,
+                top: self.top.as_ref(),
+            };
+            if condition(&sample) {
+                return Stop::Condition { half_cycle: sample.half_cycle };
+            }
+        }
+        Stop::CycleLimit { half_cycle: self.half_cycle }
+    }
+    pub fn run(&mut self, cycles: usize) -> Stop {
+        self.run_until(|_| false, cycles)
+    }
+
+    // 同じ入力で評価し直して, 出力と内部状態が変わらなくなるまで繰り返す
+    // 立ち上がりを検出するような部品は最初の評価でだけ反応する
+    fn settle(&mut self, half_cycle: usize) -> Result<(), Stop> {
+        let mut prev = None;
+        for _ in 0..self.settle_limit {
+            self.output = self.top.eval_mut(self.input);
+            let state = collect_state(&self.top);
+            if prev.as_ref() == Some(&(self.output, state.clone())) {
+                return Ok(());
+            }
+            prev = Some((self.output, state));
+        }
+        Err(Stop::Unsettled { half_cycle })
+    }
+}
+
+#[test]
+fn simulator_test() {
+    use crate::counter::Counter;
+    use crate::num_bit_converter::*;
+
+    // 2周期目からenableを入れて, 10になるまで数える
+    let mut sim = Simulator::create(Box::new(Counter::<4>::new()))
+        .clock(0)
+        .at(4, 1, &[true])
+        .monitor(|s| {
+            // terminal countは15のときだけ
+            let value = bit_to_num(s.output) & 0xf;
+            if s.output[4] != (value == 15) {
+                return Err(format!("terminal count at {}", value));
+            }
+            // 出力は各bitのスレーブの値
+            if crate::hierarchy::probe(s.top, "bits[0].slave").unwrap()[0] != s.output[0] {
+                return Err("bit 0 differs from its flip-flop".to_string());
+            }
+            Ok(())
+        })
+        .trace(Tracer::new(&["bits[0].slave"]));
+    let stop = sim.run_until(|s| s.input[0] && bit_to_num(s.output) == 10, 100);
+    // enableはクロックが0の間に取り込まれるので, 数え始めるのは6番目の半周期の立ち上がりから
+    assert_eq!(stop, Stop::Condition { half_cycle: 24 });
+    assert_eq!(sim.half_cycle(), 25);
+
+    let bit0 = sim.tracers()[0].trace(0).into_iter().map(|v| v.unwrap()[0]).collect::<Vec<_>>();
+    assert_eq!(bit0.len(), 25);
+    assert_eq!(bit0[4..10], [false, false, true, true, false, false]);
+
+    // 15を過ぎて折り返すまでモニタは失敗しない
+    assert_eq!(sim.run(20), Stop::CycleLimit { half_cycle: 65 });
+
+    // 止めれば進まない
+    sim.set_input(1, &[false]);
+    assert!(!sim.input()[1]);
+    let before = sim.output();
+    sim.step().unwrap();
+    assert_eq!(sim.output(), before);
+    assert_eq!(crate::hierarchy::probe(sim.top(), "bits[0].slave").unwrap()[0], before[0]);
+}
+
+#[test]
+fn simulator_stop_test() {
+    use crate::num_bit_converter::*;
+    use crate::register_file::RegisterFile;
+
+    // 0番のレジスタに 3, 9, 12 と書いていき, 12を読んだところでモニタが止める
+    let mut sim = Simulator::create(Box::new(RegisterFile::<1, 4>::new()));
+    for (half_cycle, value) in [(0, 3), (2, 9), (4, 12)] {
+        sim = sim.at(half_cycle, 0, &num_to_bit::<8>(1 + (value << 4)))
+            .at(half_cycle + 1, 0, &[false]);
+    }
+    let mut sim = sim.monitor(|s| match bit_to_num(s.output) & 0xf {
+        12 => Err("wrote 12".to_string()),
+        _ => Ok(()),
+    });
+    assert_eq!(sim.run(10), Stop::Monitor { half_cycle: 4, message: "wrote 12".to_string() });
+    assert_eq!(bit_to_num(sim.output()) & 0xf, 12);
+
+    // 評価のたびに反転するものは落ち着かない
+    let mut sim = Simulator::create(Box::new(Clock::new())).settle_limit(4);
+    assert_eq!(sim.run(1), Stop::Unsettled { half_cycle: 0 });
+}
@@ -0,0 +1,185 @@
+use crate::core::*;
+use crate::netlist::*;
+use crate::optimize::remove_dead;
+
+// 74HC00や74HC02のように2入力のNANDかNORだけで組む
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Library {
+    Nand2,
+    Nor2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MappingReport {
+    pub before: GateCount,
+    pub after: GateCount,
+}
+
+struct Mapper {
+    library: Library,
+    netlist: Netlist,
+}
+
+impl Mapper {
+    fn base(&mut self, a: Net, b: Net) -> Net {
+        let gate = match self.library {
+            Library::Nand2 => Gate::Nand(vec![a, b]),
+            Library::Nor2 => Gate::Nor(vec![a, b]),
+        };
+        self.netlist.add(gate)
+    }
+    // 入力を二本ともつないだものがNot, 二重のNotは打ち消す
+    fn not(&mut self, a: Net) -> Net {
+        match &self.netlist.gates()[a] {
+            Gate::Nand(ins) | Gate::Nor(ins) if ins.len() == 2 && ins[0] == ins[1] => ins[0],
+            _ => self.base(a, a),
+        }
+    }
+    fn and2(&mut self, a: Net, b: Net) -> Net {
+        match self.library {
+            Library::Nand2 => {
+                let n = self.base(a, b);
+                self.not(n)
+            }
+            Library::Nor2 => {
+                let (a, b) = (self.not(a), self.not(b));
+                self.base(a, b)
+            }
+        }
+    }
+    fn or2(&mut self, a: Net, b: Net) -> Net {
+        match self.library {
+            Library::Nand2 => {
+                let (a, b) = (self.not(a), self.not(b));
+                self.base(a, b)
+            }
+            Library::Nor2 => {
+                let n = self.base(a, b);
+                self.not(n)
+            }
+        }
+    }
+    // 多入力は2入力の木にして段数をlog2(入力数)に抑える
+    fn tree(&mut self, ins: &[Net], identity: bool, or: bool) -> Net {
+        if ins.is_empty() {
+            return self.netlist.add(Gate::Const(identity));
+        }
+        let mut level = ins.to_vec();
+        while level.len() > 1 {
+            level = level.chunks(2)
+                .map(|pair| match pair {
+                    [a, b] if or => self.or2(*a, *b),
+                    [a, b] => self.and2(*a, *b),
+                    [a] => *a,
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+        level[0]
+    }
+    fn map(&mut self, gate: &Gate, ins: &[Net]) -> Net {
+        match gate {
+            Gate::Input(i) => *i,
+            Gate::Const(v) => self.netlist.add(Gate::Const(*v)),
+            Gate::Not(_) => self.not(ins[0]),
+            Gate::And(_) => self.tree(ins, true, false),
+            Gate::Or(_) => self.tree(ins, false, true),
+            Gate::Nand(_) => {
+                let and = self.tree(ins, true, false);
+                self.not(and)
+            }
+            Gate::Nor(_) => {
+                let or = self.tree(ins, false, true);
+                self.not(or)
+            }
+        }
+    }
+}
+
+pub fn map_netlist(netlist: &Netlist, library: Library) -> (Netlist, MappingReport) {
+    let mut mapper = Mapper {
+        library,
+        netlist: Netlist::new(netlist.inputs()),
+    };
+    let mut map = Vec::with_capacity(netlist.gates().len());
+    for gate in netlist.gates() {
+        let ins = gate.inputs().iter().map(|&n| map[n]).collect::<Vec<Net>>();
+        let net = mapper.map(gate, &ins);
+        map.push(net);
+    }
+    mapper.netlist.set_outputs(netlist.outputs().iter().map(|&n| map[n]).collect());
+
+    // 打ち消したNotが残っているので掃除する
+    let mapped = remove_dead(&mapper.netlist);
+    let report = MappingReport {
+        before: netlist.count_gates(),
+        after: mapped.count_gates(),
+    };
+    (mapped, report)
+}
+
+pub fn map_to_library<const I: usize, const O: usize>(
+    component: &dyn Component<I, O>,
+    library: Library,
+) -> Option<(FlatCircuit<I, O>, MappingReport)> {
+    let (netlist, report) = map_netlist(&flatten(component)?, library);
+    Some((FlatCircuit::create(netlist)?, report))
+}
+
+#[test]
+fn nand_mapping_test() {
+    use crate::arithmetic_comp::FullAdder;
+    use crate::num_bit_converter::*;
+
+    let adder = FullAdder::new();
+    let (mapped, report) = map_to_library(&adder, Library::Nand2).unwrap();
+    assert_eq!(report.after.total(), report.after.nand);
+    assert!(mapped.netlist().gates().iter().all(|g| match g {
+        Gate::Nand(ins) => ins.len() == 2,
+        g => !g.is_logic(),
+    }));
+    for i in 0..8 {
+        let input = num_to_bit::<3>(i);
+        assert_eq!(mapped.eval(input), adder.eval(input));
+    }
+}
+
+#[test]
+fn nor_mapping_test() {
+    use crate::basic_comp::*;
+    use crate::num_bit_converter::*;
+
+    let and = And::<5>::new();
+    let (mapped, report) = map_to_library(&and, Library::Nor2).unwrap();
+    assert_eq!(report.before.and, 1);
+    assert_eq!(report.after.total(), report.after.nor);
+    for i in 0..32 {
+        let input = num_to_bit::<5>(i);
+        assert_eq!(mapped.eval(input), and.eval(input));
+    }
+
+    let xor = XOR::<3>::new();
+    let (mapped, _) = map_to_library(&xor, Library::Nor2).unwrap();
+    for i in 0..8 {
+        let input = num_to_bit::<3>(i);
+        assert_eq!(mapped.eval(input), xor.eval(input));
+    }
+}
+
+#[test]
+fn eight_bit_adder_mapping_test() {
+    use crate::arithmetic_comp::EightBitFullAdder;
+    use crate::num_bit_converter::*;
+
+    let adder = EightBitFullAdder::new();
+    let (mapped, report) = map_to_library(&adder, Library::Nand2).unwrap();
+    assert_eq!(report.before, flatten(&adder).unwrap().count_gates());
+    assert_eq!(report.after.total(), report.after.nand);
+    assert_eq!(report.after.total(), mapped.netlist().gate_count());
+    // 2入力のNANDだけにするとゲートは増える
+    assert!(report.after.total() > report.before.total(), "{:?} -> {:?}", report.before, report.after);
+    for i in 0..(1 << 17) {
+        let input = num_to_bit::<17>(i);
+        assert_eq!(mapped.eval(input), adder.eval(input));
+    }
+}